@@ -0,0 +1,93 @@
+use bvh_anim::{Bvh, Channel, Float, Motion, MotionLayout};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const MOCAPBANK: &[u8] = include_bytes!("../data/test_mocapbank.bvh");
+
+/// How many times the frames of the sample file are repeated, to get a clip long
+/// enough that it doesn't fit in the cache.
+const REPEATS: usize = 100;
+
+fn long_clip(layout: MotionLayout) -> Bvh {
+    let bvh = bvh_anim::from_bytes(MOCAPBANK).unwrap();
+    let mut motion = Motion::new(bvh.num_channels(), bvh.frame_rate());
+    for _ in 0..REPEATS {
+        for frame in bvh.frames() {
+            motion.push_frame(frame.as_slice());
+        }
+    }
+    motion.set_layout(layout);
+
+    let (skeleton, _) = bvh.into_parts();
+    Bvh::from_parts(skeleton, motion).unwrap()
+}
+
+fn channels(bvh: &Bvh) -> Vec<Channel> {
+    bvh.joints()
+        .flat_map(|joint| joint.channels.iter().copied())
+        .collect()
+}
+
+fn curves(c: &mut Criterion) {
+    let mut group = c.benchmark_group("curves");
+
+    for &(name, layout) in &[
+        ("frame_major", MotionLayout::FrameMajor),
+        ("channel_major", MotionLayout::ChannelMajor),
+    ] {
+        let mut bvh = long_clip(layout);
+        let channels = channels(&bvh);
+        group.throughput(Throughput::Elements(
+            (bvh.num_frames() * bvh.num_channels()) as u64,
+        ));
+
+        // The mean and variance of every channel.
+        group.bench_function(BenchmarkId::new("statistics", name), |b| {
+            b.iter(|| {
+                channels
+                    .iter()
+                    .map(|channel| {
+                        let curve = bvh.curve(channel);
+                        let n = curve.len() as Float;
+                        let mean = curve.iter().sum::<Float>() / n;
+                        let variance = curve
+                            .iter()
+                            .map(|&v| (v - mean) * (v - mean))
+                            .sum::<Float>()
+                            / n;
+                        (mean, variance)
+                    })
+                    .collect::<Vec<_>>()
+            })
+        });
+
+        // A three tap moving average over every channel, in place.
+        group.bench_function(BenchmarkId::new("smooth", name), |b| {
+            b.iter(|| {
+                for channel in &channels {
+                    let mut curve = bvh.curve_mut(channel);
+                    let mut prev = curve[0];
+                    for i in 1..curve.len() - 1 {
+                        let value = curve[i];
+                        curve[i] = (prev + value + curve[i + 1]) / 3.0;
+                        prev = value;
+                    }
+                }
+            })
+        });
+
+        // Copying every channel out, as a keyframe reducer would.
+        group.bench_function(BenchmarkId::new("to_vec", name), |b| {
+            b.iter(|| {
+                channels
+                    .iter()
+                    .map(|channel| bvh.curve(channel).to_vec())
+                    .collect::<Vec<_>>()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, curves);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+//! Benchmarks for loading a whole file.
+//!
+//! Measured with `cargo bench --bench parse` on `data/test_mocapbank.bvh`
+//! (median time, criterion 0.3):
+//!
+//! | Parser                                             | `from_bytes`         | `from_reader`        |
+//! |----------------------------------------------------|----------------------|----------------------|
+//! | Line-splitting parser (`byte_lines`/`fields_with`) | 2.63 ms (62.4 MiB/s) | 2.67 ms (61.7 MiB/s) |
+//! | Byte tokenizer                                     | 1.66 ms (99.1 MiB/s) | 1.73 ms (94.9 MiB/s) |
+//!
+//! `from_reader` reads the whole input into a buffer before tokenizing it, so it
+//! makes one more copy of the data than `from_bytes`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::io::BufReader;
+
+const MOCAPBANK: &[u8] = include_bytes!("../data/test_mocapbank.bvh");
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("test_mocapbank");
+    group.throughput(Throughput::Bytes(MOCAPBANK.len() as u64));
+
+    group.bench_function("from_bytes", |b| {
+        b.iter(|| bvh_anim::from_bytes(MOCAPBANK).unwrap())
+    });
+    group.bench_function("from_reader", |b| {
+        b.iter(|| bvh_anim::from_reader(BufReader::new(MOCAPBANK)).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
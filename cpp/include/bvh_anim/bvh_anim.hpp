@@ -9,6 +9,7 @@
 #include <iterator>
 #include <chrono>
 #include <iosfwd>
+#include <vector>
 
 namespace bvh_anim {
 
@@ -65,8 +66,14 @@ public:
 
 private:
     friend class bvh;
-    constexpr joint(bvh_Joint& joint): m_joint(joint) {}
+    friend class joint_iterator;
+    joint(const bvh_BvhFile& bvh, size_t index):
+        m_bvh(bvh),
+        m_index(index),
+        m_joint(bvh.bvh_joints[index]) {}
 
+    const bvh_BvhFile& m_bvh;
+    size_t m_index = 0;
     const bvh_Joint m_joint;
 };
 
@@ -151,8 +158,23 @@ public:
     }
 
     joint operator*() const;
+
 private:
     friend class joint;
+
+    joint_iterator() = delete;
+    joint_iterator(
+        const bvh_BvhFile& bvh,
+        std::vector<size_t> children
+    ):
+        m_bvh(&bvh),
+        m_children(std::move(children))
+    {
+    }
+
+    const bvh_BvhFile* m_bvh = nullptr;
+    std::vector<size_t> m_children;
+    size_t m_child_index = 0;
 };
 
 class bvh final {
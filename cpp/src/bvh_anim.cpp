@@ -59,6 +59,13 @@ channel_iterator joint::channels() const {
     return channel_iterator(m_joint.joint_channels, m_joint.joint_num_channels);
 }
 
+joint_iterator joint::children() const {
+    const auto num_children = bvh_get_joint_children(&m_bvh, m_index, nullptr, 0);
+    std::vector<size_t> children(num_children);
+    bvh_get_joint_children(&m_bvh, m_index, children.data(), children.size());
+    return joint_iterator(m_bvh, std::move(children));
+}
+
 channel_iterator& channel_iterator::operator++() {
     if (m_channel_index <= m_num_channels) {
         m_channel_index++;
@@ -79,3 +86,27 @@ channel channel_iterator::operator*() const {
     const auto& chnl = m_channels[m_channel_index];
     return channel(chnl);
 }
+
+joint_iterator& joint_iterator::operator++() {
+    if (m_child_index < m_children.size()) {
+        m_child_index++;
+    }
+
+    return *this;
+}
+
+joint_iterator joint_iterator::operator++(int) {
+    auto prev = *this;
+    ++*this;
+    return prev;
+}
+
+bool joint_iterator::operator==(const joint_iterator& i) const {
+    return m_bvh == i.m_bvh
+        && m_children == i.m_children
+        && m_child_index == i.m_child_index;
+}
+
+joint joint_iterator::operator*() const {
+    return joint(*m_bvh, m_children[m_child_index]);
+}
@@ -2,11 +2,11 @@
 
 //! Defines a `Builder` struct used to build a `Bvh` dynamically.
 
-use crate::{joint::JointName, Bvh, Channel, ChannelType};
+use crate::{joint::JointName, Bvh, Channel, ChannelType, Float, Skeleton};
 use bstr::{BStr, ByteSlice};
 use mint::Vector3;
 use smallvec::SmallVec;
-use std::{fmt, time::Duration};
+use std::{fmt, sync::Arc, time::Duration};
 
 use crate::joint::Joint;
 
@@ -40,7 +40,7 @@ impl Builder {
     /// Start to create a new `Bvh` with a root joint.
     pub fn with_root_joint(
         name: &BStr,
-        offset: Vector3<f32>,
+        offset: Vector3<Float>,
         channels: &[ChannelType],
     ) -> JointsBuilder {
         let mut num_channels = 0;
@@ -69,7 +69,7 @@ impl fmt::Debug for JointsBuilder {
 fn new_joint(
     parent_index: Option<usize>,
     name: &BStr,
-    offset: Vector3<f32>,
+    offset: Vector3<Float>,
     channels: SmallVec<[Channel; 6]>,
     depth: usize,
 ) -> Joint {
@@ -89,7 +89,7 @@ impl JointsBuilder {
         mut self,
         depth: usize,
         name: &BStr,
-        offset: Vector3<f32>,
+        offset: Vector3<Float>,
         channels: &[ChannelType],
     ) -> Self {
         let parent = self.joints.len() - 1;
@@ -97,6 +97,10 @@ impl JointsBuilder {
     }
 
     /// Push a `Joint` with `parent_idx` being the index of the parent,
+    ///
+    /// If the parent was capped with an `End Site`, the end site is removed, as
+    /// only joints without children may have one.
+    ///
     /// # Panics
     ///
     /// Panics if `parent_idx` is out of bounds.
@@ -105,19 +109,20 @@ impl JointsBuilder {
         mut self,
         depth: usize,
         name: &BStr,
-        offset: Vector3<f32>,
+        offset: Vector3<Float>,
         channels: &[ChannelType],
         parent_idx: usize,
     ) -> Self {
         let channels = collect_channels(channels, &mut self.num_channels);
         assert!(parent_idx < self.joints.len());
+        self.joints[parent_idx].end_site = None;
         self.joints
             .push(new_joint(Some(parent_idx), name, offset, channels, depth));
         self
     }
 
-    /// Cap the last pushed `Joint` with an `End Site`.
-    pub fn push_end(mut self, offset: Vector3<f32>) -> Self {
+    /// Cap the last pushed `Joint` with an `End Site` at `offset` from the joint.
+    pub fn push_end(mut self, offset: Vector3<Float>) -> Self {
         self.joints.last_mut().unwrap().end_site = Some(offset);
         self
     }
@@ -140,7 +145,7 @@ pub struct MotionBuilder {
     joints_builder: JointsBuilder,
     frame_time: Duration,
     num_frames: usize,
-    motion_values: Vec<f32>,
+    motion_values: Vec<Float>,
 }
 
 impl fmt::Debug for MotionBuilder {
@@ -151,7 +156,7 @@ impl fmt::Debug for MotionBuilder {
 
 impl MotionBuilder {
     /// Push a frame of motion values.
-    pub fn push_frame(mut self, frame: &[f32]) -> Self {
+    pub fn push_frame(mut self, frame: &[Float]) -> Self {
         assert_eq!(frame.len(), self.joints_builder.num_channels);
         self.motion_values.extend(frame);
         self
@@ -161,11 +166,15 @@ impl MotionBuilder {
     pub fn build(self) -> Result<Bvh, ()> {
         let mut bvh = Bvh::default();
 
-        bvh.joints = self.joints_builder.joints;
+        let num_channels = self.joints_builder.num_channels;
+        bvh.skeleton = Arc::new(Skeleton::from_joints(
+            self.joints_builder.joints,
+            num_channels,
+        ));
         bvh.set_frame_time(self.frame_time);
-        bvh.num_frames = self.num_frames;
-        bvh.num_channels = self.joints_builder.num_channels;
-        bvh.motion_values = self.motion_values;
+        bvh.motion.num_frames = self.num_frames;
+        bvh.motion.num_channels = num_channels;
+        bvh.motion.values = self.motion_values;
 
         Ok(bvh)
     }
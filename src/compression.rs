@@ -0,0 +1,152 @@
+//! Contains the compression formats which can be used to load and write
+//! `bvh` files.
+//!
+//! Motion capture data compresses very well, so archives often store files as
+//! `.bvh.gz` or `.bvh.zst`. The format of a file is detected from its magic
+//! bytes when it is loaded with [`Bvh::from_path`][`Bvh::from_path`] or
+//! [`Bvh::from_compressed_reader`][`Bvh::from_compressed_reader`], and
+//! [`WriteOptions::compression`][`WriteOptions::compression`] selects the format
+//! to write.
+//!
+//! Support for each format is enabled by a cargo feature: `gzip` and `zstd`
+//! respectively. Loading or writing a format which has not been enabled returns
+//! an error.
+//!
+//! [`Bvh::from_path`]: ../struct.Bvh.html#method.from_path
+//! [`Bvh::from_compressed_reader`]: ../struct.Bvh.html#method.from_compressed_reader
+//! [`WriteOptions::compression`]: ../write/struct.WriteOptions.html#structfield.compression
+
+use std::{
+    fmt,
+    io::{self, BufRead, Write},
+};
+
+const GZIP_MAGIC: &[u8] = b"\x1F\x8B";
+const ZSTD_MAGIC: &[u8] = b"\x28\xB5\x2F\xFD";
+
+/// A compression format for `bvh` files.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Compression {
+    /// The file is not compressed.
+    None,
+    /// The file is compressed with gzip. Requires the `gzip` feature.
+    Gzip,
+    /// The file is compressed with Zstandard. Requires the `zstd` feature.
+    Zstd,
+}
+
+impl Compression {
+    /// Detect the compression format of `bytes` from its magic bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::compression::Compression;
+    /// assert_eq!(Compression::detect(b"\x1F\x8B\x08\x00"), Compression::Gzip);
+    /// assert_eq!(Compression::detect(b"\x28\xB5\x2F\xFD"), Compression::Zstd);
+    /// assert_eq!(Compression::detect(b"HIERARCHY"), Compression::None);
+    /// ```
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Returns `true` if support for the compression format was enabled when
+    /// the crate was built.
+    #[inline]
+    pub fn is_supported(self) -> bool {
+        match self {
+            Compression::None => true,
+            Compression::Gzip => cfg!(feature = "gzip"),
+            Compression::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+
+    /// Get the name of the compression format.
+    #[inline]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    fn unsupported_error(self) -> io::Error {
+        io::Error::other(format!(
+            "{} compression is not supported; enable the `{}` feature of `bvh_anim`",
+            self.as_str(),
+            self.as_str()
+        ))
+    }
+}
+
+impl Default for Compression {
+    #[inline]
+    fn default() -> Self {
+        Compression::None
+    }
+}
+
+impl fmt::Display for Compression {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Read all of the data from `reader` into `buf`, decompressing it if it starts
+/// with the magic bytes of a compression format.
+///
+/// Returns the compression format of the data.
+pub(crate) fn read_to_end(reader: &mut dyn BufRead, buf: &mut Vec<u8>) -> io::Result<Compression> {
+    let compression = Compression::detect(reader.fill_buf()?);
+    match compression {
+        Compression::None => {
+            reader.read_to_end(buf)?;
+        }
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            io::copy(&mut flate2::bufread::MultiGzDecoder::new(reader), buf)?;
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            io::copy(&mut zstd::stream::read::Decoder::with_buffer(reader)?, buf)?;
+        }
+        #[allow(unreachable_patterns)]
+        _ => return Err(compression.unsupported_error()),
+    }
+
+    Ok(compression)
+}
+
+/// Call `write` with a writer which compresses everything written to it into
+/// `writer`, and finish the compressed stream.
+pub(crate) fn write_compressed(
+    compression: Compression,
+    writer: &mut dyn Write,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    match compression {
+        Compression::None => write(writer),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+            write(&mut encoder)?;
+            encoder.finish()?.flush()
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            let mut encoder = zstd::stream::write::Encoder::new(writer, 0)?;
+            write(&mut encoder)?;
+            encoder.finish()?.flush()
+        }
+        #[allow(unreachable_patterns)]
+        _ => Err(compression.unsupported_error()),
+    }
+}
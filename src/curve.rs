@@ -0,0 +1,344 @@
+//! Views of the motion values of a single channel across every frame.
+
+use crate::Float;
+use std::{
+    fmt,
+    iter::{FusedIterator, StepBy},
+    ops::{Index, IndexMut, Range},
+    slice::{Iter, IterMut},
+};
+
+/// The motion values of a single `Channel`, one for each frame.
+///
+/// The values of a channel are `num_channels` apart in a frame-major `Motion`,
+/// and next to each other in a channel-major one, so only the latter can be
+/// borrowed as a slice with [`Curve::as_slice`][`Curve::as_slice`].
+///
+/// # Examples
+///
+/// ```
+/// # use bvh_anim::{bvh, Float, JointIndex};
+/// let bvh = bvh! {
+///     HIERARCHY
+///     ROOT Hips
+///     {
+///         OFFSET 0.0 0.0 0.0
+///         CHANNELS 3 Xposition Yposition Zposition
+///         End Site
+///         {
+///             OFFSET 0.0 10.0 0.0
+///         }
+///     }
+///     MOTION
+///     Frames: 3
+///     Frame Time: 0.0333333
+///     1.0 2.0 3.0
+///     4.0 5.0 6.0
+///     7.0 8.0 9.0
+/// };
+///
+/// let y = bvh.curve(&bvh[JointIndex::new(0)].channels[1]);
+/// assert_eq!(y.len(), 3);
+/// assert_eq!(y[1], 5.0);
+/// assert_eq!(y.to_vec(), vec![2.0, 5.0, 8.0]);
+/// assert_eq!(y.iter().sum::<Float>(), 15.0);
+/// ```
+///
+/// [`Curve::as_slice`]: #method.as_slice
+#[derive(Clone, Copy)]
+pub struct Curve<'a> {
+    /// The values from the first value of the curve to its last.
+    values: &'a [Float],
+    /// The distance between consecutive values of the curve.
+    stride: usize,
+}
+
+impl<'a> Curve<'a> {
+    /// Create a `Curve` of the `len` values `stride` apart in `values`, starting
+    /// at `start`.
+    #[inline]
+    pub(crate) fn new(values: &'a [Float], start: usize, stride: usize, len: usize) -> Self {
+        Curve {
+            values: &values[span(start, stride, len)],
+            stride,
+        }
+    }
+
+    /// Returns the number of values in the `Curve`, which is the number of frames.
+    #[inline]
+    pub fn len(&self) -> usize {
+        curve_len(self.values.len(), self.stride)
+    }
+
+    /// Returns `true` if the `Curve` has no values.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the value at `frame`, or `None` if it is out of bounds.
+    #[inline]
+    pub fn get(&self, frame: usize) -> Option<&'a Float> {
+        self.values.get(frame.checked_mul(self.stride)?)
+    }
+
+    /// Returns an iterator over the values of the `Curve`, in frame order.
+    #[inline]
+    pub fn iter(&self) -> CurveIter<'a> {
+        CurveIter(self.values.iter().step_by(self.stride))
+    }
+
+    /// Copy the values of the `Curve` into a `Vec`.
+    #[inline]
+    pub fn to_vec(&self) -> Vec<Float> {
+        match self.as_slice() {
+            Some(values) => values.to_vec(),
+            None => self.iter().copied().collect(),
+        }
+    }
+
+    /// Returns the values of the `Curve` as a slice if they are stored next to
+    /// each other, which is always the case in a channel-major `Motion`.
+    #[inline]
+    pub fn as_slice(&self) -> Option<&'a [Float]> {
+        if self.stride == 1 || self.values.len() <= 1 {
+            Some(self.values)
+        } else {
+            None
+        }
+    }
+}
+
+impl Index<usize> for Curve<'_> {
+    type Output = Float;
+    #[inline]
+    fn index(&self, frame: usize) -> &Self::Output {
+        self.get(frame).expect("frame out of bounds of the curve")
+    }
+}
+
+impl<'a> IntoIterator for Curve<'a> {
+    type Item = &'a Float;
+    type IntoIter = CurveIter<'a>;
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &Curve<'a> {
+    type Item = &'a Float;
+    type IntoIter = CurveIter<'a>;
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl fmt::Debug for Curve<'_> {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmtr.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// The motion values of a single `Channel`, one for each frame, which can be
+/// changed in place.
+///
+/// See [`Curve`][`Curve`] for the read-only version.
+///
+/// [`Curve`]: struct.Curve.html
+pub struct CurveMut<'a> {
+    /// The values from the first value of the curve to its last.
+    values: &'a mut [Float],
+    /// The distance between consecutive values of the curve.
+    stride: usize,
+}
+
+impl<'a> CurveMut<'a> {
+    /// Create a `CurveMut` of the `len` values `stride` apart in `values`,
+    /// starting at `start`.
+    #[inline]
+    pub(crate) fn new(values: &'a mut [Float], start: usize, stride: usize, len: usize) -> Self {
+        CurveMut {
+            values: &mut values[span(start, stride, len)],
+            stride,
+        }
+    }
+
+    /// Returns a read-only view of the `CurveMut`.
+    #[inline]
+    pub fn as_curve(&self) -> Curve<'_> {
+        Curve {
+            values: self.values,
+            stride: self.stride,
+        }
+    }
+
+    /// Returns the number of values in the `CurveMut`, which is the number of
+    /// frames.
+    #[inline]
+    pub fn len(&self) -> usize {
+        curve_len(self.values.len(), self.stride)
+    }
+
+    /// Returns `true` if the `CurveMut` has no values.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the value at `frame`, or `None` if it is out of bounds.
+    #[inline]
+    pub fn get(&self, frame: usize) -> Option<&Float> {
+        self.values.get(frame.checked_mul(self.stride)?)
+    }
+
+    /// Returns the value at `frame` mutably, or `None` if it is out of bounds.
+    #[inline]
+    pub fn get_mut(&mut self, frame: usize) -> Option<&mut Float> {
+        self.values.get_mut(frame.checked_mul(self.stride)?)
+    }
+
+    /// Returns an iterator over the values of the `CurveMut`, in frame order.
+    #[inline]
+    pub fn iter(&self) -> CurveIter<'_> {
+        self.as_curve().iter()
+    }
+
+    /// Returns a mutable iterator over the values of the `CurveMut`, in frame
+    /// order.
+    #[inline]
+    pub fn iter_mut(&mut self) -> CurveIterMut<'_> {
+        CurveIterMut(self.values.iter_mut().step_by(self.stride))
+    }
+
+    /// Copy the values of the `CurveMut` into a `Vec`.
+    #[inline]
+    pub fn to_vec(&self) -> Vec<Float> {
+        self.as_curve().to_vec()
+    }
+
+    /// Returns the values of the `CurveMut` as a mutable slice if they are stored
+    /// next to each other, which is always the case in a channel-major `Motion`.
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> Option<&mut [Float]> {
+        if self.stride == 1 || self.values.len() <= 1 {
+            Some(self.values)
+        } else {
+            None
+        }
+    }
+}
+
+impl Index<usize> for CurveMut<'_> {
+    type Output = Float;
+    #[inline]
+    fn index(&self, frame: usize) -> &Self::Output {
+        self.get(frame).expect("frame out of bounds of the curve")
+    }
+}
+
+impl IndexMut<usize> for CurveMut<'_> {
+    #[inline]
+    fn index_mut(&mut self, frame: usize) -> &mut Self::Output {
+        self.get_mut(frame)
+            .expect("frame out of bounds of the curve")
+    }
+}
+
+impl<'a> IntoIterator for CurveMut<'a> {
+    type Item = &'a mut Float;
+    type IntoIter = CurveIterMut<'a>;
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        CurveIterMut(self.values.iter_mut().step_by(self.stride))
+    }
+}
+
+impl fmt::Debug for CurveMut<'_> {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_curve(), fmtr)
+    }
+}
+
+/// An iterator over the values of a [`Curve`][`Curve`].
+///
+/// [`Curve`]: struct.Curve.html
+#[derive(Clone, Debug)]
+pub struct CurveIter<'a>(StepBy<Iter<'a, Float>>);
+
+impl<'a> Iterator for CurveIter<'a> {
+    type Item = &'a Float;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for CurveIter<'_> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back()
+    }
+}
+
+impl ExactSizeIterator for CurveIter<'_> {}
+
+impl FusedIterator for CurveIter<'_> {}
+
+/// A mutable iterator over the values of a [`CurveMut`][`CurveMut`].
+///
+/// [`CurveMut`]: struct.CurveMut.html
+#[derive(Debug)]
+pub struct CurveIterMut<'a>(StepBy<IterMut<'a, Float>>);
+
+impl<'a> Iterator for CurveIterMut<'a> {
+    type Item = &'a mut Float;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for CurveIterMut<'_> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back()
+    }
+}
+
+impl ExactSizeIterator for CurveIterMut<'_> {}
+
+impl FusedIterator for CurveIterMut<'_> {}
+
+/// The range of values from the first value of a curve to its last.
+#[inline]
+fn span(start: usize, stride: usize, len: usize) -> Range<usize> {
+    match len {
+        0 => 0..0,
+        len => start..start + (len - 1) * stride + 1,
+    }
+}
+
+/// The number of values in a curve whose values span `span` values.
+#[inline]
+fn curve_len(span: usize, stride: usize) -> usize {
+    match span {
+        0 => 0,
+        span => (span - 1) / stride + 1,
+    }
+}
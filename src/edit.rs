@@ -0,0 +1,714 @@
+//! Structural editing of the joint hierarchy of a `Bvh`.
+//!
+//! Each edit rebuilds the depths of the joints, the motion indices of their
+//! channels and the motion values of every frame, so that they stay consistent
+//! with the new hierarchy.
+
+use crate::{
+    errors::EditJointsError, Axis, Bvh, Channel, ChannelType, Float, Joint, JointIndex, JointName,
+    MotionLayout, Skeleton,
+};
+use mint::Vector3;
+use smallvec::SmallVec;
+use std::{cmp::Ordering, sync::Arc};
+
+/// The motion index of a channel which was added by an edit, and so has no
+/// motion values to copy.
+const NEW_CHANNEL: usize = usize::MAX;
+
+impl Bvh {
+    /// Insert a new `Joint` called `name` as the last child of `parent`.
+    ///
+    /// Each channel of the new joint has a value of `0.0` in every frame, and the
+    /// joint is capped with an `End Site` at its origin. If `parent` was capped
+    /// with an `End Site`, the end site is removed.
+    ///
+    /// Returns the index of the new joint. The indices of any joints after it
+    /// are shifted up by one. Returns an error, and leaves the `Bvh` unchanged, if
+    /// `channels` has more than one channel of the same type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::{bvh, ChannelType, JointIndex};
+    /// let mut bvh = bvh! {
+    ///     HIERARCHY
+    ///     ROOT Hips
+    ///     {
+    ///         OFFSET 0.0 0.0 0.0
+    ///         CHANNELS 3 Xposition Yposition Zposition
+    ///         End Site
+    ///         {
+    ///             OFFSET 0.0 10.0 0.0
+    ///         }
+    ///     }
+    ///     MOTION
+    ///     Frames: 2
+    ///     Frame Time: 0.033333333
+    ///     1.0 2.0 3.0
+    ///     4.0 5.0 6.0
+    /// };
+    ///
+    /// let hips = JointIndex::new(0);
+    /// let chest = bvh.insert_joint(
+    ///     hips,
+    ///     "Chest",
+    ///     [0.0, 10.0, 0.0].into(),
+    ///     &[ChannelType::RotationZ, ChannelType::RotationX, ChannelType::RotationY],
+    /// )?;
+    ///
+    /// assert_eq!(bvh[chest].parent(), Some(hips));
+    /// assert_eq!(bvh.num_channels(), 6);
+    /// assert_eq!(bvh.frames().next().unwrap().as_slice(), &[1.0, 2.0, 3.0, 0.0, 0.0, 0.0]);
+    /// # Result::<(), bvh_anim::errors::EditJointsError>::Ok(())
+    /// ```
+    pub fn insert_joint<N: AsRef<[u8]>>(
+        &mut self,
+        parent: JointIndex,
+        name: N,
+        offset: Vector3<Float>,
+        channels: &[ChannelType],
+    ) -> Result<JointIndex, EditJointsError> {
+        self.check_joint_index(parent)?;
+
+        let index = self.subtree_end(parent);
+        if let Some(channel_type) = first_duplicate(channels.iter().copied()) {
+            return Err(EditJointsError::DuplicateChannel {
+                joint: JointIndex::new(index),
+                channel_type,
+            });
+        }
+
+        let mut joints = self.skeleton.joints.clone();
+        remap_parents(&mut joints, |p| Some(if p >= index { p + 1 } else { p }));
+        joints.insert(
+            index,
+            Joint {
+                name: JointName::from(name.as_ref()),
+                offset,
+                channels: channels
+                    .iter()
+                    .map(|&channel_type| Channel::new(channel_type, NEW_CHANNEL))
+                    .collect(),
+                end_site: Some(zero_vector()),
+                parent_index: Some(parent.get()),
+                depth: 0,
+            },
+        );
+
+        self.relayout(joints);
+        Ok(JointIndex::new(index))
+    }
+
+    /// Remove the `Joint` at `index`, along with the motion values of its channels.
+    ///
+    /// The children of the joint become children of its parent, and the offset of
+    /// the joint is added to their offsets so that the rest pose of the skeleton is
+    /// unchanged. If the joint was a root, its children become roots. If the joint
+    /// was the only child of its parent, the parent is capped with an `End Site` at
+    /// the end of the removed joint.
+    ///
+    /// The indices of the joints after the removed joint are shifted down by one.
+    ///
+    /// The animation of the children is only unchanged if the joint did not move
+    /// or rotate. See
+    /// [`Bvh::remove_joint_merging_rotation`][`Bvh::remove_joint_merging_rotation`]
+    /// to keep the rotation of the joint.
+    ///
+    /// [`Bvh::remove_joint_merging_rotation`]: #method.remove_joint_merging_rotation
+    #[inline]
+    pub fn remove_joint(&mut self, index: JointIndex) -> Result<(), EditJointsError> {
+        self.remove_joint_(index, false)
+    }
+
+    /// Remove the `Joint` at `index` as with [`Bvh::remove_joint`][`Bvh::remove_joint`],
+    /// but first combine its rotation with the rotation of each of its children in
+    /// every frame.
+    ///
+    /// Each child must have exactly one rotation channel about each axis, in any
+    /// order, or an error is returned and the `Bvh` is unchanged. Position channels
+    /// are not merged, and the offsets of the children are not rotated, so the
+    /// children may still move if the removed joint was offset from them.
+    ///
+    /// [`Bvh::remove_joint`]: #method.remove_joint
+    #[inline]
+    pub fn remove_joint_merging_rotation(
+        &mut self,
+        index: JointIndex,
+    ) -> Result<(), EditJointsError> {
+        self.remove_joint_(index, true)
+    }
+
+    fn remove_joint_(
+        &mut self,
+        index: JointIndex,
+        merge_rotation: bool,
+    ) -> Result<(), EditJointsError> {
+        self.check_joint_index(index)?;
+
+        let children: Vec<_> = self.children(index).collect();
+        if merge_rotation {
+            self.merge_rotation(index, &children)?;
+        }
+
+        let index = index.get();
+        let removed = self.skeleton.joints[index].clone();
+        let mut joints = self.skeleton.joints.clone();
+        for child in &children {
+            let offset = &mut joints[child.get()].offset;
+            *offset = add_vectors(*offset, removed.offset);
+        }
+
+        remap_parents(&mut joints, |p| match p.cmp(&index) {
+            Ordering::Less => Some(p),
+            Ordering::Equal => removed.parent_index,
+            Ordering::Greater => Some(p - 1),
+        });
+        joints.remove(index);
+
+        if let (true, Some(parent)) = (children.is_empty(), removed.parent_index) {
+            if !joints
+                .iter()
+                .any(|joint| joint.parent_index == Some(parent))
+            {
+                let end_site = removed.end_site.unwrap_or_else(zero_vector);
+                joints[parent].end_site = Some(add_vectors(removed.offset, end_site));
+            }
+        }
+
+        self.relayout(joints);
+        Ok(())
+    }
+
+    /// Move the `Joint` at `index`, along with all of its descendants, so that it
+    /// becomes the last child of `new_parent`.
+    ///
+    /// The offset and motion values of the joint are kept, so they are now
+    /// relative to `new_parent`. If `new_parent` was capped with an `End Site`, the
+    /// end site is removed, and if the joint was the only child of its old parent,
+    /// the old parent is capped with an `End Site` at the joint's offset.
+    ///
+    /// The joints are reordered so that every subtree is still contiguous, so the
+    /// index of any joint may change.
+    ///
+    /// Returns an error if `new_parent` is the joint or one of its descendants.
+    pub fn reparent_joint(
+        &mut self,
+        index: JointIndex,
+        new_parent: JointIndex,
+    ) -> Result<(), EditJointsError> {
+        self.check_joint_index(index)?;
+        self.check_joint_index(new_parent)?;
+
+        let subtree = index.get()..self.subtree_end(index);
+        if subtree.contains(&new_parent.get()) {
+            return Err(EditJointsError::WouldCreateCycle {
+                joint: index,
+                new_parent,
+            });
+        }
+
+        let old_parent = self.skeleton.joints[index.get()].parent_index;
+        if old_parent == Some(new_parent.get()) {
+            return Ok(());
+        }
+
+        // The indices of the current joints, in their new order.
+        let parent_end = self.subtree_end(new_parent);
+        let mut order: Vec<_> = (0..self.skeleton.joints.len())
+            .filter(|i| !subtree.contains(i))
+            .collect();
+        let insert_at = order.partition_point(|&i| i < parent_end);
+        order.splice(insert_at..insert_at, subtree);
+
+        let mut new_indices = vec![0; order.len()];
+        for (new_index, &old_index) in order.iter().enumerate() {
+            new_indices[old_index] = new_index;
+        }
+
+        let mut joints: Vec<_> = order
+            .iter()
+            .map(|&i| self.skeleton.joints[i].clone())
+            .collect();
+        remap_parents(&mut joints, |p| Some(new_indices[p]));
+        joints[new_indices[index.get()]].parent_index = Some(new_indices[new_parent.get()]);
+
+        if let Some(old_parent) = old_parent.map(|p| new_indices[p]) {
+            if !joints
+                .iter()
+                .any(|joint| joint.parent_index == Some(old_parent))
+            {
+                joints[old_parent].end_site = Some(self.skeleton.joints[index.get()].offset);
+            }
+        }
+
+        self.relayout(joints);
+        Ok(())
+    }
+
+    /// Add channels of the given types to the `Joint` at `index`, with a value of
+    /// `0.0` in every frame.
+    ///
+    /// New position channels are added after the existing position channels and
+    /// before any rotation channels, and new rotation channels are added after the
+    /// existing rotation channels, in the order in which they are given. Use
+    /// [`Bvh::set_channel_order`][`Bvh::set_channel_order`] to put them in a
+    /// different order.
+    ///
+    /// Returns an error if the joint already has a channel of one of the types, or
+    /// a type is given twice.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::{bvh, ChannelType};
+    /// let mut bvh = bvh! {
+    ///     HIERARCHY
+    ///     ROOT Hips
+    ///     {
+    ///         OFFSET 0.0 0.0 0.0
+    ///         CHANNELS 2 Zrotation Xrotation
+    ///         End Site
+    ///         {
+    ///             OFFSET 0.0 10.0 0.0
+    ///         }
+    ///     }
+    ///     MOTION
+    ///     Frames: 1
+    ///     Frame Time: 0.033333333
+    ///     10.0 20.0
+    /// };
+    ///
+    /// let positions = [ChannelType::PositionX, ChannelType::PositionY, ChannelType::PositionZ];
+    /// for joint in bvh.joint_indices().collect::<Vec<_>>() {
+    ///     bvh.add_channels(joint, &positions)?;
+    /// }
+    /// assert_eq!(bvh.frames().next().unwrap().as_slice(), &[0.0, 0.0, 0.0, 10.0, 20.0]);
+    /// # Result::<(), bvh_anim::errors::EditJointsError>::Ok(())
+    /// ```
+    ///
+    /// [`Bvh::set_channel_order`]: #method.set_channel_order
+    pub fn add_channels(
+        &mut self,
+        index: JointIndex,
+        channel_types: &[ChannelType],
+    ) -> Result<(), EditJointsError> {
+        self.check_joint_index(index)?;
+
+        let existing = self[index].channels.iter().map(Channel::channel_type);
+        if let Some(channel_type) = first_duplicate(existing.chain(channel_types.iter().copied())) {
+            return Err(EditJointsError::DuplicateChannel {
+                joint: index,
+                channel_type,
+            });
+        }
+
+        let mut joints = self.skeleton.joints.clone();
+        let channels = &mut joints[index.get()].channels;
+        let first_rotation = channels
+            .iter()
+            .position(|channel| channel.channel_type.is_rotation())
+            .unwrap_or(channels.len());
+        let new_channel = |&channel_type: &ChannelType| Channel::new(channel_type, NEW_CHANNEL);
+        channels.insert_many(
+            first_rotation,
+            channel_types
+                .iter()
+                .filter(|ty| ty.is_position())
+                .map(new_channel),
+        );
+        channels.extend(
+            channel_types
+                .iter()
+                .filter(|ty| ty.is_rotation())
+                .map(new_channel),
+        );
+
+        self.relayout(joints);
+        Ok(())
+    }
+
+    /// Remove the channels of the given types from the `Joint` at `index`, along
+    /// with their motion values.
+    ///
+    /// Returns an error if the joint does not have a channel of one of the types.
+    pub fn remove_channels(
+        &mut self,
+        index: JointIndex,
+        channel_types: &[ChannelType],
+    ) -> Result<(), EditJointsError> {
+        self.check_joint_index(index)?;
+
+        let joint = &self[index];
+        if let Some(&channel_type) = channel_types.iter().find(|&&ty| !joint.has_channel(ty)) {
+            return Err(EditJointsError::MissingChannel {
+                joint: index,
+                channel_type,
+            });
+        }
+
+        let mut joints = self.skeleton.joints.clone();
+        joints[index.get()]
+            .channels
+            .retain(|channel| !channel_types.contains(&channel.channel_type));
+
+        self.relayout(joints);
+        Ok(())
+    }
+
+    /// Remove every channel whose value is `0.0` in every frame, and return the
+    /// number of channels which were removed.
+    ///
+    /// Nothing is removed if the `Bvh` has no frames.
+    pub fn remove_zero_channels(&mut self) -> usize {
+        if self.motion.num_frames == 0 {
+            return 0;
+        }
+
+        let motion = &self.motion;
+        let is_zero = |channel: &Channel| motion.curve(channel).iter().all(|&value| value == 0.0);
+
+        let mut joints = self.skeleton.joints.clone();
+        for joint in &mut joints {
+            joint.channels.retain(|channel| !is_zero(channel));
+        }
+
+        let num_channels = joints.iter().map(|j| j.channels.len()).sum::<usize>();
+        let num_removed = self.motion.num_channels - num_channels;
+        if num_removed > 0 {
+            self.relayout(joints);
+        }
+        num_removed
+    }
+
+    /// Change the order of the channels of the `Joint` at `index` to `order`.
+    ///
+    /// `order` must contain the type of each channel of the joint exactly once.
+    /// If the order of the rotation channels changes, the rotation in every frame
+    /// is converted so that the orientation of the joint is unchanged, which
+    /// requires the joint to have exactly one rotation channel about each axis.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::{bvh, ChannelType, JointIndex};
+    /// let mut bvh = bvh! {
+    ///     HIERARCHY
+    ///     ROOT Hips
+    ///     {
+    ///         OFFSET 0.0 0.0 0.0
+    ///         CHANNELS 3 Zrotation Xrotation Yrotation
+    ///         End Site
+    ///         {
+    ///             OFFSET 0.0 10.0 0.0
+    ///         }
+    ///     }
+    ///     MOTION
+    ///     Frames: 1
+    ///     Frame Time: 0.033333333
+    ///     0.0 0.0 45.0
+    /// };
+    ///
+    /// let order = [ChannelType::RotationX, ChannelType::RotationY, ChannelType::RotationZ];
+    /// bvh.set_channel_order(JointIndex::new(0), &order)?;
+    ///
+    /// let frame = bvh.frames().next().unwrap();
+    /// assert!((frame.as_slice()[1] - 45.0).abs() < 1e-4);
+    /// # Result::<(), bvh_anim::errors::EditJointsError>::Ok(())
+    /// ```
+    pub fn set_channel_order(
+        &mut self,
+        index: JointIndex,
+        order: &[ChannelType],
+    ) -> Result<(), EditJointsError> {
+        self.check_joint_index(index)?;
+
+        let joint = &self[index];
+        let existing: SmallVec<[ChannelType; 6]> =
+            joint.channels.iter().map(Channel::channel_type).collect();
+        let duplicate = first_duplicate(order.iter().copied())
+            .or_else(|| first_duplicate(existing.iter().copied()));
+        if let Some(channel_type) = duplicate {
+            return Err(EditJointsError::DuplicateChannel {
+                joint: index,
+                channel_type,
+            });
+        }
+
+        let missing = order
+            .iter()
+            .chain(&existing)
+            .find(|ty| !order.contains(ty) || !existing.contains(ty));
+        if let Some(&channel_type) = missing {
+            return Err(EditJointsError::MissingChannel {
+                joint: index,
+                channel_type,
+            });
+        }
+
+        let old_rotation = rotation_channels(joint);
+        let new_order: SmallVec<[Axis; 3]> = order
+            .iter()
+            .filter(|ty| ty.is_rotation())
+            .map(ChannelType::axis)
+            .collect();
+        if old_rotation
+            .iter()
+            .map(|&(axis, _)| axis)
+            .ne(new_order.iter().copied())
+        {
+            let (channels, _) = self.euler_channels(index)?;
+            let new_order = [new_order[0], new_order[1], new_order[2]];
+            self.motion.for_each_frame_mut(|frame| {
+                let angles = euler_angles(&rotation_matrix(&channels, frame), new_order);
+                for (&axis, angle) in new_order.iter().zip(angles.iter()) {
+                    let &(_, motion_index) = channels.iter().find(|&&(a, _)| a == axis).unwrap();
+                    frame[motion_index] = *angle as Float;
+                }
+            });
+        }
+
+        let mut joints = self.skeleton.joints.clone();
+        let channels = &mut joints[index.get()].channels;
+        *channels = order
+            .iter()
+            .map(|&ty| *channels.iter().find(|ch| ch.channel_type == ty).unwrap())
+            .collect();
+
+        self.relayout(joints);
+        Ok(())
+    }
+
+    fn check_joint_index(&self, index: JointIndex) -> Result<(), EditJointsError> {
+        if index.get() < self.skeleton.joints.len() {
+            Ok(())
+        } else {
+            Err(EditJointsError::BadJoint(index))
+        }
+    }
+
+    /// The index one past the last descendant of the joint at `index`.
+    fn subtree_end(&self, index: JointIndex) -> usize {
+        index.get() + 1 + self.descendants(index).count()
+    }
+
+    /// The rotation channels of the joint at `index` and their axes, which must
+    /// be one about each axis.
+    fn euler_channels(
+        &self,
+        index: JointIndex,
+    ) -> Result<(RotationChannels, [Axis; 3]), EditJointsError> {
+        let channels = rotation_channels(&self[index]);
+        match channels[..] {
+            [(a, _), (b, _), (c, _)] if a != b && b != c && a != c => Ok((channels, [a, b, c])),
+            _ => Err(EditJointsError::UnsupportedRotationChannels { joint: index }),
+        }
+    }
+
+    /// Combine the rotation of the joint at `index` with the rotation of each of
+    /// its `children`, in place.
+    fn merge_rotation(
+        &mut self,
+        index: JointIndex,
+        children: &[JointIndex],
+    ) -> Result<(), EditJointsError> {
+        let parent_rotation = rotation_channels(&self[index]);
+        if parent_rotation.is_empty() {
+            return Ok(());
+        }
+
+        let mut child_rotations = Vec::with_capacity(children.len());
+        for &child in children {
+            child_rotations.push(self.euler_channels(child)?);
+        }
+
+        self.motion.for_each_frame_mut(|frame| {
+            let parent_matrix = rotation_matrix(&parent_rotation, frame);
+            for (channels, order) in &child_rotations {
+                let matrix = mul_matrices(&parent_matrix, &rotation_matrix(channels, frame));
+                let angles = euler_angles(&matrix, *order);
+                for (&(_, motion_index), angle) in channels.iter().zip(angles.iter()) {
+                    frame[motion_index] = *angle as Float;
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Replace the joints with `joints`, and rebuild the depths, channels, end sites
+    /// and motion values to match.
+    ///
+    /// The parent of each joint must come before it. The motion index of each
+    /// channel must be the index of its value in the current frames, or
+    /// `NEW_CHANNEL`.
+    fn relayout(&mut self, mut joints: Vec<Joint>) {
+        let mut has_children = vec![false; joints.len()];
+        let mut sources = Vec::with_capacity(self.motion.num_channels);
+
+        for index in 0..joints.len() {
+            let depth = match joints[index].parent_index {
+                Some(parent) => {
+                    has_children[parent] = true;
+                    joints[parent].depth + 1
+                }
+                None => 0,
+            };
+
+            let joint = &mut joints[index];
+            joint.depth = depth;
+            for channel in &mut joint.channels {
+                sources.push(channel.motion_index);
+                channel.motion_index = sources.len() - 1;
+            }
+        }
+
+        // The writer closes each chain of joints at its end site, so every leaf
+        // needs one and no other joint can have one.
+        for (joint, has_children) in joints.iter_mut().zip(has_children) {
+            if has_children {
+                joint.end_site = None;
+            } else if joint.end_site.is_none() {
+                joint.end_site = Some(zero_vector());
+            }
+        }
+
+        let (num_frames, num_channels) = (self.motion.num_frames, self.motion.num_channels);
+        let mut motion_values = Vec::with_capacity(num_frames * sources.len());
+        match self.motion.layout {
+            MotionLayout::FrameMajor => {
+                for frame in 0..num_frames {
+                    let start = frame * num_channels;
+                    let old_frame = &self.motion.values[start..start + num_channels];
+                    motion_values.extend(sources.iter().map(|&source| match source {
+                        NEW_CHANNEL => 0.0,
+                        source => old_frame[source],
+                    }));
+                }
+            }
+            MotionLayout::ChannelMajor => {
+                for &source in &sources {
+                    match source {
+                        NEW_CHANNEL => motion_values.resize(motion_values.len() + num_frames, 0.0),
+                        source => motion_values.extend_from_slice(
+                            &self.motion.values[source * num_frames..(source + 1) * num_frames],
+                        ),
+                    }
+                }
+            }
+        }
+
+        self.skeleton = Arc::new(Skeleton::from_joints(joints, sources.len()));
+        self.motion.num_channels = sources.len();
+        self.motion.values = motion_values;
+        self.motion.frame_major.take();
+    }
+}
+
+fn first_duplicate(channel_types: impl Iterator<Item = ChannelType>) -> Option<ChannelType> {
+    let mut seen = SmallVec::<[ChannelType; 6]>::new();
+    for channel_type in channel_types {
+        if seen.contains(&channel_type) {
+            return Some(channel_type);
+        }
+        seen.push(channel_type);
+    }
+    None
+}
+
+fn remap_parents(joints: &mut [Joint], mut remap: impl FnMut(usize) -> Option<usize>) {
+    for joint in joints {
+        if let Some(parent) = joint.parent_index {
+            joint.parent_index = remap(parent);
+        }
+    }
+}
+
+#[inline]
+fn zero_vector() -> Vector3<Float> {
+    [0.0, 0.0, 0.0].into()
+}
+
+#[inline]
+fn add_vectors(a: Vector3<Float>, b: Vector3<Float>) -> Vector3<Float> {
+    [a.x + b.x, a.y + b.y, a.z + b.z].into()
+}
+
+type Matrix = [[f64; 3]; 3];
+
+const IDENTITY: Matrix = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// The axis and motion index of each rotation channel of a joint, in order.
+type RotationChannels = SmallVec<[(Axis, usize); 3]>;
+
+fn rotation_channels(joint: &Joint) -> RotationChannels {
+    joint
+        .channels
+        .iter()
+        .filter(|channel| channel.channel_type.is_rotation())
+        .map(|channel| (channel.channel_type.axis(), channel.motion_index))
+        .collect()
+}
+
+#[inline]
+fn axis_index(axis: Axis) -> usize {
+    match axis {
+        Axis::X => 0,
+        Axis::Y => 1,
+        Axis::Z => 2,
+    }
+}
+
+fn mul_matrices(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [[0.0; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+/// The rotation of a joint in a `frame`. As in the bvh format, the rotations
+/// are applied to a vector in the reverse of the order of the channels.
+// `Float` is already `f64` when the `f64` feature is enabled.
+#[allow(clippy::useless_conversion)]
+fn rotation_matrix(channels: &[(Axis, usize)], frame: &[Float]) -> Matrix {
+    channels
+        .iter()
+        .fold(IDENTITY, |matrix, &(axis, motion_index)| {
+            let (sin, cos) = f64::from(frame[motion_index]).to_radians().sin_cos();
+            let i = axis_index(axis);
+            let (j, k) = ((i + 1) % 3, (i + 2) % 3);
+
+            let mut rotation = IDENTITY;
+            rotation[j][j] = cos;
+            rotation[j][k] = -sin;
+            rotation[k][j] = sin;
+            rotation[k][k] = cos;
+            mul_matrices(&matrix, &rotation)
+        })
+}
+
+/// Decompose `matrix` into rotations in degrees about three distinct axes, such
+/// that rotating about `order[0]`, `order[1]` and `order[2]` in turn gives the
+/// same matrix as `rotation_matrix`.
+fn euler_angles(matrix: &Matrix, order: [Axis; 3]) -> [f64; 3] {
+    let [i, j, k] = order.map(axis_index);
+    // Orders such as XYZ are even permutations of the axes; orders such as XZY
+    // flip the signs of the sines.
+    let sign = if (i + 1) % 3 == j { 1.0 } else { -1.0 };
+
+    let sin_b = (sign * matrix[i][k]).clamp(-1.0, 1.0);
+    let b = sin_b.asin();
+    let (a, c) = if sin_b.abs() < 1.0 - 1e-9 {
+        let a = (-sign * matrix[j][k]).atan2(matrix[k][k]);
+        let c = (-sign * matrix[i][j]).atan2(matrix[i][i]);
+        (a, c)
+    } else {
+        // In gimbal lock only the sum of the first and last angles is known.
+        ((sign * matrix[k][j]).atan2(matrix[j][j]), 0.0)
+    };
+
+    [a.to_degrees(), b.to_degrees(), c.to_degrees()]
+}
@@ -0,0 +1,401 @@
+//! Contains types to describe the text encoding of a `bvh` file, and of the
+//! names of its joints.
+//!
+//! The parser works on `utf8`, or any other ascii-compatible encoding. Files which
+//! start with a byte order mark are detected when they are loaded: the mark of a
+//! `utf8` file is skipped, and a `utf16` file is transcoded to `utf8` as it is
+//! read. The encoding which was found is available from
+//! [`Bvh::encoding`][`Bvh::encoding`]. Files are always written as `utf8`, with a
+//! byte order mark if the `Bvh` was loaded from `utf8` with one.
+//!
+//! Joint names are kept as raw bytes. Use [`Joint::name_lossy`][`Joint::name_lossy`]
+//! with a [`NameEncoding`][`NameEncoding`] to decode them for display.
+//!
+//! [`Bvh::encoding`]: ../struct.Bvh.html#method.encoding
+//! [`Joint::name_lossy`]: ../struct.Joint.html#method.name_lossy
+//! [`NameEncoding`]: enum.NameEncoding.html
+
+use std::{
+    borrow::Cow,
+    char,
+    io::{self, BufRead},
+    str,
+};
+
+pub(crate) const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16BE_BOM: &[u8] = b"\xFE\xFF";
+
+/// The text encoding of a `bvh` file, as detected from its byte order mark.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TextEncoding {
+    /// `utf8` (or an ascii-compatible encoding) without a byte order mark.
+    Utf8,
+    /// `utf8` which starts with a byte order mark.
+    Utf8WithBom,
+    /// Little-endian `utf16`, which starts with a byte order mark.
+    Utf16Le,
+    /// Big-endian `utf16`, which starts with a byte order mark.
+    Utf16Be,
+}
+
+impl TextEncoding {
+    /// Detect the encoding of `bytes` from its byte order mark.
+    ///
+    /// Returns the encoding, and the length of the byte order mark.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::encoding::TextEncoding;
+    /// assert_eq!(TextEncoding::detect(b"HIERARCHY"), (TextEncoding::Utf8, 0));
+    /// assert_eq!(TextEncoding::detect(b"\xEF\xBB\xBFHIERARCHY"), (TextEncoding::Utf8WithBom, 3));
+    /// assert_eq!(TextEncoding::detect(b"\xFF\xFEH\0"), (TextEncoding::Utf16Le, 2));
+    /// ```
+    pub fn detect(bytes: &[u8]) -> (Self, usize) {
+        if bytes.starts_with(UTF8_BOM) {
+            (TextEncoding::Utf8WithBom, UTF8_BOM.len())
+        } else if bytes.starts_with(UTF16LE_BOM) {
+            (TextEncoding::Utf16Le, UTF16LE_BOM.len())
+        } else if bytes.starts_with(UTF16BE_BOM) {
+            (TextEncoding::Utf16Be, UTF16BE_BOM.len())
+        } else {
+            (TextEncoding::Utf8, 0)
+        }
+    }
+
+    /// Returns `true` if the encoding is `utf16`, and must be transcoded
+    /// before it can be parsed.
+    #[inline]
+    pub fn is_utf16(self) -> bool {
+        match self {
+            TextEncoding::Utf16Le | TextEncoding::Utf16Be => true,
+            TextEncoding::Utf8 | TextEncoding::Utf8WithBom => false,
+        }
+    }
+
+    /// Get the name of the encoding.
+    #[inline]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf8WithBom => "UTF-8 with BOM",
+            TextEncoding::Utf16Le => "UTF-16LE",
+            TextEncoding::Utf16Be => "UTF-16BE",
+        }
+    }
+}
+
+impl Default for TextEncoding {
+    #[inline]
+    fn default() -> Self {
+        TextEncoding::Utf8
+    }
+}
+
+/// Detect the encoding of `bytes`, and get the text after the byte order mark
+/// as `utf8`.
+///
+/// Unpaired surrogates in `utf16` text are replaced with `U+FFFD`.
+pub(crate) fn decode(bytes: &[u8]) -> (TextEncoding, Cow<'_, [u8]>) {
+    let (encoding, bom_len) = TextEncoding::detect(bytes);
+    if !encoding.is_utf16() {
+        return (encoding, Cow::Borrowed(&bytes[bom_len..]));
+    }
+
+    let mut decoder = StreamDecoder::new();
+    let mut decoded = Vec::with_capacity(bytes.len() / 2);
+    decoder.decode(bytes, &mut decoded);
+    decoder.finish(&mut decoded);
+    (encoding, Cow::Owned(decoded))
+}
+
+/// Transcodes a `bvh` file to `utf8` as it is read, a line at a time.
+///
+/// The encoding is detected from the byte order mark at the start of the
+/// stream, which is not passed on. A `utf16` code unit or surrogate pair which
+/// is split between two reads is carried over to the next one, so the input
+/// may be broken up anywhere.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StreamDecoder {
+    /// The detected encoding, or `None` while the start of a byte order mark
+    /// has been read, but not the whole of it.
+    encoding: Option<TextEncoding>,
+    /// The start of a byte order mark, or the first byte of a `utf16` code unit.
+    pending: Vec<u8>,
+    /// A high surrogate waiting for the low surrogate which follows it.
+    high_surrogate: Option<u16>,
+}
+
+impl StreamDecoder {
+    #[inline]
+    pub(crate) fn new() -> Self {
+        Default::default()
+    }
+
+    /// The encoding of the stream, once enough of it has been read to detect it.
+    #[inline]
+    pub(crate) fn encoding(&self) -> TextEncoding {
+        self.encoding.unwrap_or_default()
+    }
+
+    /// Decode the whole of `input`, appending the `utf8` text to `out`.
+    pub(crate) fn decode(&mut self, mut input: &[u8], out: &mut Vec<u8>) {
+        while !input.is_empty() {
+            let (consumed, _) = self.decode_line(input, out);
+            input = &input[consumed..];
+        }
+    }
+
+    /// Decode `input` up to and including the first newline, appending the `utf8`
+    /// text to `out`.
+    ///
+    /// Returns the number of bytes of `input` which were used, and whether a
+    /// newline was found.
+    pub(crate) fn decode_line(&mut self, input: &[u8], out: &mut Vec<u8>) -> (usize, bool) {
+        let mut consumed = 0;
+        if self.encoding.is_none() {
+            consumed = self.detect(input, out);
+        }
+
+        let input = &input[consumed..];
+        let (used, found_newline) = match self.encoding {
+            None => (0, false),
+            Some(TextEncoding::Utf8) | Some(TextEncoding::Utf8WithBom) => {
+                match input.iter().position(|&b| b == b'\n') {
+                    Some(i) => {
+                        out.extend_from_slice(&input[..=i]);
+                        (i + 1, true)
+                    }
+                    None => {
+                        out.extend_from_slice(input);
+                        (input.len(), false)
+                    }
+                }
+            }
+            Some(TextEncoding::Utf16Le) => self.decode_utf16_line(input, out, u16::from_le_bytes),
+            Some(TextEncoding::Utf16Be) => self.decode_utf16_line(input, out, u16::from_be_bytes),
+        };
+
+        (consumed + used, found_newline)
+    }
+
+    /// Read the next line of `reader` onto the end of `line`, as `utf8`.
+    ///
+    /// Only the bytes up to the end of the line are consumed. Returns the number of
+    /// bytes appended to `line`, which is `0` at the end of the stream.
+    pub(crate) fn read_line<R: BufRead + ?Sized>(
+        &mut self,
+        reader: &mut R,
+        line: &mut Vec<u8>,
+    ) -> io::Result<usize> {
+        let start = line.len();
+        loop {
+            let input = reader.fill_buf()?;
+            if input.is_empty() {
+                self.finish(line);
+                break;
+            }
+
+            let (consumed, found_newline) = self.decode_line(input, line);
+            reader.consume(consumed);
+            if found_newline {
+                break;
+            }
+        }
+        Ok(line.len() - start)
+    }
+
+    /// Flush any input which was held back, at the end of the stream.
+    ///
+    /// A truncated `utf16` code unit or an unpaired surrogate is replaced with
+    /// `U+FFFD`.
+    pub(crate) fn finish(&mut self, out: &mut Vec<u8>) {
+        match self.encoding {
+            None => {
+                self.encoding = Some(TextEncoding::Utf8);
+                out.append(&mut self.pending);
+            }
+            Some(encoding) if encoding.is_utf16() => {
+                if self.high_surrogate.take().is_some() {
+                    push_char(char::REPLACEMENT_CHARACTER, out);
+                }
+                if !self.pending.is_empty() {
+                    self.pending.clear();
+                    push_char(char::REPLACEMENT_CHARACTER, out);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// Read bytes of `input` for as long as they could be part of a byte order
+    /// mark, and set the encoding once it is known.
+    fn detect(&mut self, input: &[u8], out: &mut Vec<u8>) -> usize {
+        let boms = [UTF8_BOM, UTF16LE_BOM, UTF16BE_BOM];
+        for (i, &byte) in input.iter().enumerate() {
+            self.pending.push(byte);
+            let is_prefix = boms.iter().any(|bom| bom.starts_with(&self.pending));
+            if !is_prefix {
+                self.encoding = Some(TextEncoding::Utf8);
+                self.pending.pop();
+                out.append(&mut self.pending);
+                return i;
+            }
+
+            let (encoding, bom_len) = TextEncoding::detect(&self.pending);
+            if bom_len != 0 {
+                self.encoding = Some(encoding);
+                self.pending.clear();
+                return i + 1;
+            }
+        }
+
+        input.len()
+    }
+
+    fn decode_utf16_line(
+        &mut self,
+        input: &[u8],
+        out: &mut Vec<u8>,
+        to_unit: fn([u8; 2]) -> u16,
+    ) -> (usize, bool) {
+        for (i, &byte) in input.iter().enumerate() {
+            let first = match self.pending.pop() {
+                Some(first) => first,
+                None => {
+                    self.pending.push(byte);
+                    continue;
+                }
+            };
+
+            let unit = to_unit([first, byte]);
+            if let Some(high) = self.high_surrogate.take() {
+                if is_low_surrogate(unit) {
+                    let c =
+                        0x10000 + ((u32::from(high) - 0xD800) << 10) + (u32::from(unit) - 0xDC00);
+                    push_char(
+                        char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER),
+                        out,
+                    );
+                    continue;
+                }
+                push_char(char::REPLACEMENT_CHARACTER, out);
+            }
+
+            if is_high_surrogate(unit) {
+                self.high_surrogate = Some(unit);
+            } else {
+                let c = char::from_u32(u32::from(unit)).unwrap_or(char::REPLACEMENT_CHARACTER);
+                push_char(c, out);
+                if c == '\n' {
+                    return (i + 1, true);
+                }
+            }
+        }
+
+        (input.len(), false)
+    }
+}
+
+#[inline]
+fn is_high_surrogate(unit: u16) -> bool {
+    (0xD800..0xDC00).contains(&unit)
+}
+
+#[inline]
+fn is_low_surrogate(unit: u16) -> bool {
+    (0xDC00..0xE000).contains(&unit)
+}
+
+#[inline]
+fn push_char(c: char, out: &mut Vec<u8>) {
+    let mut buf = [0; 4];
+    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+}
+
+/// The encoding used to decode the name of a `Joint`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum NameEncoding {
+    /// Decode the name as `utf8`, replacing any invalid sequences with `U+FFFD`.
+    Utf8,
+    /// Decode the name as Latin-1 (ISO 8859-1), where every byte is a character.
+    Latin1,
+}
+
+impl NameEncoding {
+    /// Decode `bytes` as a string in this encoding.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::encoding::NameEncoding;
+    /// assert_eq!(NameEncoding::Latin1.decode(b"Epaule_\xE9"), "Epaule_é");
+    /// assert_eq!(NameEncoding::Utf8.decode(b"Epaule_\xE9"), "Epaule_\u{FFFD}");
+    /// ```
+    pub fn decode(self, bytes: &[u8]) -> Cow<'_, str> {
+        match self {
+            NameEncoding::Utf8 => String::from_utf8_lossy(bytes),
+            NameEncoding::Latin1 => match str::from_utf8(bytes) {
+                Ok(s) if s.is_ascii() => Cow::Borrowed(s),
+                _ => Cow::Owned(bytes.iter().map(|&b| char::from(b)).collect()),
+            },
+        }
+    }
+}
+
+impl Default for NameEncoding {
+    #[inline]
+    fn default() -> Self {
+        NameEncoding::Utf8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_chunks(chunks: &[&[u8]]) -> (TextEncoding, Vec<u8>) {
+        let mut decoder = StreamDecoder::new();
+        let mut out = vec![];
+        for chunk in chunks {
+            decoder.decode(chunk, &mut out);
+        }
+        decoder.finish(&mut out);
+        (decoder.encoding(), out)
+    }
+
+    #[test]
+    fn stream_decoder_carries_split_input() {
+        // A byte order mark, a code unit and a surrogate pair, each split in two.
+        let chunks: &[&[u8]] = &[b"\xFF", b"\xFEA", b"\0\x3E\xD8", b"\xDD\xDD\n\0"];
+        let expected = "A\u{1F9DD}\n".as_bytes().to_vec();
+        assert_eq!(decode_chunks(chunks), (TextEncoding::Utf16Le, expected));
+
+        let chunks: &[&[u8]] = &[b"\xEF", b"\xBB\xBFA\n"];
+        assert_eq!(
+            decode_chunks(chunks),
+            (TextEncoding::Utf8WithBom, b"A\n".to_vec())
+        );
+
+        // The start of a byte order mark which turns out to be text.
+        let chunks: &[&[u8]] = &[b"\xEF\xBB", b"A"];
+        assert_eq!(
+            decode_chunks(chunks),
+            (TextEncoding::Utf8, b"\xEF\xBBA".to_vec())
+        );
+        assert_eq!(
+            decode_chunks(&[b"\xFE"]),
+            (TextEncoding::Utf8, b"\xFE".to_vec())
+        );
+    }
+
+    #[test]
+    fn stream_decoder_replaces_truncated_utf16() {
+        let replaced = "A\u{FFFD}\u{FFFD}".as_bytes().to_vec();
+        assert_eq!(
+            decode_chunks(&[b"\xFE\xFF\0A\xD8\x3E\0"]),
+            (TextEncoding::Utf16Be, replaced)
+        );
+    }
+}
@@ -1,7 +1,7 @@
 //! Errors which may occur when manipulating `Bvh` files.
 
-use bstr::BString;
-use crate::{Axis, Channel};
+use bstr::{BString, ByteSlice};
+use crate::{Axis, Channel, ChannelType, JointIndex};
 use lexical::Error as LexicalError;
 use std::{error::Error as StdError, fmt, io};
 
@@ -24,6 +24,53 @@ impl LoadError {
         }
     }
 
+    /// Get the location in the source where the error occurred, or `None` if
+    /// there is no associated location.
+    #[inline]
+    pub fn span(&self) -> Option<Span> {
+        match self.kind {
+            LoadErrorKind::Joints(ref e) => e.span(),
+            LoadErrorKind::Motion(ref e) => e.span(),
+        }
+    }
+
+    /// Returns a value which displays the error together with the line of
+    /// `source` where it occurred, with the offending token underlined.
+    ///
+    /// `source` should be the same bytes which the `Bvh` was loaded from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::from_bytes;
+    /// let source = b"HIERARCHY\nROOT Hips\n{\n\tOFFSET 0.0 zero 0.0\n}\n";
+    /// let err = from_bytes(&source[..]).unwrap_err();
+    /// let rendered = err.display_with_source(&source[..]).to_string();
+    /// assert!(rendered.contains("\tOFFSET 0.0 zero 0.0"));
+    /// assert!(rendered.contains("\t           ^^^^"));
+    /// ```
+    #[inline]
+    pub fn display_with_source<'a>(&'a self, source: &'a [u8]) -> SourceDiagnostic<'a> {
+        SourceDiagnostic {
+            error: self,
+            source,
+        }
+    }
+
+    /// Write the error message, without the location prefix.
+    fn fmt_message(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            LoadErrorKind::Joints(ref e) => {
+                fmtr.write_str("Could not load hierarchy: ")?;
+                e.fmt_message(fmtr)
+            }
+            LoadErrorKind::Motion(ref e) => {
+                fmtr.write_str("Could not load motion: ")?;
+                e.fmt_message(fmtr)
+            }
+        }
+    }
+
     /// Returns the `LoadError` kind.
     #[inline]
     pub fn kind(&self) -> &LoadErrorKind {
@@ -69,6 +116,90 @@ impl StdError for LoadError {
     }
 }
 
+/// A location in the source of a bvh file.
+///
+/// Lines and columns are zero-based, and the column and length are measured
+/// in bytes. When displayed, the line and column are one-based.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Span {
+    /// The line of the source.
+    pub line: usize,
+    /// The byte offset from the start of the line.
+    pub column: usize,
+    /// The length of the span in bytes. May be `0` if the span refers
+    /// to something which is missing.
+    pub len: usize,
+}
+
+impl Span {
+    /// Create a new `Span`.
+    #[inline]
+    pub const fn new(line: usize, column: usize, len: usize) -> Self {
+        Span { line, column, len }
+    }
+}
+
+impl fmt::Display for Span {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmtr, "{}:{}", self.line + 1, self.column + 1)
+    }
+}
+
+/// Displays a `LoadError` alongside the source line where it occurred.
+///
+/// Created with the [`LoadError::display_with_source`][`LoadError::display_with_source`]
+/// method.
+///
+/// [`LoadError::display_with_source`]: struct.LoadError.html#method.display_with_source
+#[derive(Debug)]
+pub struct SourceDiagnostic<'a> {
+    error: &'a LoadError,
+    source: &'a [u8],
+}
+
+impl fmt::Display for SourceDiagnostic<'_> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmtr.write_str("error: ")?;
+        self.error.fmt_message(fmtr)?;
+
+        let span = match self.error.span() {
+            Some(span) => span,
+            None => return Ok(()),
+        };
+
+        write!(fmtr, "\n --> {}", span)?;
+
+        let line = match self.source.lines().nth(span.line) {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+
+        let line_num = (span.line + 1).to_string();
+        let gutter = " ".repeat(line_num.len());
+        let column = span.column.min(line.len());
+        let end = (column + span.len).min(line.len());
+
+        // Copy tabs from the source line so that the underline stays aligned.
+        let padding = line[..column]
+            .to_str_lossy()
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let underline = "^".repeat(line[column..end].to_str_lossy().chars().count().max(1));
+
+        write!(
+            fmtr,
+            "\n{gutter} |\n{line_num} | {line}\n{gutter} | {padding}{underline}",
+            gutter = gutter,
+            line_num = line_num,
+            line = line.to_str_lossy(),
+            padding = padding,
+            underline = underline,
+        )
+    }
+}
+
 /// The kind of the `LoadError`.
 #[derive(Debug)]
 pub enum LoadErrorKind {
@@ -99,35 +230,83 @@ pub enum LoadJointsError {
     /// An I/O error occurred.
     Io(io::Error),
     /// The skeletal hierarchy is missing the `Root` joint.
-    MissingRoot,
+    MissingRoot {
+        /// The location in the source bvh where the hierarchy ended.
+        span: Span,
+    },
+    /// A `HIERARCHY` section was encountered in the wrong location.
+    UnexpectedHierarchySection {
+        /// The location in the source bvh where the error occurred.
+        span: Span,
+    },
+    /// A `ROOT` joint was encountered in the wrong location.
+    UnexpectedRoot {
+        /// The location in the source bvh where the error occurred.
+        span: Span,
+    },
+    /// A `JOINT` was encountered in the wrong location.
+    UnexpectedJoint {
+        /// The location in the source bvh where the error occurred.
+        span: Span,
+    },
+    /// An `End Site` section was encountered in the wrong location.
+    UnexpectedEndSite {
+        /// The location in the source bvh where the error occurred.
+        span: Span,
+    },
+    /// An `End` keyword was not followed by the `Site` keyword.
+    MalformedEndSite {
+        /// The location in the source bvh where the error occurred.
+        span: Span,
+    },
+    /// An opening brace was encountered in the wrong location.
+    UnexpectedOpeningBrace {
+        /// The location in the source bvh where the error occurred.
+        span: Span,
+    },
+    /// A closing brace was encountered which does not match any opening brace.
+    UnexpectedClosingBrace {
+        /// The location in the source bvh where the error occurred.
+        span: Span,
+    },
     /// A name could not be found for the `Joint`.
     MissingJointName {
-        /// Line number in the source bvh where the error occurred.
-        line: usize,
+        /// The location in the source bvh where the error occurred.
+        span: Span,
     },
     /// A `CHANNELS` section was encountered in the wrong location.
     UnexpectedChannelsSection {
-        /// Line number in the source bvh where the error occurred.
-        line: usize,
+        /// The location in the source bvh where the error occurred.
+        span: Span,
     },
     /// The number of channels could not be parsed in a `CHANNELS` section.
     ParseNumChannelsError {
         /// The parse error, if there was a malformed string to parse.
         error: Option<LexicalError>,
-        /// Line number in the source bvh where the error occurred.
-        line: usize,
+        /// The location in the source bvh where the error occurred.
+        span: Span,
+    },
+    /// The number of channels in a `CHANNELS` section did not match the
+    /// number of channel names which followed it.
+    ChannelCountMismatch {
+        /// The number of channels declared in the `CHANNELS` section.
+        expected: usize,
+        /// The number of channel names which were found.
+        actual: usize,
+        /// The location in the source bvh where the error occurred.
+        span: Span,
     },
     /// A channel type could not be parsed in the `CHANNELS` section.
     ParseChannelError {
         /// The parse error.
         error: ParseChannelError,
-        /// Line number in the source bvh where the error occurred.
-        line: usize,
+        /// The location in the source bvh where the error occurred.
+        span: Span,
     },
     /// An `OFFSET` section was encountered in the wrong location.
     UnexpectedOffsetSection {
-        /// Line number in the source bvh where the error occurred.
-        line: usize,
+        /// The location in the source bvh where the error occurred.
+        span: Span,
     },
     /// An axis in the `OFFSET` section could not be parsed into a value.
     ParseOffsetError {
@@ -135,15 +314,43 @@ pub enum LoadJointsError {
         parse_float_error: LexicalError,
         /// The axis of the offset which could not be parsed.
         axis: Axis,
-        /// Line number in the source bvh where the error occurred.
-        line: usize,
+        /// The location in the source bvh where the error occurred.
+        span: Span,
     },
     /// An `OFFSET` section was missing an axis in the offset vector.
     MissingOffsetAxis {
         /// The smallest axis which was missing.
         axis: Axis,
-        /// Line number in the source bvh where the error occurred.
-        line: usize,
+        /// The location in the source bvh where the error occurred.
+        span: Span,
+    },
+    /// The hierarchy has more joints than `Limits::max_joints`.
+    TooManyJoints {
+        /// The maximum number of joints.
+        limit: usize,
+        /// The location of the first joint over the limit.
+        span: Span,
+    },
+    /// A joint is nested more deeply than `Limits::max_depth`.
+    HierarchyTooDeep {
+        /// The maximum depth of a joint.
+        limit: usize,
+        /// The location of the first joint over the limit.
+        span: Span,
+    },
+    /// The hierarchy has more channels than `Limits::max_channels`.
+    TooManyChannels {
+        /// The maximum number of channels.
+        limit: usize,
+        /// The location of the first channel over the limit.
+        span: Span,
+    },
+    /// The hierarchy does not match the `Skeleton` which the clip was loaded
+    /// against.
+    SkeletonMismatch {
+        /// The index of the first joint which differs. If one hierarchy is a
+        /// prefix of the other, this is the length of the shorter one.
+        joint: JointIndex,
     },
 }
 
@@ -152,65 +359,132 @@ impl LoadJointsError {
     /// no associated line number.
     #[inline]
     pub fn line(&self) -> Option<usize> {
-        match *self {
-            LoadJointsError::MissingJointName { line }
-            | LoadJointsError::UnexpectedChannelsSection { line }
-            | LoadJointsError::ParseNumChannelsError { line, .. }
-            | LoadJointsError::ParseChannelError { line, .. }
-            | LoadJointsError::UnexpectedOffsetSection { line }
-            | LoadJointsError::ParseOffsetError { line, .. }
-            | LoadJointsError::MissingOffsetAxis { line, .. } => Some(line),
-            _ => None,
-        }
+        self.span().map(|span| span.line)
     }
-}
 
-impl From<io::Error> for LoadJointsError {
+    /// Get the location in the source where the error occurred, or `None` if
+    /// there is no associated location.
     #[inline]
-    fn from(e: io::Error) -> Self {
-        LoadJointsError::Io(e)
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            LoadJointsError::Io(_) | LoadJointsError::SkeletonMismatch { .. } => None,
+            LoadJointsError::MissingRoot { span }
+            | LoadJointsError::UnexpectedHierarchySection { span }
+            | LoadJointsError::UnexpectedRoot { span }
+            | LoadJointsError::UnexpectedJoint { span }
+            | LoadJointsError::UnexpectedEndSite { span }
+            | LoadJointsError::MalformedEndSite { span }
+            | LoadJointsError::UnexpectedOpeningBrace { span }
+            | LoadJointsError::UnexpectedClosingBrace { span }
+            | LoadJointsError::MissingJointName { span }
+            | LoadJointsError::UnexpectedChannelsSection { span }
+            | LoadJointsError::ParseNumChannelsError { span, .. }
+            | LoadJointsError::ChannelCountMismatch { span, .. }
+            | LoadJointsError::ParseChannelError { span, .. }
+            | LoadJointsError::UnexpectedOffsetSection { span }
+            | LoadJointsError::ParseOffsetError { span, .. }
+            | LoadJointsError::MissingOffsetAxis { span, .. }
+            | LoadJointsError::TooManyJoints { span, .. }
+            | LoadJointsError::HierarchyTooDeep { span, .. }
+            | LoadJointsError::TooManyChannels { span, .. } => Some(span),
+        }
     }
-}
 
-impl fmt::Display for LoadJointsError {
-    #[inline]
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    /// Write the error message, without the location prefix.
+    fn fmt_message(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
             LoadJointsError::Io(ref e) => fmt::Display::fmt(&e, f),
-            LoadJointsError::MissingRoot => f.write_str("The root heirarchy could not be found"),
-            LoadJointsError::MissingJointName { line } => {
-                write!(f, "{}: the name is missing from the joints section", line)
+            LoadJointsError::MissingRoot { .. } => {
+                f.write_str("the root heirarchy could not be found")
             }
-            LoadJointsError::UnexpectedChannelsSection { line } => write!(
-                f,
-                "{}: unexpectedly encountered a \"CHANNELS\" section",
-                line
-            ),
-            LoadJointsError::ParseNumChannelsError { ref error, line } => match error {
-                Some(ref e) => write!(f, "{}: could not parse the number of channels: {}", line, e),
-                None => write!(f, "{}: could not find the number of channels", line),
-            },
-            LoadJointsError::ParseChannelError { ref error, line } => {
-                write!(f, "{}: could not parse channel: {}", line, error)
+            LoadJointsError::UnexpectedHierarchySection { .. } => {
+                f.write_str("unexpectedly encountered a \"HIERARCHY\" section")
+            }
+            LoadJointsError::UnexpectedRoot { .. } => {
+                f.write_str("unexpectedly encountered a \"ROOT\" joint")
+            }
+            LoadJointsError::UnexpectedJoint { .. } => {
+                f.write_str("unexpectedly encountered a \"JOINT\"")
+            }
+            LoadJointsError::UnexpectedEndSite { .. } => {
+                f.write_str("unexpectedly encountered an \"End Site\" section")
+            }
+            LoadJointsError::MalformedEndSite { .. } => {
+                f.write_str("expected \"Site\" after the \"End\" keyword")
+            }
+            LoadJointsError::UnexpectedOpeningBrace { .. } => {
+                f.write_str("unexpectedly encountered an opening brace")
+            }
+            LoadJointsError::UnexpectedClosingBrace { .. } => {
+                f.write_str("encountered an unmatched closing brace")
+            }
+            LoadJointsError::MissingJointName { .. } => {
+                f.write_str("the name is missing from the joints section")
             }
-            LoadJointsError::UnexpectedOffsetSection { line } => write!(
+            LoadJointsError::UnexpectedChannelsSection { .. } => {
+                f.write_str("unexpectedly encountered a \"CHANNELS\" section")
+            }
+            LoadJointsError::ParseNumChannelsError { ref error, .. } => match error {
+                Some(ref e) => write!(f, "could not parse the number of channels: {}", e),
+                None => f.write_str("could not find the number of channels"),
+            },
+            LoadJointsError::ChannelCountMismatch {
+                expected, actual, ..
+            } => write!(
                 f,
-                "{}: unexpectedly encountered an \"OFFSET\" section",
-                line
+                "expected {} channels, found {} channels",
+                expected, actual
             ),
+            LoadJointsError::ParseChannelError { ref error, .. } => {
+                write!(f, "could not parse channel: {}", error)
+            }
+            LoadJointsError::UnexpectedOffsetSection { .. } => {
+                f.write_str("unexpectedly encountered an \"OFFSET\" section")
+            }
             LoadJointsError::ParseOffsetError {
                 ref parse_float_error,
                 axis,
-                line,
+                ..
             } => write!(
                 f,
-                "{}: could not parse the {}-axis offset: {}",
-                line, axis, parse_float_error
+                "could not parse the {}-axis offset: {}",
+                axis, parse_float_error
             ),
-            LoadJointsError::MissingOffsetAxis { axis, line } => {
-                write!(f, "{}: the {}-axis offset value is missing", line, axis)
+            LoadJointsError::MissingOffsetAxis { axis, .. } => {
+                write!(f, "the {}-axis offset value is missing", axis)
+            }
+            LoadJointsError::TooManyJoints { limit, .. } => {
+                write!(f, "the hierarchy has more than the limit of {} joints", limit)
+            }
+            LoadJointsError::HierarchyTooDeep { limit, .. } => {
+                write!(f, "the joint is nested deeper than the limit of {}", limit)
             }
+            LoadJointsError::TooManyChannels { limit, .. } => {
+                write!(f, "the hierarchy has more than the limit of {} channels", limit)
+            }
+            LoadJointsError::SkeletonMismatch { joint } => write!(
+                f,
+                "the hierarchy does not match the skeleton at joint {}",
+                joint
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for LoadJointsError {
+    #[inline]
+    fn from(e: io::Error) -> Self {
+        LoadJointsError::Io(e)
+    }
+}
+
+impl fmt::Display for LoadJointsError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(span) = self.span() {
+            write!(f, "{}: ", span)?;
         }
+        self.fmt_message(f)
     }
 }
 
@@ -240,22 +514,22 @@ pub enum LoadMotionError {
     Io(io::Error),
     /// The `MOTION` section is missing in the bvh.
     MissingMotionSection {
-        /// The line where the error occurred.
-        line: usize,
+        /// The location in the source bvh where the error occurred.
+        span: Span,
     },
     /// The "Number of Frames" section could not be parsed in the bvh.
     MissingNumFrames {
         /// The parse error, or `None` if there was no number to be parsed.
         parse_error: Option<LexicalError>,
-        /// The line where the error occurred.
-        line: usize,
+        /// The location in the source bvh where the error occurred.
+        span: Span,
     },
     /// The "Frame Time" section could not be parsed in the bvh.
     MissingFrameTime {
         /// The parse error, or `None` if there was no number to be parsed.
         parse_error: Option<LexicalError>,
-        /// The line where the error occurred.
-        line: usize,
+        /// The location in the source bvh where the error occurred.
+        span: Span,
     },
     /// The motion values section could not be parsed in the bvh.
     ParseMotionSection {
@@ -263,8 +537,17 @@ pub enum LoadMotionError {
         parse_error: LexicalError,
         /// The index of the motion value where the error occurred.
         channel_index: usize,
-        /// The line where the error occurred.
-        line: usize,
+        /// The location in the source bvh where the error occurred.
+        span: Span,
+    },
+    /// A frame did not contain one motion value for each channel.
+    FrameLengthMismatch {
+        /// The number of channels in the skeleton.
+        expected: usize,
+        /// The number of motion values which were found.
+        actual: usize,
+        /// The location in the source bvh where the error occurred.
+        span: Span,
     },
     /// There was a discrepancy between the number of motion values promised
     /// by the file and the actual amount.
@@ -277,69 +560,122 @@ pub enum LoadMotionError {
         expected_num_frames: usize,
         /// Expected number of clips.
         expected_num_clips: usize,
+        /// The location of the number of frames in the source bvh.
+        span: Span,
+    },
+    /// The motion has more frames than `Limits::max_frames`.
+    TooManyFrames {
+        /// The maximum number of frames.
+        limit: usize,
+        /// The location in the source bvh where the limit was exceeded.
+        span: Span,
+    },
+    /// The motion has more values than `Limits::max_motion_values`, or than
+    /// can be addressed in memory.
+    TooManyMotionValues {
+        /// The maximum number of motion values.
+        limit: usize,
+        /// The location in the source bvh where the limit was exceeded.
+        span: Span,
     },
 }
 
 impl LoadMotionError {
     /// Get the line where the error occurred, or `None` if there is
     /// no associated line number.
+    #[inline]
     pub fn line(&self) -> Option<usize> {
+        self.span().map(|span| span.line)
+    }
+
+    /// Get the location in the source where the error occurred, or `None` if
+    /// there is no associated location.
+    #[inline]
+    pub fn span(&self) -> Option<Span> {
         match *self {
-            LoadMotionError::MissingMotionSection { line }
-            | LoadMotionError::MissingNumFrames { line, .. }
-            | LoadMotionError::MissingFrameTime { line, .. }
-            | LoadMotionError::ParseMotionSection { line, .. } => Some(line),
-            _ => None,
+            LoadMotionError::Io(_) => None,
+            LoadMotionError::MissingMotionSection { span }
+            | LoadMotionError::MissingNumFrames { span, .. }
+            | LoadMotionError::MissingFrameTime { span, .. }
+            | LoadMotionError::ParseMotionSection { span, .. }
+            | LoadMotionError::FrameLengthMismatch { span, .. }
+            | LoadMotionError::MotionCountMismatch { span, .. }
+            | LoadMotionError::TooManyFrames { span, .. }
+            | LoadMotionError::TooManyMotionValues { span, .. } => Some(span),
         }
     }
-}
 
-impl fmt::Display for LoadMotionError {
-    fn fmt(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
+    /// Write the error message, without the location prefix.
+    fn fmt_message(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
-            LoadMotionError::Io(ref e) => {
-                fmt::Display::fmt(e, fmtr)
-            }
-            LoadMotionError::MissingMotionSection {
-                line
-            } => {
-                write!(fmtr, "{}: {}", line, self.description())
-            }
-            LoadMotionError::MissingNumFrames { ref parse_error, line } => {
-                if let Some(ref e) = parse_error {
-                    write!(fmtr, "{}: could not parse the num frames value: {}", line, e)
-                } else {
-                    write!(fmtr, "{}: {}", line, self.description())
-                }
-            }
-            LoadMotionError::MissingFrameTime { ref parse_error, line } => {
-                if let Some(ref e) = parse_error {
-                    write!(fmtr, "{}: could not parse the frame time: {}", line, e)
-                } else {
-                    write!(fmtr, "{}: {}", line, self.description())
-                }
-            }
-            LoadMotionError::ParseMotionSection { ref parse_error, line, .. } => {
-                write!(fmtr, "{}: {} ({})", line, self.description(), parse_error)
+            LoadMotionError::Io(ref e) => fmt::Display::fmt(e, fmtr),
+            LoadMotionError::MissingMotionSection { .. } => {
+                fmtr.write_str("the 'MOTION' section of the bvh file is missing")
             }
-            LoadMotionError::MotionCountMismatch  {
+            LoadMotionError::MissingNumFrames {
+                ref parse_error, ..
+            } => match parse_error {
+                Some(ref e) => write!(fmtr, "could not parse the num frames value: {}", e),
+                None => fmtr.write_str("the number of frames section is missing from the bvh file"),
+            },
+            LoadMotionError::MissingFrameTime {
+                ref parse_error, ..
+            } => match parse_error {
+                Some(ref e) => write!(fmtr, "could not parse the frame time: {}", e),
+                None => fmtr.write_str("the frame time is missing from the bvh file"),
+            },
+            LoadMotionError::ParseMotionSection {
+                ref parse_error,
+                channel_index,
+                ..
+            } => write!(
+                fmtr,
+                "could not parse the motion value for channel {} ({})",
+                channel_index, parse_error
+            ),
+            LoadMotionError::FrameLengthMismatch {
+                expected, actual, ..
+            } => write!(
+                fmtr,
+                "expected {} motion values in the frame, found {} values",
+                expected, actual
+            ),
+            LoadMotionError::MotionCountMismatch {
                 actual_total_motion_values,
                 expected_total_motion_values,
                 expected_num_frames,
                 expected_num_clips,
-            } => {
-                write!(
-                    fmtr,
-                    "expected to find {} motion values, found {} values (num frames = {}, num clips = {})",
-                    expected_total_motion_values,
-                    actual_total_motion_values,
-                    expected_num_frames,
-                    expected_num_clips)
+                ..
+            } => write!(
+                fmtr,
+                "expected to find {} motion values, found {} values (num frames = {}, num clips = {})",
+                expected_total_motion_values,
+                actual_total_motion_values,
+                expected_num_frames,
+                expected_num_clips
+            ),
+            LoadMotionError::TooManyFrames { limit, .. } => {
+                write!(fmtr, "the motion has more than the limit of {} frames", limit)
             }
+            LoadMotionError::TooManyMotionValues { limit, .. } => write!(
+                fmtr,
+                "the motion has more than the limit of {} motion values",
+                limit
+            ),
         }
     }
 }
 
+impl fmt::Display for LoadMotionError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(span) = self.span() {
+            write!(fmtr, "{}: ", span)?;
+        }
+        self.fmt_message(fmtr)
+    }
+}
+
 impl StdError for LoadMotionError {
     #[inline]
     fn description(&self) -> &str {
@@ -355,7 +691,10 @@ impl StdError for LoadMotionError {
                 "the frame time is missing from the bvh file"
             }
             LoadMotionError::ParseMotionSection { .. } => "could not parse the motion value",
+            LoadMotionError::FrameLengthMismatch { .. } => "unexpected number of values in frame",
             LoadMotionError::MotionCountMismatch { .. } => "unexpected number of motion values",
+            LoadMotionError::TooManyFrames { .. } => "too many frames",
+            LoadMotionError::TooManyMotionValues { .. } => "too many motion values",
         }
     }
 
@@ -420,6 +759,95 @@ impl StdError for SetMotionError<'_> {
     }
 }
 
+/// An error which may occur when editing the joint hierarchy of a `Bvh`.
+///
+/// The `Bvh` is left unchanged when an edit fails.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EditJointsError {
+    /// The joint index was out of bounds.
+    BadJoint(JointIndex),
+    /// The joint cannot be moved underneath itself or one of its descendants.
+    WouldCreateCycle {
+        /// The joint which was being moved.
+        joint: JointIndex,
+        /// The requested new parent of the joint.
+        new_parent: JointIndex,
+    },
+    /// A rotation cannot be converted for the joint because it does not have
+    /// exactly one rotation channel about each axis.
+    ///
+    /// This is needed to merge the rotation of a removed parent into the joint,
+    /// or to change the order of its rotation channels.
+    UnsupportedRotationChannels {
+        /// The joint.
+        joint: JointIndex,
+    },
+    /// The joint does not have a channel of the given type.
+    MissingChannel {
+        /// The joint.
+        joint: JointIndex,
+        /// The type of the channel.
+        channel_type: ChannelType,
+    },
+    /// The joint would have more than one channel of the given type.
+    DuplicateChannel {
+        /// The joint.
+        joint: JointIndex,
+        /// The type of the channel.
+        channel_type: ChannelType,
+    },
+}
+
+impl fmt::Display for EditJointsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            EditJointsError::BadJoint(joint) => write!(f, "joint {} was out of bounds", joint),
+            EditJointsError::WouldCreateCycle { joint, new_parent } => write!(
+                f,
+                "joint {} cannot be made a child of joint {}, which is in its own subtree",
+                joint, new_parent
+            ),
+            EditJointsError::UnsupportedRotationChannels { joint } => write!(
+                f,
+                "joint {} must have one rotation channel about each axis to convert its rotation",
+                joint
+            ),
+            EditJointsError::MissingChannel {
+                joint,
+                channel_type,
+            } => write!(f, "joint {} does not have a {} channel", joint, channel_type),
+            EditJointsError::DuplicateChannel {
+                joint,
+                channel_type,
+            } => write!(f, "joint {} would have more than one {} channel", joint, channel_type),
+        }
+    }
+}
+
+impl StdError for EditJointsError {}
+
+/// An error which occurs when a `Motion` is paired with a `Skeleton` which has
+/// a different number of channels.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MismatchedChannelsError {
+    /// The number of channels of the `Skeleton`.
+    pub skeleton_channels: usize,
+    /// The number of values in each frame of the `Motion`.
+    pub motion_channels: usize,
+}
+
+impl fmt::Display for MismatchedChannelsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the skeleton has {} channels, but the motion has {} values in each frame",
+            self.skeleton_channels, self.motion_channels
+        )
+    }
+}
+
+impl StdError for MismatchedChannelsError {}
+
 /// Represents an error which may occur when attempting to parse a
 /// `BString` into a `ChannelType`.
 #[derive(Debug)]
@@ -12,13 +12,13 @@
 //! [`Bvh::from_ffi`]: struct.Bvh.html#method.from_ffi
 //! [`Bvh::into_ffi`]: struct.Bvh.html#method.into_ffi
 
-use bstr::BStr;
-use cfile::CFile;
+use bstr::{BStr, ByteSlice};
+use cfile::CFileRef;
 use crate::{
-    duation_to_fractional_seconds, fraction_seconds_to_duration, frames_iter_logic,
-    joint::JointPrivateData, Bvh, Channel, ChannelType, JointData, JointName,
+    frames_iter_logic, hierarchy::ChildLists, Bvh, Channel, ChannelType, Float, FrameRate, Joint,
+    JointIndex, JointName, Motion, MotionLayout, Skeleton,
 };
-use foreign_types::ForeignType;
+use foreign_types::ForeignTypeRef;
 use libc::{c_char, c_double, c_float, c_int, c_void, size_t, strlen, uint32_t, uint8_t, FILE};
 use mint::Vector3;
 use pkg_version::{pkg_version_major, pkg_version_minor, pkg_version_patch};
@@ -32,6 +32,7 @@ use std::{
     mem,
     ptr::{self, NonNull},
     slice,
+    sync::Arc,
 };
 
 /// Type alias for a function used to allocate memory.
@@ -181,7 +182,7 @@ impl bvh_AllocCallbacks {
         }
 
         if self.is_rust_allocator() {
-            JointName::from(CString::from_raw(cstr))
+            JointName::from(CString::from_raw(cstr).into_bytes())
         } else {
             let len = strlen(cstr) + 1;
             let bytes = self.alloc_to_vec(cstr as *mut u8, len);
@@ -460,7 +461,8 @@ pub unsafe extern "C" fn bvh_read_with_allocator(
     joint_alloc_callbacks: *const bvh_AllocCallbacks,
 ) -> c_int {
     let cfile = match NonNull::new(bvh_file) {
-        Some(f) => BufReader::new(CFile::from_ptr(f.as_ptr())),
+        // Borrow the stream, so that it is not closed when the reader is dropped.
+        Some(f) => BufReader::new(CFileRef::from_ptr_mut(f.as_ptr())),
         None => return 0,
     };
 
@@ -700,18 +702,59 @@ pub unsafe extern "C" fn bvh_get_frame(
         .unwrap_or(ptr::null_mut())
 }
 
-impl From<Vector3<f32>> for bvh_Offset {
+/// Get the indices of the children of the joint at `joint_index` in `bvh_file`,
+/// in the order in which they appear in the file.
+///
+/// Writes up to `max_children` indices to `out_children`, and returns the total
+/// number of children, which may be greater than `max_children`. Call this with
+/// a `max_children` of `0` to get the number of children.
+///
+/// If `bvh_file` is `NULL`, or `joint_index` >= `bvh_file::bvh_num_joints`,
+/// then this will return `0`.
+#[no_mangle]
+pub unsafe extern "C" fn bvh_get_joint_children(
+    bvh_file: *const bvh_BvhFile,
+    joint_index: size_t,
+    out_children: *mut size_t,
+    max_children: size_t,
+) -> size_t {
+    if bvh_file.is_null() {
+        return 0;
+    }
+
+    let joints = ptr_to_array((*bvh_file).bvh_joints, (*bvh_file).bvh_num_joints);
+    if joint_index >= joints.len() {
+        return 0;
+    }
+
+    // The root joint has a parent index of `SIZE_MAX`.
+    let parents = joints
+        .iter()
+        .map(|joint| Some(joint.joint_parent_index).filter(|&parent| parent < joints.len()));
+    let child_lists = ChildLists::from_parents(parents);
+    let children = child_lists.children(JointIndex::new(joint_index));
+
+    if !out_children.is_null() {
+        for (i, child) in children.iter().take(max_children).enumerate() {
+            *out_children.add(i) = child.get();
+        }
+    }
+
+    children.len()
+}
+
+impl From<Vector3<Float>> for bvh_Offset {
     #[inline]
-    fn from(v: Vector3<f32>) -> Self {
+    fn from(v: Vector3<Float>) -> Self {
         bvh_Offset {
-            offset_x: v.x,
-            offset_y: v.y,
-            offset_z: v.z,
+            offset_x: v.x as c_float,
+            offset_y: v.y as c_float,
+            offset_z: v.z as c_float,
         }
     }
 }
 
-impl From<bvh_Offset> for Vector3<f32> {
+impl From<bvh_Offset> for Vector3<Float> {
     #[inline]
     fn from(offset: bvh_Offset) -> Self {
         let crate::ffi::bvh_Offset {
@@ -719,7 +762,7 @@ impl From<bvh_Offset> for Vector3<f32> {
             offset_y,
             offset_z,
         } = offset;
-        [offset_x, offset_y, offset_z].into()
+        [offset_x as Float, offset_y as Float, offset_z as Float].into()
     }
 }
 
@@ -786,78 +829,73 @@ impl Bvh {
     /// In addition, this method will take ownership of memory which was
     /// owned by `bvh`, which may cause corruption if there are still
     /// references to `bvh`'s data.
+    ///
+    /// If an error is returned, the joints of `bvh` are inconsistent, and no
+    /// memory has been taken from `bvh`, so it must still be destroyed.
     pub unsafe fn from_ffi(bvh: bvh_BvhFile) -> Result<Self, ()> {
-        // @TODO(burtonageo): Massive error checking/consistency checking required here
         // @TODO(burtonageo): Check custom allocators
-        let joints = if bvh.bvh_num_joints == 0 {
-            Vec::new()
-        } else {
-            let mut out_joints = Vec::with_capacity(bvh.bvh_num_joints);
+        let ffi_joints = ptr_to_array(bvh.bvh_joints, bvh.bvh_num_joints);
+
+        // Check everything before taking ownership of any of the memory, so that
+        // `bvh` is still valid if an error is returned. The parent of each joint
+        // must come before it, and can't have an end site.
+        for (i, ffi_joint) in ffi_joints.iter().enumerate() {
+            let parent = ffi_joint.joint_parent_index;
+            if parent != usize::max_value()
+                && (parent >= i || ffi_joints[parent].joint_has_end_site != 0)
             {
-                let ffi_root = &*bvh.bvh_joints.offset(0);
-
-                let channels = Box::from_raw(slice::from_raw_parts_mut(
-                    ffi_root.joint_channels,
-                    ffi_root.joint_num_channels,
-                ));
-
-                let root = JointData::Root {
-                    name: CString::from_raw(ffi_root.joint_name).into(),
-                    offset: ffi_root.joint_offset.into(),
-                    channels: Vec::from(channels).into_iter().map(Into::into).collect(),
-                };
-
-                out_joints.push(root);
+                return Err(());
             }
+        }
+        let num_channels = ffi_joints
+            .iter()
+            .map(|joint| joint.joint_num_channels)
+            .sum::<usize>();
+        if num_channels != bvh.bvh_num_channels {
+            return Err(());
+        }
 
-            for i in 1..bvh.bvh_num_joints {
-                let signed_i = match isize::try_from(i) {
-                    Ok(i) => i,
-                    Err(_) => continue,
-                };
-                let ffi_joint = &*bvh.bvh_joints.offset(signed_i);
-
-                let channels = Box::from_raw(slice::from_raw_parts_mut(
-                    ffi_joint.joint_channels,
-                    ffi_joint.joint_num_channels,
-                ));
-
-                let joint = JointData::Child {
-                    name: CString::from_raw(ffi_joint.joint_name).into(),
-                    offset: ffi_joint.joint_offset.into(),
-                    channels: Vec::from(channels).into_iter().map(Into::into).collect(),
-                    end_site_offset: if ffi_joint.joint_has_end_site == 1 {
-                        Some(ffi_joint.joint_end_site.into())
-                    } else {
-                        None
-                    },
-                    private: JointPrivateData {
-                        self_index: i,
-                        parent_index: ffi_joint.joint_parent_index,
-                        depth: ffi_joint.joint_depth,
-                    },
-                };
+        let mut joints = Vec::with_capacity(bvh.bvh_num_joints);
+        for ffi_joint in ffi_joints {
 
-                out_joints.push(joint);
-            }
+            let channels = Box::from_raw(slice::from_raw_parts_mut(
+                ffi_joint.joint_channels,
+                ffi_joint.joint_num_channels,
+            ));
 
-            out_joints
-        };
+            let parent_index = if ffi_joint.joint_parent_index == usize::max_value() {
+                None
+            } else {
+                Some(ffi_joint.joint_parent_index)
+            };
+
+            joints.push(Joint {
+                name: JointName::from(CString::from_raw(ffi_joint.joint_name).into_bytes()),
+                offset: ffi_joint.joint_offset.into(),
+                channels: Vec::from(channels).into_iter().map(Into::into).collect(),
+                end_site: if ffi_joint.joint_has_end_site != 0 {
+                    Some(ffi_joint.joint_end_site.into())
+                } else {
+                    None
+                },
+                parent_index,
+                depth: ffi_joint.joint_depth,
+            });
+        }
 
         let motion_values = Box::from_raw(slice::from_raw_parts_mut(
             bvh.bvh_motion_data,
             bvh.bvh_num_channels * bvh.bvh_num_frames,
         ));
 
-        let out_bvh = Bvh {
-            joints,
-            motion_values: motion_values.into(),
-            num_channels: bvh.bvh_num_channels,
-            num_frames: bvh.bvh_num_frames,
-            frame_time: fraction_seconds_to_duration(bvh.bvh_frame_time),
-        };
+        let skeleton = Skeleton::from_joints(joints, num_channels);
 
-        Ok(out_bvh)
+        let frame_rate = FrameRate::from_secs_f64(bvh.bvh_frame_time);
+        let mut motion = Motion::new(bvh.bvh_num_channels, frame_rate);
+        motion.values = Vec::from(motion_values).into_iter().map(|v| v as Float).collect();
+        motion.num_frames = bvh.bvh_num_frames;
+
+        Bvh::from_parts(Arc::new(skeleton), motion).map_err(|_| ())
     }
 
     #[allow(unused)]
@@ -891,7 +929,7 @@ impl Bvh {
     /// If both allocators are the default allocators, this method will use the rust
     /// allocator, and will move the pointers over without copying them.
     pub fn into_ffi_with_allocator(
-        mut self,
+        self,
         bvh_allocator: &bvh_AllocCallbacks,
         joints_allocator: &bvh_AllocCallbacks,
     ) -> Result<bvh_BvhFile, ()> {
@@ -901,28 +939,31 @@ impl Bvh {
                 _ => return Err(()),
             };
 
-        let mut out_bvh = bvh_BvhFile::default();
-        out_bvh.bvh_num_joints = self.joints.len();
+        let (skeleton, mut motion) = self.into_parts();
+        motion.set_layout(MotionLayout::FrameMajor);
 
-        let joints = mem::replace(&mut self.joints, Vec::new());
+        let mut out_bvh = bvh_BvhFile::default();
+        out_bvh.bvh_num_joints = skeleton.num_joints();
 
-        let out_joints = joints
-            .into_iter()
+        let out_joints = skeleton
+            .joints()
             .map(|joint| {
                 let channels = joint
-                    .channels()
+                    .channels
                     .iter()
                     .map(|&c| c.into())
                     .collect::<Vec<bvh_Channel>>();
 
                 bvh_Joint {
-                    joint_name: unsafe { joints_allocator.joint_name_to_cstring(joint.name()) },
+                    joint_name: unsafe {
+                        joints_allocator.joint_name_to_cstring(joint.name.as_bstr())
+                    },
                     joint_num_channels: channels.len(),
                     joint_channels: unsafe { joints_allocator.copy_vec_to_alloc(channels) },
                     joint_parent_index: joint.parent_index().unwrap_or(usize::max_value()),
                     joint_depth: joint.depth(),
-                    joint_offset: (*joint.offset()).into(),
-                    joint_end_site: joint.end_site().map(|&e| e.into()).unwrap_or_default(),
+                    joint_offset: joint.offset.into(),
+                    joint_end_site: joint.end_site().map(Into::into).unwrap_or_default(),
                     joint_has_end_site: if joint.has_end_site() { 1 } else { 0 },
                 }
             })
@@ -930,10 +971,11 @@ impl Bvh {
 
         out_bvh.bvh_joints = unsafe { bvh_allocator.copy_vec_to_alloc(out_joints) };
 
-        out_bvh.bvh_frame_time = duation_to_fractional_seconds(self.frame_time());
-        out_bvh.bvh_motion_data = unsafe { bvh_allocator.copy_vec_to_alloc(self.motion_values) };
-        out_bvh.bvh_num_channels = self.num_channels;
-        out_bvh.bvh_num_frames = self.num_frames;
+        out_bvh.bvh_frame_time = motion.frame_rate().frame_time_secs();
+        out_bvh.bvh_num_channels = motion.num_channels();
+        out_bvh.bvh_num_frames = motion.num_frames();
+        let motion_data = motion.values.into_iter().map(|v| v as c_float).collect();
+        out_bvh.bvh_motion_data = unsafe { bvh_allocator.copy_vec_to_alloc(motion_data) };
 
         Ok(out_bvh)
     }
@@ -1023,12 +1065,15 @@ fn ptr_to_array<'a, T>(data: *mut T, size: libc::size_t) -> &'a [T] {
 
 #[cfg(test)]
 mod tests {
-    use crate::ffi::{
-        bvh_AllocCallbacks, bvh_BvhFile, bvh_ChannelType, bvh_Offset, bvh_destroy, bvh_get_frame,
-        bvh_parse,
+    use crate::{
+        ffi::{
+            bvh_AllocCallbacks, bvh_BvhFile, bvh_ChannelType, bvh_Offset, bvh_destroy,
+            bvh_get_frame, bvh_get_joint_children, bvh_parse,
+        },
+        Bvh, FrameRate,
     };
     use libc::strcmp;
-    use std::ffi::CStr;
+    use std::{ffi::CStr, ptr};
 
     fn check_ffi_bvh(mut bvh_ffi: bvh_BvhFile) {
         assert_eq!(bvh_ffi.bvh_num_joints, 2);
@@ -1098,7 +1143,7 @@ mod tests {
             assert_eq!(end.joint_end_site, expected_end_site);
         }
 
-        assert_eq!(bvh_ffi.bvh_frame_time, 0.033333333);
+        assert_eq!(bvh_ffi.bvh_frame_time, 1.0 / 30.0);
         for i in 0..bvh_ffi.bvh_num_frames {
             let frame = unsafe { bvh_get_frame(&mut bvh_ffi, i) };
             for j in 0..bvh_ffi.bvh_num_channels {
@@ -1192,6 +1237,81 @@ mod tests {
         }
     }
 
+    #[test]
+    fn ffi_round_trip() {
+        let bvh = bvh! {
+            HIERARCHY
+            ROOT Base
+            {
+                OFFSET 0.0 0.0 0.0
+                CHANNELS 3 Xposition Yposition Zposition
+                JOINT Left
+                {
+                    OFFSET 1.0 0.0 0.0
+                    CHANNELS 1 Zrotation
+                    End Site
+                    {
+                        OFFSET 0.0 2.0 0.0
+                    }
+                }
+                JOINT Right
+                {
+                    OFFSET -1.0 0.0 0.0
+                    CHANNELS 1 Zrotation
+                    End Site
+                    {
+                        OFFSET 0.0 -2.0 0.0
+                    }
+                }
+            }
+
+            MOTION
+            Frames: 2
+            Frame Time: 0.0333667
+            0.0 1.0 2.0 3.0 4.0
+            5.0 6.0 7.0 8.0 9.0
+        };
+
+        let bvh_ffi = bvh.clone().into_ffi();
+        unsafe {
+            assert_eq!((*bvh_ffi.bvh_joints.offset(0)).joint_has_end_site, 0);
+            assert_eq!((*bvh_ffi.bvh_joints.offset(2)).joint_has_end_site, 1);
+
+            let mut children = [0; 2];
+            let mut children_of = |index| {
+                bvh_get_joint_children(&bvh_ffi, index, children.as_mut_ptr(), children.len())
+            };
+            assert_eq!(children_of(0), 2);
+            assert_eq!(children_of(2), 0);
+            assert_eq!(children_of(3), 0);
+            assert_eq!(children, [1, 2]);
+            assert_eq!(bvh_get_joint_children(&bvh_ffi, 0, ptr::null_mut(), 0), 2);
+        }
+
+        let round_trip = unsafe { Bvh::from_ffi(bvh_ffi) }.unwrap();
+        assert_eq!(round_trip, bvh);
+        assert_eq!(round_trip.frame_rate(), FrameRate::FPS_29_97);
+
+        // An inconsistent file is rejected without taking any of its memory.
+        let mut bad_parent = bvh.clone().into_ffi();
+        let mut bad_channels = bvh.into_ffi();
+        unsafe {
+            (*bad_parent.bvh_joints.offset(1)).joint_parent_index = 2;
+            bad_channels.bvh_num_channels = 4;
+
+            assert!(Bvh::from_ffi(bad_parent).is_err());
+            assert!(Bvh::from_ffi(bad_channels).is_err());
+
+            let name = CStr::from_ptr((*bad_parent.bvh_joints.offset(1)).joint_name);
+            assert_eq!(name.to_bytes(), b"Left");
+
+            // Destroying frees the motion values by their count, so restore it.
+            bad_channels.bvh_num_channels = 5;
+            assert_ne!(bvh_destroy(&mut bad_parent), 0);
+            assert_ne!(bvh_destroy(&mut bad_channels), 0);
+        }
+    }
+
     #[test]
     fn default_alloc_is_rust_allocator() {
         let alloc = bvh_AllocCallbacks::default();
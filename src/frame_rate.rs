@@ -0,0 +1,315 @@
+use std::{convert::TryFrom, fmt, time::Duration};
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// The most decimal places of a frame time which are kept exactly.
+const MAX_DECIMAL_PLACES: u32 = 18;
+
+/// The frame rate of an animation, stored exactly as `frames` frames every
+/// `seconds` seconds.
+///
+/// Frame times in `bvh` files are written as decimals such as `0.0333333`, which
+/// can't represent the common frame rates exactly. When a frame time is parsed, it
+/// is snapped to the nearest of 24, 25, 29.97, 30, 60 or 120 fps if it is within
+/// one unit of the last written digit of that rate, and otherwise is kept as the
+/// exact decimal which was written.
+///
+/// A frame rate with a frame time of zero is used when the frame time is not
+/// known, and is the default.
+///
+/// # Examples
+///
+/// ```
+/// # use bvh_anim::FrameRate;
+/// # use std::time::Duration;
+/// let ntsc = FrameRate::from_secs_f64(0.0333667);
+/// assert_eq!(ntsc, FrameRate::FPS_29_97);
+/// assert_eq!((ntsc.frames(), ntsc.seconds()), (30000, 1001));
+///
+/// // 30 fps can be snapped from any number of digits.
+/// assert_eq!(FrameRate::from_secs_f64(0.033333333), FrameRate::FPS_30);
+/// assert_eq!(FrameRate::from_secs_f64(0.0333), FrameRate::FPS_30);
+///
+/// // Other frame times are kept exactly.
+/// let rate = FrameRate::from_secs_f64(0.0125);
+/// assert_eq!(rate.fps(), 80.0);
+///
+/// assert_eq!(FrameRate::FPS_30.time_of_frame(30), Duration::from_secs(1));
+/// assert_eq!(FrameRate::FPS_30.frame_at_time(Duration::from_millis(1500)), 45);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct FrameRate {
+    frames: u64,
+    seconds: u64,
+}
+
+impl FrameRate {
+    /// 24 frames per second, used for film.
+    pub const FPS_24: FrameRate = FrameRate::whole(24);
+    /// 25 frames per second, used for PAL video.
+    pub const FPS_25: FrameRate = FrameRate::whole(25);
+    /// 30000 frames every 1001 seconds (29.97 fps), used for NTSC video.
+    pub const FPS_29_97: FrameRate = FrameRate {
+        frames: 30000,
+        seconds: 1001,
+    };
+    /// 30 frames per second.
+    pub const FPS_30: FrameRate = FrameRate::whole(30);
+    /// 60 frames per second.
+    pub const FPS_60: FrameRate = FrameRate::whole(60);
+    /// 120 frames per second, used by many motion capture systems.
+    pub const FPS_120: FrameRate = FrameRate::whole(120);
+
+    /// The frame rates which parsed frame times are snapped to.
+    const STANDARD: [FrameRate; 6] = [
+        FrameRate::FPS_24,
+        FrameRate::FPS_25,
+        FrameRate::FPS_29_97,
+        FrameRate::FPS_30,
+        FrameRate::FPS_60,
+        FrameRate::FPS_120,
+    ];
+
+    /// Create a `FrameRate` of `frames` frames every `seconds` seconds.
+    ///
+    /// Returns `None` if `frames` is `0`. If `seconds` is `0`, the frame time is
+    /// zero.
+    #[inline]
+    pub const fn new(frames: u64, seconds: u64) -> Option<Self> {
+        if frames == 0 {
+            return None;
+        }
+        let divisor = gcd(frames, seconds);
+        Some(FrameRate {
+            frames: frames / divisor,
+            seconds: seconds / divisor,
+        })
+    }
+
+    #[inline]
+    const fn whole(fps: u64) -> Self {
+        FrameRate {
+            frames: fps,
+            seconds: 1,
+        }
+    }
+
+    /// Create a `FrameRate` from a frame time in seconds, snapping it to a
+    /// standard frame rate if it is close to one.
+    ///
+    /// The frame time is treated as the shortest decimal which converts to
+    /// `secs`. Negative and non-finite frame times are treated as zero.
+    pub fn from_secs_f64(secs: f64) -> Self {
+        if !secs.is_finite() || secs <= 0.0 {
+            return FrameRate::default();
+        }
+        // `f64` is displayed as the shortest decimal which round-trips, without
+        // an exponent.
+        FrameRate::from_decimal(secs.to_string().as_bytes()).unwrap_or_default()
+    }
+
+    /// Create a `FrameRate` from the duration of each frame, snapping it to a
+    /// standard frame rate if it is close to one.
+    #[inline]
+    pub fn from_frame_time(frame_time: Duration) -> Self {
+        FrameRate::from_scaled(frame_time.as_nanos(), 9)
+    }
+
+    /// Parse a frame time written as a decimal number of seconds, such as
+    /// `0.0333333`, snapping it to a standard frame rate if it is close to one.
+    ///
+    /// Returns `None` if `text` is not a plain decimal number.
+    pub(crate) fn from_decimal(text: &[u8]) -> Option<Self> {
+        let text = text.strip_prefix(b"+").unwrap_or(text);
+        let (whole, fraction) = match text.iter().position(|&b| b == b'.') {
+            Some(point) => (&text[..point], &text[point + 1..]),
+            None => (text, &b""[..]),
+        };
+        let is_digits = |part: &[u8]| part.iter().all(u8::is_ascii_digit);
+        if whole.len() + fraction.len() == 0 || !is_digits(whole) || !is_digits(fraction) {
+            return None;
+        }
+
+        let places = fraction.len().min(MAX_DECIMAL_PLACES as usize);
+        let mut value = 0u128;
+        for &digit in whole.iter().chain(&fraction[..places]) {
+            value = value.checked_mul(10)?.checked_add(u128::from(digit - b'0'))?;
+        }
+        // Round away the places which can't be kept.
+        if fraction.get(places).is_some_and(|&digit| digit >= b'5') {
+            value += 1;
+        }
+
+        Some(FrameRate::from_scaled(value, places as u32))
+    }
+
+    /// Create a `FrameRate` from a frame time of `value / 10^places` seconds.
+    fn from_scaled(mut value: u128, mut places: u32) -> Self {
+        if value == 0 {
+            return FrameRate::default();
+        }
+
+        if let Some(standard) = FrameRate::snap(value, places) {
+            return standard;
+        }
+
+        loop {
+            let scale = 10u128.pow(places);
+            let divisor = gcd_u128(value, scale);
+            let (seconds, frames) = (value / divisor, scale / divisor);
+            if let (Ok(seconds), Ok(frames)) = (u64::try_from(seconds), u64::try_from(frames)) {
+                return FrameRate { frames, seconds };
+            }
+            if places == 0 {
+                return FrameRate {
+                    frames: 1,
+                    seconds: u64::MAX,
+                };
+            }
+            // Too precise to store, so drop the last place.
+            value = (value + 5) / 10;
+            places -= 1;
+        }
+    }
+
+    /// Find the standard frame rate whose frame time is closest to `value / 10^places`
+    /// seconds, if any is within one unit of the last place.
+    fn snap(value: u128, places: u32) -> Option<Self> {
+        // Too few digits to tell the frame rates apart.
+        if value < 100 {
+            return None;
+        }
+
+        let scale = 10u128.pow(places);
+        FrameRate::STANDARD
+            .iter()
+            .filter_map(|&rate| {
+                // Compare `value` against the exact `scale * seconds / frames`,
+                // scaled up by `frames` to stay in integers.
+                let frames = u128::from(rate.frames);
+                let exact = scale.checked_mul(u128::from(rate.seconds))?;
+                let distance = value.checked_mul(frames)?.abs_diff(exact);
+                if distance < frames {
+                    Some((distance * 1_000_000 / frames, rate))
+                } else {
+                    None
+                }
+            })
+            .min_by_key(|&(distance, _)| distance)
+            .map(|(_, rate)| rate)
+    }
+
+    /// Get the number of frames in every `seconds()` seconds.
+    #[inline]
+    pub const fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Get the number of seconds which it takes to play `frames()` frames.
+    #[inline]
+    pub const fn seconds(&self) -> u64 {
+        self.seconds
+    }
+
+    /// Get the number of frames per second.
+    ///
+    /// This is infinite if the frame time is zero.
+    #[inline]
+    pub fn fps(&self) -> f64 {
+        self.frames as f64 / self.seconds as f64
+    }
+
+    /// Get the duration of each frame in seconds.
+    #[inline]
+    pub fn frame_time_secs(&self) -> f64 {
+        self.seconds as f64 / self.frames as f64
+    }
+
+    /// Get the duration of each frame, rounded to the nearest nanosecond.
+    #[inline]
+    pub fn frame_time(&self) -> Duration {
+        self.time_of_frame(1)
+    }
+
+    /// Get the time at which the frame at `index` starts, rounded to the nearest
+    /// nanosecond.
+    ///
+    /// Unlike adding up the rounded frame time, this does not drift over long
+    /// animations.
+    pub fn time_of_frame(&self, index: usize) -> Duration {
+        let frames = u128::from(self.frames);
+        let nanos = (index as u128)
+            .checked_mul(u128::from(self.seconds) * NANOS_PER_SEC)
+            .map(|total| (total + frames / 2) / frames)
+            .unwrap_or(u128::MAX);
+
+        let secs = nanos / NANOS_PER_SEC;
+        match u64::try_from(secs) {
+            Ok(secs) => Duration::new(secs, (nanos % NANOS_PER_SEC) as u32),
+            Err(_) => Duration::MAX,
+        }
+    }
+
+    /// Get the index of the frame which is shown at `time`.
+    ///
+    /// This is the last frame whose [`time_of_frame`][`FrameRate::time_of_frame`]
+    /// is not after `time`. If the frame time is zero, this is always `0`.
+    ///
+    /// [`FrameRate::time_of_frame`]: #method.time_of_frame
+    pub fn frame_at_time(&self, time: Duration) -> usize {
+        if self.seconds == 0 {
+            return 0;
+        }
+
+        let period = u128::from(self.seconds) * NANOS_PER_SEC;
+        let estimate = time.as_nanos().saturating_mul(u128::from(self.frames)) / period;
+        let mut index = usize::try_from(estimate).unwrap_or(usize::MAX);
+
+        // The start of each frame is rounded, so the estimate may be one frame out.
+        if index > 0 && self.time_of_frame(index) > time {
+            index -= 1;
+        } else if index < usize::MAX && self.time_of_frame(index + 1) <= time {
+            index += 1;
+        }
+        index
+    }
+}
+
+impl Default for FrameRate {
+    /// A frame rate with a frame time of zero.
+    #[inline]
+    fn default() -> Self {
+        FrameRate {
+            frames: 1,
+            seconds: 0,
+        }
+    }
+}
+
+impl fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.seconds == 1 {
+            write!(f, "{} fps", self.frames)
+        } else {
+            write!(f, "{}/{} fps", self.frames, self.seconds)
+        }
+    }
+}
+
+const fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let rem = a % b;
+        a = b;
+        b = rem;
+    }
+    a
+}
+
+fn gcd_u128(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        let rem = a % b;
+        a = b;
+        b = rem;
+    }
+    a
+}
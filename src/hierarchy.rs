@@ -0,0 +1,165 @@
+//! Navigation of the joint hierarchy of a `Bvh`.
+
+use crate::{Joint, JointIndex};
+use std::{collections::VecDeque, iter::FusedIterator, slice};
+
+/// The children of every joint, cached so that they can be found without
+/// scanning all of the joints.
+///
+/// The children of the joint at index `i` are `children[starts[i]..starts[i + 1]]`,
+/// in file order.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ChildLists {
+    starts: Vec<usize>,
+    children: Vec<JointIndex>,
+}
+
+impl ChildLists {
+    #[inline]
+    pub(crate) fn new(joints: &[Joint]) -> Self {
+        Self::from_parents(joints.iter().map(Joint::parent_index))
+    }
+
+    /// Build the child lists from the parent index of every joint, in file order.
+    ///
+    /// Every parent index must be less than the number of joints.
+    pub(crate) fn from_parents<I>(parents: I) -> Self
+    where
+        I: Iterator<Item = Option<usize>> + Clone,
+    {
+        let num_joints = parents.clone().count();
+        let mut starts = vec![0; num_joints + 1];
+        for parent in parents.clone().flatten() {
+            starts[parent + 1] += 1;
+        }
+        for i in 1..starts.len() {
+            starts[i] += starts[i - 1];
+        }
+
+        let mut next = starts.clone();
+        let mut children = vec![JointIndex::new(0); num_joints];
+        for (index, parent) in parents.enumerate() {
+            if let Some(parent) = parent {
+                children[next[parent]] = JointIndex::new(index);
+                next[parent] += 1;
+            }
+        }
+        children.truncate(starts[num_joints]);
+
+        ChildLists { starts, children }
+    }
+
+    #[inline]
+    pub(crate) fn children(&self, index: JointIndex) -> &[JointIndex] {
+        let index = index.get();
+        &self.children[self.starts[index]..self.starts[index + 1]]
+    }
+}
+
+/// An iterator over the direct children of a `Joint`.
+///
+/// Created by [`Bvh::children`][`Bvh::children`].
+///
+/// [`Bvh::children`]: struct.Bvh.html#method.children
+#[derive(Clone, Debug)]
+pub struct Children<'a> {
+    pub(crate) iter: slice::Iter<'a, JointIndex>,
+}
+
+impl Iterator for Children<'_> {
+    type Item = JointIndex;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().copied()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Children<'_> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().copied()
+    }
+}
+
+impl ExactSizeIterator for Children<'_> {}
+
+impl FusedIterator for Children<'_> {}
+
+/// A depth-first iterator over the descendants of a `Joint`.
+///
+/// Created by [`Bvh::descendants`][`Bvh::descendants`].
+///
+/// [`Bvh::descendants`]: struct.Bvh.html#method.descendants
+#[derive(Clone, Debug)]
+pub struct DepthFirst<'a> {
+    pub(crate) child_lists: &'a ChildLists,
+    pub(crate) stack: Vec<JointIndex>,
+}
+
+impl Iterator for DepthFirst<'_> {
+    type Item = JointIndex;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.stack.pop()?;
+        self.stack
+            .extend(self.child_lists.children(index).iter().rev());
+        Some(index)
+    }
+}
+
+impl FusedIterator for DepthFirst<'_> {}
+
+/// A breadth-first iterator over the descendants of a `Joint`.
+///
+/// Created by [`Bvh::descendants_breadth_first`][`Bvh::descendants_breadth_first`].
+///
+/// [`Bvh::descendants_breadth_first`]: struct.Bvh.html#method.descendants_breadth_first
+#[derive(Clone, Debug)]
+pub struct BreadthFirst<'a> {
+    pub(crate) child_lists: &'a ChildLists,
+    pub(crate) queue: VecDeque<JointIndex>,
+}
+
+impl Iterator for BreadthFirst<'_> {
+    type Item = JointIndex;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.queue.pop_front()?;
+        self.queue.extend(self.child_lists.children(index));
+        Some(index)
+    }
+}
+
+impl FusedIterator for BreadthFirst<'_> {}
+
+/// An iterator which walks up the hierarchy from a `Joint` towards its root.
+///
+/// Created by [`Bvh::ancestors`][`Bvh::ancestors`] and
+/// [`Bvh::path_to_root`][`Bvh::path_to_root`].
+///
+/// [`Bvh::ancestors`]: struct.Bvh.html#method.ancestors
+/// [`Bvh::path_to_root`]: struct.Bvh.html#method.path_to_root
+#[derive(Clone, Debug)]
+pub struct Ancestors<'a> {
+    pub(crate) joints: &'a [Joint],
+    pub(crate) next: Option<JointIndex>,
+}
+
+impl Iterator for Ancestors<'_> {
+    type Item = JointIndex;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.next?;
+        self.next = self.joints[index.get()].parent();
+        Some(index)
+    }
+}
+
+impl FusedIterator for Ancestors<'_> {}
@@ -1,25 +1,82 @@
 use bstr::ByteSlice;
-use crate::Channel;
+use crate::{encoding::NameEncoding, Channel, ChannelType, Float, RotationOrder};
 use mint::Vector3;
 use smallvec::SmallVec;
-use std::{fmt, mem};
+use std::{borrow::Cow, fmt, mem, str};
 
 /// An alias for the type used for the `Joint::name`.
 ///
 /// This is a byte string which may be valid `utf8`.
 pub type JointName = SmallVec<[u8; mem::size_of::<String>()]>;
 
+/// The index of a `Joint` in the [`Bvh::joints`][`Bvh::joints`] array.
+///
+/// [`Bvh::joints`]: struct.Bvh.html#method.joints
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct JointIndex(usize);
+
+impl JointIndex {
+    /// Create a new `JointIndex` from the position of a `Joint` in the
+    /// `Bvh::joints` array.
+    #[inline]
+    pub const fn new(index: usize) -> Self {
+        JointIndex(index)
+    }
+
+    /// Get the position of the `Joint` in the `Bvh::joints` array.
+    #[inline]
+    pub const fn get(self) -> usize {
+        self.0
+    }
+}
+
+impl From<usize> for JointIndex {
+    #[inline]
+    fn from(index: usize) -> Self {
+        JointIndex(index)
+    }
+}
+
+impl From<JointIndex> for usize {
+    #[inline]
+    fn from(index: JointIndex) -> Self {
+        index.0
+    }
+}
+
+impl fmt::Display for JointIndex {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
 /// A `Joint` in a bvh skeleton.
+///
+/// An `End Site` is not a `Joint` of its own, and has no `JointIndex`. It is
+/// stored as the [`end_site`][`Joint::end_site`] offset of the joint whose
+/// braces it is written in, which must be a joint with no children. The parser,
+/// the [`bvh!`][`bvh!`] macro, the [`builder`][`builder`] and the `ffi` module all
+/// use this representation.
+///
+/// A file in which a `JOINT` follows the `End Site` of its parent is an error,
+/// unless it is parsed with `ParsePolicy::Lenient`, which drops the end site and
+/// records a `ParseWarning::EndSiteDropped`.
+///
+/// [`Joint::end_site`]: struct.Joint.html#method.end_site
+/// [`bvh!`]: macro.bvh.html
+/// [`builder`]: builder/index.html
 #[derive(Clone, PartialEq)]
 pub struct Joint {
     /// Name of the `Joint`.
     pub name: JointName,
     /// Positional offset of this `Joint` relative to the parent.
-    pub offset: Vector3<f32>,
+    pub offset: Vector3<Float>,
     /// The channels applicable to this `Joint`.
     pub channels: SmallVec<[Channel; 6]>,
-    /// End site offset.
-    pub(crate) end_site: Option<Vector3<f32>>,
+    /// End site offset, relative to this `Joint`. Only a `Joint` without children
+    /// may have an end site.
+    pub(crate) end_site: Option<Vector3<Float>>,
     /// The index of the parent `Joint` in the `Bvh::joints` array.
     pub(crate) parent_index: Option<usize>,
     /// The depth of the `Joint`.
@@ -27,6 +84,48 @@ pub struct Joint {
 }
 
 impl Joint {
+    /// Get the name of the `Joint` as a `str`, if it is valid `utf8`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::Joint;
+    /// let mut joint = Joint::default();
+    /// joint.name = b"Hips"[..].into();
+    /// assert_eq!(joint.name_str(), Ok("Hips"));
+    ///
+    /// joint.name = b"Hanche_\xE9"[..].into();
+    /// assert!(joint.name_str().is_err());
+    /// ```
+    #[inline]
+    pub fn name_str(&self) -> Result<&str, str::Utf8Error> {
+        str::from_utf8(&self.name)
+    }
+
+    /// Get the name of the `Joint` decoded as a string in the given `encoding`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::{encoding::NameEncoding, Joint};
+    /// let mut joint = Joint::default();
+    /// joint.name = b"Hanche_\xE9"[..].into();
+    /// assert_eq!(joint.name_lossy(NameEncoding::Latin1), "Hanche_é");
+    /// assert_eq!(joint.name_lossy(NameEncoding::Utf8), "Hanche_\u{FFFD}");
+    /// ```
+    #[inline]
+    pub fn name_lossy(&self, encoding: NameEncoding) -> Cow<'_, str> {
+        encoding.decode(&self.name)
+    }
+
+    /// Returns `true` if the `Joint` has a channel of type `channel_type`.
+    #[inline]
+    pub fn has_channel(&self, channel_type: ChannelType) -> bool {
+        self.channels
+            .iter()
+            .any(|channel| channel.channel_type() == channel_type)
+    }
+
     /// Returns `true` if the `Joint` is a child `Joint`, or `false` if it isn't.
     ///
     /// # Examples
@@ -58,9 +157,26 @@ impl Joint {
 
     /// Returns `true` if the `Joint` is an end `Joint` (i.e. has an end site),
     /// or `false` if it isn't.
+    ///
+    /// This is the same as [`has_end_site`][`Joint::has_end_site`].
+    ///
+    /// [`Joint::has_end_site`]: #method.has_end_site
     #[inline]
     pub fn is_end_joint(&self) -> bool {
-        self.end_site.is_none()
+        self.has_end_site()
+    }
+
+    /// Returns the order in which the rotation channels of the `Joint` are
+    /// applied, or `None` if it has no rotation channels.
+    #[inline]
+    pub fn rotation_order(&self) -> Option<RotationOrder> {
+        RotationOrder::from_channels(&self.channels)
+    }
+
+    /// Returns `true` if the `Joint` is capped with an `End Site`.
+    #[inline]
+    pub fn has_end_site(&self) -> bool {
+        self.end_site.is_some()
     }
 
     /// Returns the index of the parent `Joint` in the `bvh`, or `None` if this
@@ -70,9 +186,16 @@ impl Joint {
         self.parent_index
     }
 
+    /// Returns the `JointIndex` of the parent `Joint`, or `None` if this is a
+    /// root `Joint`.
+    #[inline]
+    pub fn parent(&self) -> Option<JointIndex> {
+        self.parent_index.map(JointIndex)
+    }
+
     /// Returns the `end_site` of the `Joint` if this is a leaf `Joint`, or `None`.
     #[inline]
-    pub fn end_site(&self) -> Option<Vector3<f32>> {
+    pub fn end_site(&self) -> Option<Vector3<Float>> {
         self.end_site
     }
 
@@ -0,0 +1,371 @@
+//! Typed views of the motion values of a single joint in a frame.
+
+use crate::{Axis, Channel, Float, Frame, Joint, Motion};
+use mint::Vector3;
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+/// The order in which the rotations of a `Joint` are applied, named after the
+/// order of its rotation channels.
+///
+/// The rotation channels of a joint are applied in the order in which they are
+/// written, so `Zrotation Xrotation Yrotation` gives [`RotationOrder::ZXY`], where
+/// the rotation is `Rz * Rx * Ry`.
+///
+/// [`RotationOrder::ZXY`]: #variant.ZXY
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RotationOrder {
+    /// `Xrotation Yrotation Zrotation`.
+    XYZ,
+    /// `Xrotation Zrotation Yrotation`.
+    XZY,
+    /// `Yrotation Xrotation Zrotation`.
+    YXZ,
+    /// `Yrotation Zrotation Xrotation`.
+    YZX,
+    /// `Zrotation Xrotation Yrotation`.
+    ZXY,
+    /// `Zrotation Yrotation Xrotation`.
+    ZYX,
+}
+
+impl RotationOrder {
+    /// Returns the `RotationOrder` of the rotation channels in `channels`, or `None`
+    /// if there are none.
+    ///
+    /// If only one or two axes have a channel, the missing axes are placed after
+    /// them in `x`, `y`, `z` order. Their angles are always zero, so this does not
+    /// change the rotation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::{bvh, JointIndex, RotationOrder};
+    /// let bvh = bvh! {
+    ///     HIERARCHY
+    ///     ROOT Hips
+    ///     {
+    ///         OFFSET 0.0 0.0 0.0
+    ///         CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation
+    ///         End Site
+    ///         {
+    ///             OFFSET 0.0 10.0 0.0
+    ///         }
+    ///     }
+    ///     MOTION
+    ///     Frames: 1
+    ///     Frame Time: 0.0333333
+    ///     0.0 0.0 0.0 0.0 0.0 0.0
+    /// };
+    ///
+    /// let channels = &bvh[JointIndex::new(0)].channels;
+    /// assert_eq!(RotationOrder::from_channels(&channels), Some(RotationOrder::ZXY));
+    /// assert_eq!(RotationOrder::from_channels(&channels[..3]), None);
+    /// assert_eq!(RotationOrder::from_channels(&channels[..4]), Some(RotationOrder::ZXY));
+    /// ```
+    pub fn from_channels(channels: &[Channel]) -> Option<Self> {
+        let mut axes = Vec::with_capacity(3);
+        for channel in channels.iter().filter(|channel| channel.channel_type().is_rotation()) {
+            let axis = channel.channel_type().axis();
+            if !axes.contains(&axis) {
+                axes.push(axis);
+            }
+        }
+        if axes.is_empty() {
+            return None;
+        }
+        for &axis in &[Axis::X, Axis::Y, Axis::Z] {
+            if !axes.contains(&axis) {
+                axes.push(axis);
+            }
+        }
+
+        let order = match (axes[0], axes[1]) {
+            (Axis::X, Axis::Y) => RotationOrder::XYZ,
+            (Axis::X, _) => RotationOrder::XZY,
+            (Axis::Y, Axis::X) => RotationOrder::YXZ,
+            (Axis::Y, _) => RotationOrder::YZX,
+            (Axis::Z, Axis::X) => RotationOrder::ZXY,
+            (Axis::Z, _) => RotationOrder::ZYX,
+        };
+        Some(order)
+    }
+
+    /// Returns the axes of the rotations in the order in which they are applied.
+    #[inline]
+    pub fn axes(&self) -> [Axis; 3] {
+        match *self {
+            RotationOrder::XYZ => [Axis::X, Axis::Y, Axis::Z],
+            RotationOrder::XZY => [Axis::X, Axis::Z, Axis::Y],
+            RotationOrder::YXZ => [Axis::Y, Axis::X, Axis::Z],
+            RotationOrder::YZX => [Axis::Y, Axis::Z, Axis::X],
+            RotationOrder::ZXY => [Axis::Z, Axis::X, Axis::Y],
+            RotationOrder::ZYX => [Axis::Z, Axis::Y, Axis::X],
+        }
+    }
+}
+
+impl fmt::Display for RotationOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for axis in &self.axes() {
+            let name = match *axis {
+                Axis::X => "X",
+                Axis::Y => "Y",
+                Axis::Z => "Z",
+            };
+            f.write_str(name)?;
+        }
+        Ok(())
+    }
+}
+
+/// A rotation made of an angle about each axis, applied in a `RotationOrder`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EulerRotation {
+    /// The order in which the rotations about each axis are applied.
+    pub order: RotationOrder,
+    /// The angle of the rotation about each axis, in degrees. This is indexed by
+    /// axis, not by the position of the axis in `order`.
+    pub angles: Vector3<Float>,
+}
+
+/// The local transform of a `Joint` in a single frame.
+///
+/// Use [`Bvh::joint_motion`][`Bvh::joint_motion`] or
+/// [`Frame::joint_motion`][`Frame::joint_motion`] to read the motion values of a
+/// joint as a `JointMotion`.
+///
+/// [`Bvh::joint_motion`]: struct.Bvh.html#method.joint_motion
+/// [`Frame::joint_motion`]: struct.Frame.html#method.joint_motion
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JointMotion {
+    /// The translation of the joint, or `None` if it has no position channels.
+    /// Axes without a channel are zero.
+    pub translation: Option<Vector3<Float>>,
+    /// The rotation of the joint, or `None` if it has no rotation channels. Axes
+    /// without a channel are zero.
+    pub rotation: Option<EulerRotation>,
+}
+
+impl JointMotion {
+    /// Read the motion values of `joint`, getting the value of each channel with
+    /// `value`.
+    fn read(joint: &Joint, value: impl Fn(&Channel) -> Float) -> Self {
+        let mut translation = None;
+        let mut angles = None;
+        for channel in &joint.channels {
+            let vector = if channel.channel_type().is_position() {
+                translation.get_or_insert_with(zero_vector)
+            } else {
+                angles.get_or_insert_with(zero_vector)
+            };
+            *component_mut(vector, channel.channel_type().axis()) = value(channel);
+        }
+
+        JointMotion {
+            translation,
+            rotation: angles.map(|angles| EulerRotation {
+                order: joint
+                    .rotation_order()
+                    .expect("a joint with a rotation channel has a rotation order"),
+                angles,
+            }),
+        }
+    }
+
+    /// Write the motion values of `joint`, setting the value of each channel with
+    /// `set_value`.
+    fn write(&self, joint: &Joint, mut set_value: impl FnMut(&Channel, Float)) {
+        for channel in &joint.channels {
+            let vector = if channel.channel_type().is_position() {
+                self.translation
+            } else {
+                self.rotation.map(|rotation| rotation.angles)
+            };
+            if let Some(vector) = vector {
+                set_value(channel, component(vector, channel.channel_type().axis()));
+            }
+        }
+    }
+}
+
+/// A mutable view of the motion values of a `Joint` in a single frame.
+///
+/// The view dereferences to a [`JointMotion`][`JointMotion`], and its values are
+/// written back into the frame when the view is dropped. Only the axes which the
+/// joint has channels for are written, and the `order` of the rotation is fixed by
+/// the channels of the joint, so changes to it are ignored.
+///
+/// # Examples
+///
+/// ```
+/// # use bvh_anim::{bvh, JointIndex, RotationOrder};
+/// let mut bvh = bvh! {
+///     HIERARCHY
+///     ROOT Hips
+///     {
+///         OFFSET 0.0 0.0 0.0
+///         CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation
+///         End Site
+///         {
+///             OFFSET 0.0 10.0 0.0
+///         }
+///     }
+///     MOTION
+///     Frames: 1
+///     Frame Time: 0.0333333
+///     1.0 2.0 3.0 10.0 20.0 30.0
+/// };
+///
+/// let hips = JointIndex::new(0);
+/// let motion = bvh.joint_motion(0, hips);
+/// assert_eq!(motion.translation, Some([1.0, 2.0, 3.0].into()));
+/// let rotation = motion.rotation.unwrap();
+/// assert_eq!(rotation.order, RotationOrder::ZXY);
+/// assert_eq!(rotation.angles, [20.0, 30.0, 10.0].into());
+///
+/// if let Some(ref mut rotation) = bvh.joint_motion_mut(0, hips).rotation {
+///     rotation.angles.x = 45.0;
+/// }
+/// assert_eq!(bvh.frames().next().unwrap().as_slice(), &[1.0, 2.0, 3.0, 10.0, 45.0, 30.0]);
+/// ```
+///
+/// [`JointMotion`]: struct.JointMotion.html
+pub struct JointMotionMut<'a> {
+    motion: JointMotion,
+    values: FrameValues<'a>,
+    joint: &'a Joint,
+}
+
+/// The values which a `JointMotionMut` is written back into.
+enum FrameValues<'a> {
+    Frame(&'a mut Frame),
+    Motion(&'a mut Motion, usize),
+}
+
+impl Deref for JointMotionMut<'_> {
+    type Target = JointMotion;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.motion
+    }
+}
+
+impl DerefMut for JointMotionMut<'_> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.motion
+    }
+}
+
+impl Drop for JointMotionMut<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        let joint = self.joint;
+        match self.values {
+            FrameValues::Frame(ref mut frame) => {
+                self.motion.write(joint, |channel, value| frame[channel] = value);
+            }
+            FrameValues::Motion(ref mut motion, frame) => {
+                self.motion
+                    .write(joint, |channel, value| *motion.value_mut(frame, channel) = value);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for JointMotionMut<'_> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.motion, f)
+    }
+}
+
+impl Frame {
+    /// Returns the motion values of `joint` in the `Frame` as a `JointMotion`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a channel of `joint` is out of bounds of the `Frame`.
+    #[inline]
+    pub fn joint_motion(&self, joint: &Joint) -> JointMotion {
+        JointMotion::read(joint, |channel| self[channel])
+    }
+
+    /// Returns a mutable view of the motion values of `joint` in the `Frame`, which
+    /// writes them back when it is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a channel of `joint` is out of bounds of the `Frame`.
+    #[inline]
+    pub fn joint_motion_mut<'a>(&'a mut self, joint: &'a Joint) -> JointMotionMut<'a> {
+        JointMotionMut {
+            motion: self.joint_motion(joint),
+            values: FrameValues::Frame(self),
+            joint,
+        }
+    }
+}
+
+impl Motion {
+    /// Returns the motion values of `joint` at `frame` as a `JointMotion`.
+    ///
+    /// Unlike going through [`Motion::frames`][`Motion::frames`], this reads the
+    /// values in place in either layout.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame` or a channel of `joint` is out of bounds of the `Motion`.
+    ///
+    /// [`Motion::frames`]: struct.Motion.html#method.frames
+    #[inline]
+    pub fn joint_motion(&self, frame: usize, joint: &Joint) -> JointMotion {
+        assert!(frame < self.num_frames, "frame out of bounds of the motion");
+        JointMotion::read(joint, |channel| *self.value(frame, channel))
+    }
+
+    /// Returns a mutable view of the motion values of `joint` at `frame`, which
+    /// writes them back when it is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame` or a channel of `joint` is out of bounds of the `Motion`.
+    #[inline]
+    pub fn joint_motion_mut<'a>(
+        &'a mut self,
+        frame: usize,
+        joint: &'a Joint,
+    ) -> JointMotionMut<'a> {
+        JointMotionMut {
+            motion: self.joint_motion(frame, joint),
+            values: FrameValues::Motion(self, frame),
+            joint,
+        }
+    }
+}
+
+#[inline]
+fn zero_vector() -> Vector3<Float> {
+    Vector3::from([0.0, 0.0, 0.0])
+}
+
+#[inline]
+fn component(vector: Vector3<Float>, axis: Axis) -> Float {
+    match axis {
+        Axis::X => vector.x,
+        Axis::Y => vector.y,
+        Axis::Z => vector.z,
+    }
+}
+
+#[inline]
+fn component_mut(vector: &mut Vector3<Float>, axis: Axis) -> &mut Float {
+    match axis {
+        Axis::X => &mut vector.x,
+        Axis::Y => &mut vector.y,
+        Axis::Z => &mut vector.z,
+    }
+}
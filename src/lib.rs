@@ -43,7 +43,8 @@
 //!   to the global transform for the root.
 //! * An offset, which is the vector distance from the parent joint.
 //! * An optional end site, which is used to cap off a chain of joints. This is only used
-//!   to calculate the length of the final bone in the chain.
+//!   to calculate the length of the final bone in the chain. It is stored on the last
+//!   joint of the chain, and returned by [`Joint::end_site`][`Joint::end_site`].
 //!
 //! ```text
 //! HEIRARCHY
@@ -121,10 +122,47 @@
 //! * You can use the [`from_reader`][`from_reader`] function, which will parse a `BufRead`
 //!   as a bvh file. The [`from_bytes`][`from_bytes`] function is a convenient wrapper function
 //!   to parse an in-memory slice of bytes as a `bvh` file. Note that the file does not need to
-//!   be strictly UTF-8, although it should be an ascii-compatible encoding. These functions are
-//!   also available as associated methods on the `Bvh` type directly as [`Bvh::from_reader`]
+//!   be strictly UTF-8, although it should be an ascii-compatible encoding. A byte order mark
+//!   is skipped, and UTF-16 files are transcoded to UTF-8 before they are parsed. These functions
+//!   are also available as associated methods on the `Bvh` type directly as [`Bvh::from_reader`]
 //!   [`Bvh::from_reader`] and [`Bvh::from_bytes`][`Bvh::from_bytes`]
 //!
+//! * You can use the [`ParseOptions`][`ParseOptions`] type to control how strictly the file is
+//!   checked. With [`ParsePolicy::Lenient`][`ParsePolicy::Lenient`], inconsistencies such as
+//!   frames with the wrong number of values are repaired and reported as warnings. Its
+//!   [`Limits`][`Limits`] bound the number of joints, channels and frames, so that files
+//!   from untrusted sources can be loaded safely.
+//!
+//! * Files written by exporters which use lowercase keywords, `End site`, `Frames:123` or
+//!   `#` comments are also accepted. The variations which were found are available from
+//!   [`Bvh::dialect`][`Bvh::dialect`], and can be passed to `WriteOptions::with_dialect`
+//!   to write the file back out in the same style.
+//!
+//! * [`Bvh::from_path`][`Bvh::from_path`] and
+//!   [`Bvh::from_compressed_reader`][`Bvh::from_compressed_reader`] detect files which were
+//!   compressed with gzip or Zstandard, and decompress them while loading. Support for each format is enabled by
+//!   the `gzip` and `zstd` features, which also allow `WriteOptions::with_compression` to
+//!   write compressed files.
+//!
+//! * For very large files, the [`FrameReader`][`FrameReader`] type parses the hierarchy up
+//!   front, and then reads the frames one at a time without storing them.
+//!
+//! * For live streams, the [`PushParser`][`PushParser`] type can be fed data in chunks as it
+//!   arrives, and produces the skeleton followed by each frame as soon as it is complete.
+//!
+//! * With the `rayon` feature enabled, the motion section is split into chunks which are
+//!   parsed in parallel. The `mmap` feature adds `Bvh::from_mmap`, which parses a file in
+//!   place without copying it into a buffer first.
+//!
+//! * With the `async` feature enabled, `from_async_reader` loads a `Bvh` from a
+//!   `futures::io::AsyncBufRead`, and `WriteOptions::write_async` writes to an `AsyncWrite`.
+//!
+//! * Offsets and motion values are stored as [`Float`][`Float`], which is `f32` by default.
+//!   The `f64` feature changes it to `f64`, for long captures where root translations need
+//!   more precision. With the `f64` feature, `WriteOptions` writes every value with enough
+//!   digits to be read back exactly; use `WriteOptions::with_full_precision` to choose this
+//!   either way.
+//!
 //! * You can use the [`bvh!`][`bvh!`] macro to construct a [`Bvh`][`Bvh`] instance in your source files
 //!   using the same syntax as you would use for a standard bvh file.
 //!
@@ -143,10 +181,45 @@
 //!   of the [`Bvh`][`Bvh`]. Each [`Joint`][`Joint`] can be inspected through its [`JointData`]
 //!   [`JointData`], which can be obtained with the [`Joint::data`][`Joint::data`] method.
 //!
+//! * A [`JointIndex`][`JointIndex`] identifies a [`Joint`][`Joint`] within its [`Bvh`][`Bvh`].
+//!   Methods such as [`Bvh::children`][`Bvh::children`], [`Bvh::descendants`][`Bvh::descendants`]
+//!   and [`Bvh::lowest_common_ancestor`][`Bvh::lowest_common_ancestor`] navigate the hierarchy.
+//!   Joints can be found by name with [`Bvh::joint_by_name`][`Bvh::joint_by_name`], or by
+//!   pattern with the [`select`][`select`] module.
+//!
+//! * The hierarchy can be edited with [`Bvh::insert_joint`][`Bvh::insert_joint`],
+//!   [`Bvh::remove_joint`][`Bvh::remove_joint`] and [`Bvh::reparent_joint`][`Bvh::reparent_joint`],
+//!   and the channels of a joint with [`Bvh::add_channels`][`Bvh::add_channels`],
+//!   [`Bvh::remove_channels`][`Bvh::remove_channels`] and
+//!   [`Bvh::set_channel_order`][`Bvh::set_channel_order`]. These keep the channels and motion
+//!   values of every joint consistent.
+//!
+//! * A [`Bvh`][`Bvh`] pairs a [`Skeleton`][`Skeleton`], which holds the joints, with a
+//!   [`Motion`][`Motion`], which holds the frame time and motion values. The skeleton is kept
+//!   in an `Arc`, so further clips of the same character can be loaded with
+//!   [`Bvh::from_bytes_with_skeleton`][`Bvh::from_bytes_with_skeleton`] to share it instead of
+//!   each keeping a copy. Editing the joints of a clip gives it its own skeleton first.
+//!
+//! * The frame time is stored exactly as a [`FrameRate`][`FrameRate`]. Frame times which are
+//!   close to 24, 25, 29.97, 30, 60 or 120 fps are snapped to that rate when parsed, so
+//!   [`FrameRate::time_of_frame`][`FrameRate::time_of_frame`] and
+//!   [`FrameRate::frame_at_time`][`FrameRate::frame_at_time`] don't drift over long clips.
+//!
 //! * The [`Bvh::frames`][`Bvh::frames`] method returns a [`Frames`][`Frames`] iterator over each
 //!   frame of the animation. A [`Frame`][`Frame`] can only be indexed by a [`Channel`][`Channel`]
 //!   belonging to an associated [`Joint`][`Joint`] of the [`Bvh`][`Bvh`], although you can convert
-//!   it into an [`&[`][`slice`][`f32`][`f32`][`]`][`slice`] using the [`Frame::as_slice`][`Frame::as_slice`] method.
+//!   it into an [`&[`][`slice`][`Float`][`Float`][`]`][`slice`] using the [`Frame::as_slice`][`Frame::as_slice`] method.
+//!
+//! * [`Bvh::joint_motion`][`Bvh::joint_motion`] reads the translation and rotation of a joint in a
+//!   frame as a [`JointMotion`][`JointMotion`]. The rotation carries the
+//!   [`RotationOrder`][`RotationOrder`] given by the order of the joint's channels, and
+//!   [`Bvh::joint_motion_mut`][`Bvh::joint_motion_mut`] writes changed values back into the frame.
+//!
+//! * [`Bvh::curve`][`Bvh::curve`] returns the values of a single channel across every frame
+//!   as a [`Curve`][`Curve`]. For work which walks whole curves, such as filtering or keyframe
+//!   reduction, [`Bvh::set_layout`][`Bvh::set_layout`] can store the values channel by channel
+//!   with [`MotionLayout::ChannelMajor`][`MotionLayout::ChannelMajor`]. The frames can still be
+//!   iterated over in either layout.
 //!
 //! * You can serialise the [`Bvh`][`Bvh`] into a [`Write`][`Write`] type using the [`Bvh::write_to`]
 //!   [`Bvh::write_to`] method. There is also the [`Bvh::to_bstring`][`Bvh::to_bstring`] method, which
@@ -154,6 +227,15 @@
 //!   can be customised using the [`WriteOptions`][`WriteOptions`] type, such as the line termination
 //!   style, indentation method, and floating point accuracy.
 //!
+//! * Joint names are kept as bytes, and can be decoded for display with
+//!   [`Joint::name_lossy`][`Joint::name_lossy`], which takes the [`NameEncoding`][`NameEncoding`]
+//!   to use.
+//!
+//! * If the file was loaded with `ParseOptions::with_preserve_syntax`, the original text of
+//!   the file is kept in a [`SyntaxTree`][`SyntaxTree`]. Writing the [`Bvh`][`Bvh`] then
+//!   keeps the text, whitespace and comments of everything which has not been edited, so
+//!   an unmodified file is written back byte-for-byte.
+//!
 //! ## Examples
 //!
 //! This library comes with some example applications, which can be viewed on [Github][Github].
@@ -170,21 +252,59 @@
 //! [`from_bytes`]: fn.from_bytes.html
 //! [`Bvh::from_reader`]: struct.Bvh.html#method.from_reader
 //! [`Bvh::from_bytes`]:  struct.Bvh.html#method.from_bytes
+//! [`Bvh::from_path`]: struct.Bvh.html#method.from_path
+//! [`Bvh::from_compressed_reader`]: struct.Bvh.html#method.from_compressed_reader
+//! [`ParseOptions`]: parse/struct.ParseOptions.html
+//! [`FrameReader`]: parse/struct.FrameReader.html
+//! [`PushParser`]: parse/struct.PushParser.html
+//! [`Bvh::dialect`]: struct.Bvh.html#method.dialect
+//! [`SyntaxTree`]: syntax/struct.SyntaxTree.html
+//! [`ParsePolicy::Lenient`]: parse/enum.ParsePolicy.html#variant.Lenient
+//! [`Limits`]: parse/struct.Limits.html
 //! [`bvh!`]: macro.bvh.html
 //! [`builder`]: builder/index.html
 //! [`Bvh::new`]: struct.Bvh.html#method.new
 //! [`Default::default`]: https://doc.rust-lang.org/stable/std/default/trait.Default.html#tymethod.default
 //! [`Bvh::joints`]: struct.Bvh.html#method.joints
 //! [`Joint`]: struct.Joint.html
+//! [`JointIndex`]: struct.JointIndex.html
+//! [`Bvh::children`]: struct.Bvh.html#method.children
+//! [`Bvh::descendants`]: struct.Bvh.html#method.descendants
+//! [`Bvh::lowest_common_ancestor`]: struct.Bvh.html#method.lowest_common_ancestor
+//! [`Bvh::joint_by_name`]: struct.Bvh.html#method.joint_by_name
+//! [`select`]: select/index.html
+//! [`Bvh::insert_joint`]: struct.Bvh.html#method.insert_joint
+//! [`Bvh::remove_joint`]: struct.Bvh.html#method.remove_joint
+//! [`Bvh::reparent_joint`]: struct.Bvh.html#method.reparent_joint
+//! [`Bvh::add_channels`]: struct.Bvh.html#method.add_channels
+//! [`Bvh::remove_channels`]: struct.Bvh.html#method.remove_channels
+//! [`Bvh::set_channel_order`]: struct.Bvh.html#method.set_channel_order
+//! [`Skeleton`]: struct.Skeleton.html
+//! [`Motion`]: struct.Motion.html
+//! [`Joint::end_site`]: struct.Joint.html#method.end_site
+//! [`FrameRate`]: struct.FrameRate.html
+//! [`FrameRate::time_of_frame`]: struct.FrameRate.html#method.time_of_frame
+//! [`FrameRate::frame_at_time`]: struct.FrameRate.html#method.frame_at_time
+//! [`Bvh::from_bytes_with_skeleton`]: struct.Bvh.html#method.from_bytes_with_skeleton
 //! [`JointData`]: enum.JointData.html
 //! [`Joint::data`]: struct.Joint.html#method.data
+//! [`Joint::name_lossy`]: struct.Joint.html#method.name_lossy
+//! [`NameEncoding`]: encoding/enum.NameEncoding.html
 //! [`Bvh::frames`]: struct.Bvh.html#method.frames
 //! [`Frames`]: struct.Frames.html
 //! [`Frame`]: struct.Frame.html
 //! [`slice`]: https://doc.rust-lang.org/std/primitive.slice.html
-//! [`f32`]: https://doc.rust-lang.org/stable/std/primitive.f32.html
+//! [`Float`]: type.Float.html
 //! [`Channel`]: struct.Channel.html
 //! [`Frame::as_slice`]: struct.Frame.html#method.as_slice
+//! [`Bvh::joint_motion`]: struct.Bvh.html#method.joint_motion
+//! [`Bvh::joint_motion_mut`]: struct.Bvh.html#method.joint_motion_mut
+//! [`JointMotion`]: struct.JointMotion.html
+//! [`RotationOrder`]: enum.RotationOrder.html
+//! [`Bvh::curve`]: struct.Bvh.html#method.curve
+//! [`Curve`]: struct.Curve.html
+//! [`Bvh::set_layout`]: struct.Bvh.html#method.set_layout
+//! [`MotionLayout::ChannelMajor`]: enum.MotionLayout.html#variant.ChannelMajor
 //! [`Write`]: https://doc.rust-lang.org/stable/std/io/trait.Write.html
 //! [`Bvh::write_to`]: struct.Bvh.html#method.write_to
 //! [`Bvh::to_bstring`]: struct.Bvh.html#method.to_bstring
@@ -198,18 +318,30 @@
 mod macros;
 
 pub mod builder;
+pub mod compression;
+pub mod encoding;
 pub mod errors;
 
 #[cfg(feature = "ffi")]
 pub mod ffi;
 
+pub mod parse;
+pub mod select;
+pub mod syntax;
 pub mod write;
 
+mod curve;
+mod edit;
+mod frame_rate;
+mod hierarchy;
 mod joint;
-mod parse;
+mod joint_motion;
+mod motion;
+mod skeleton;
+mod tokenizer;
 
 use bstr::{
-    io::{BufReadExt, ByteLines},
+    io::BufReadExt,
     BStr, BString, ByteSlice,
 };
 use mint::Vector3;
@@ -217,86 +349,66 @@ use num_traits::{one, zero, One, Zero};
 use std::{
     convert::TryFrom,
     fmt,
-    io::{self, Cursor, Write},
-    iter::Enumerate,
+    fs::File,
+    io::{self, BufReader, Write},
     mem,
     ops::{Index, IndexMut, Range},
+    path::Path,
     slice::{Iter, IterMut},
     str::{self, FromStr},
+    sync::Arc,
     time::Duration,
 };
 
-pub use joint::{Joint, JointName};
-#[doc(hidden)]
-pub use macros::BvhLiteralBuilder;
-
-use errors::{LoadError, ParseChannelError, SetMotionError};
 
-struct CachedEnumerate<I> {
-    iter: Enumerate<I>,
-    last_enumerator: Option<usize>,
-}
-
-impl<I> CachedEnumerate<I> {
-    #[inline]
-    fn new(iter: Enumerate<I>) -> Self {
-        CachedEnumerate {
-            iter,
-            last_enumerator: None,
-        }
-    }
+#[cfg(feature = "async")]
+use futures_util::io::AsyncBufRead;
 
-    #[inline]
-    fn last_enumerator(&self) -> Option<usize> {
-        self.last_enumerator
-    }
-}
+pub use curve::{Curve, CurveIter, CurveIterMut, CurveMut};
+pub use frame_rate::FrameRate;
+pub use hierarchy::{Ancestors, BreadthFirst, Children, DepthFirst};
+pub use joint::{Joint, JointIndex, JointName};
+pub use joint_motion::{EulerRotation, JointMotion, JointMotionMut, RotationOrder};
+pub use motion::{Motion, MotionLayout};
+pub use skeleton::Skeleton;
 
-impl<I: Iterator> Iterator for CachedEnumerate<I> {
-    type Item = <Enumerate<I> as Iterator>::Item;
-    #[inline]
-    fn next(&mut self) -> Option<Self::Item> {
-        let (curr, item) = self.iter.next()?;
-        self.last_enumerator = Some(curr);
-        Some((curr, item))
-    }
+/// The floating point type of joint offsets and motion values.
+///
+/// This is `f32`, unless the `f64` feature is enabled.
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
 
-    #[inline]
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
-    }
-}
+/// The floating point type of joint offsets and motion values.
+///
+/// This is `f64`, because the `f64` feature is enabled.
+#[cfg(feature = "f64")]
+pub type Float = f64;
+#[doc(hidden)]
+pub use macros::BvhLiteralBuilder;
 
-type EnumeratedLines<'a> = CachedEnumerate<ByteLines<&'a mut dyn BufReadExt>>;
-
-impl EnumeratedLines<'_> {
-    pub fn next_non_empty_line(&mut self) -> Option<<Self as Iterator>::Item> {
-        let mut next = self.next();
-        loop {
-            match next {
-                None => return None,
-                Some((idx, result)) => {
-                    let string = match result {
-                        Ok(s) => s,
-                        Err(e) => return Some((idx, Err(e))),
-                    };
-                    if string.trim().is_empty() {
-                        next = self.next()
-                    } else {
-                        return Some((idx, Ok(string)));
-                    }
-                }
-            }
-        }
-    }
-}
+use encoding::TextEncoding;
+use errors::{LoadError, MismatchedChannelsError, ParseChannelError, SetMotionError};
+use parse::{Dialect, Header, ParseOptions, ParseWarning};
+use select::{Selection, Selector};
+use syntax::SyntaxTree;
 
 /// Loads the `Bvh` from the `reader`.
+///
+/// See [`Bvh::from_reader`][`Bvh::from_reader`].
+///
+/// [`Bvh::from_reader`]: struct.Bvh.html#method.from_reader
 #[inline]
 pub fn from_reader<R: BufReadExt>(data: R) -> Result<Bvh, LoadError> {
     Bvh::from_reader(data)
 }
 
+/// Loads the `Bvh` from the asynchronous `reader`.
+#[cfg(feature = "async")]
+#[inline]
+pub async fn from_async_reader<R: AsyncBufRead + Unpin>(reader: R) -> Result<Bvh, LoadError> {
+    Bvh::from_async_reader(reader).await
+}
+
 /// Parse a sequence of bytes as if it were an in-memory `Bvh` file.
 ///
 /// # Examples
@@ -365,31 +477,30 @@ pub fn from_str(string: &str) -> Result<Bvh, LoadError> {
 ///
 /// See the [module documentation](index.html#using-this-library)
 /// for more information.
-#[derive(Clone, Default, Debug, PartialEq)]
+#[derive(Clone, Default, Debug)]
 pub struct Bvh {
-    /// The list of joints. If the root joint exists, it is always at
-    /// index `0`.
-    joints: Vec<Joint>,
-    /// The motion values of the `Frame`.
-    motion_values: Vec<f32>,
-    /// The number of frames in the bvh.
-    num_frames: usize,
-    /// The number of `Channel`s in the bvh.
-    num_channels: usize,
-    /// The total time it takes to play one frame.
-    frame_time: Duration,
+    /// The joints of the bvh, which may be shared with other clips.
+    skeleton: Arc<Skeleton>,
+    /// The frame time and motion values of the bvh.
+    motion: Motion,
+    /// The formatting variations found when the bvh was parsed.
+    dialect: Dialect,
+    /// The text encoding of the file which the bvh was parsed from.
+    encoding: TextEncoding,
+    /// The original text of the bvh, if it was parsed with `preserve_syntax`.
+    syntax: Option<Arc<SyntaxTree>>,
 }
 
 impl Bvh {
     /// Create an empty `Bvh`.
     #[inline]
-    pub const fn new() -> Self {
+    pub fn new() -> Self {
         Self {
-            joints: Vec::new(),
-            motion_values: Vec::new(),
-            num_frames: 0,
-            num_channels: 0,
-            frame_time: Duration::from_secs(0),
+            skeleton: Arc::new(Skeleton::new()),
+            motion: Motion::new(0, FrameRate::default()),
+            dialect: Dialect::new(),
+            encoding: TextEncoding::Utf8,
+            syntax: None,
         }
     }
 
@@ -422,23 +533,199 @@ impl Bvh {
     /// ```
     #[inline]
     pub fn from_bytes<B: AsRef<[u8]>>(bytes: B) -> Result<Self, LoadError> {
-        Bvh::from_reader(Cursor::new(bytes))
+        Self::from_bytes_with_options_(bytes.as_ref(), &ParseOptions::default(), None)
+            .map(|(bvh, _)| bvh)
+    }
+
+    /// Loads the `Bvh` from the `file` by mapping it into memory, so that the
+    /// motion values are parsed in place without first being copied into a buffer.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated by this or any other process
+    /// while it is mapped. See [`memmap2::Mmap`][`memmap2::Mmap`] for details.
+    ///
+    /// [`memmap2::Mmap`]: https://docs.rs/memmap2/*/memmap2/struct.Mmap.html
+    #[cfg(feature = "mmap")]
+    pub unsafe fn from_mmap(file: &File) -> Result<Self, LoadError> {
+        let map = memmap2::Mmap::map(file).map_err(errors::LoadJointsError::from)?;
+        Self::from_bytes(&map[..])
     }
 
     /// Loads the `Bvh` from the `reader`.
+    ///
+    /// The whole of the `reader` is first read into a buffer, which is then
+    /// tokenized in the same way as by [`Bvh::from_bytes`][`Bvh::from_bytes`], so
+    /// this allocates a copy of the input. Use `from_bytes` if the data is already
+    /// in memory, or [`FrameReader`][`FrameReader`] to read the frames one at a time.
+    ///
+    /// [`Bvh::from_bytes`]: #method.from_bytes
+    /// [`FrameReader`]: parse/struct.FrameReader.html
     pub fn from_reader<R: BufReadExt>(mut reader: R) -> Result<Self, LoadError> {
         Self::from_reader_(reader.by_ref())
     }
 
-    fn from_reader_(reader: &mut dyn BufReadExt) -> Result<Self, LoadError> {
-        let mut lines = CachedEnumerate::new(reader.byte_lines().enumerate());
+    /// Loads the `Bvh` from the `reader`, decompressing it first if it starts with
+    /// the magic bytes of a [`Compression`][`Compression`] format.
+    ///
+    /// Uncompressed data is loaded as with [`Bvh::from_reader`][`Bvh::from_reader`].
+    ///
+    /// [`Compression`]: compression/enum.Compression.html
+    /// [`Bvh::from_reader`]: #method.from_reader
+    pub fn from_compressed_reader<R: BufReadExt>(mut reader: R) -> Result<Self, LoadError> {
+        let mut bytes = vec![];
+        compression::read_to_end(&mut reader, &mut bytes).map_err(errors::LoadJointsError::from)?;
+        Self::from_bytes(&bytes)
+    }
 
+    /// Loads the `Bvh` from the file at `path`, which may be compressed.
+    ///
+    /// See [`Bvh::from_compressed_reader`][`Bvh::from_compressed_reader`].
+    ///
+    /// [`Bvh::from_compressed_reader`]: #method.from_compressed_reader
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        let file = File::open(path).map_err(errors::LoadJointsError::from)?;
+        Self::from_compressed_reader(BufReader::new(file))
+    }
+
+    /// Loads the `Bvh` from the asynchronous `reader`.
+    #[cfg(feature = "async")]
+    pub async fn from_async_reader<R: AsyncBufRead + Unpin>(reader: R) -> Result<Self, LoadError> {
+        ParseOptions::default()
+            .parse_async(reader)
+            .await
+            .map(|(bvh, _)| bvh)
+    }
+
+    /// Loads a further clip of an existing skeleton from the in-memory `bytes`.
+    ///
+    /// The hierarchy in `bytes` must match `skeleton` exactly. The returned `Bvh`
+    /// then shares `skeleton`, instead of keeping its own copy of the joints.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::Bvh;
+    /// # use std::sync::Arc;
+    /// let clip = |frames: &str| {
+    ///     format!("
+    ///         HIERARCHY
+    ///         ROOT Hips
+    ///         {{
+    ///             OFFSET 0.0 0.0 0.0
+    ///             CHANNELS 3 Xposition Yposition Zposition
+    ///             End Site
+    ///             {{
+    ///                 OFFSET 0.0 0.0 0.0
+    ///             }}
+    ///         }}
+    ///         MOTION
+    ///         Frames: 1
+    ///         Frame Time: 0.033333333
+    ///         {}
+    ///     ", frames)
+    /// };
+    ///
+    /// let walk = Bvh::from_bytes(clip("0.0 0.0 0.0"))?;
+    /// let run = Bvh::from_bytes_with_skeleton(clip("1.0 0.0 0.0"), walk.skeleton())?;
+    ///
+    /// assert!(Arc::ptr_eq(walk.skeleton(), run.skeleton()));
+    /// assert_eq!(run.frames().next().unwrap().as_slice(), &[1.0, 0.0, 0.0]);
+    /// # Result::<(), bvh_anim::errors::LoadError>::Ok(())
+    /// ```
+    #[inline]
+    pub fn from_bytes_with_skeleton<B: AsRef<[u8]>>(
+        bytes: B,
+        skeleton: &Arc<Skeleton>,
+    ) -> Result<Self, LoadError> {
+        Self::from_bytes_with_options_(bytes.as_ref(), &ParseOptions::default(), Some(skeleton))
+            .map(|(bvh, _)| bvh)
+    }
+
+    /// Loads a further clip of an existing skeleton from the `reader`.
+    ///
+    /// See [`Bvh::from_bytes_with_skeleton`][`Bvh::from_bytes_with_skeleton`].
+    ///
+    /// [`Bvh::from_bytes_with_skeleton`]: #method.from_bytes_with_skeleton
+    pub fn from_reader_with_skeleton<R: BufReadExt>(
+        mut reader: R,
+        skeleton: &Arc<Skeleton>,
+    ) -> Result<Self, LoadError> {
+        Self::from_reader_with_options_(reader.by_ref(), &ParseOptions::default(), Some(skeleton))
+            .map(|(bvh, _)| bvh)
+    }
+
+    fn from_reader_(reader: &mut dyn BufReadExt) -> Result<Self, LoadError> {
+        Self::from_reader_with_options_(reader, &ParseOptions::default(), None)
+            .map(|(bvh, _)| bvh)
+    }
+
+    fn from_reader_with_options_(
+        reader: &mut dyn BufReadExt,
+        options: &ParseOptions,
+        skeleton: Option<&Arc<Skeleton>>,
+    ) -> Result<(Self, Vec<ParseWarning>), LoadError> {
+        // The tokenizer borrows its tokens from a single slice, so the input has
+        // to be buffered in full.
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).map_err(errors::LoadJointsError::from)?;
+        Self::from_bytes_with_options_(&bytes, options, skeleton)
+    }
+
+    fn from_bytes_with_options_(
+        bytes: &[u8],
+        options: &ParseOptions,
+        skeleton: Option<&Arc<Skeleton>>,
+    ) -> Result<(Self, Vec<ParseWarning>), LoadError> {
         let mut bvh = Bvh::default();
+        let mut warnings = vec![];
+
+        let (encoding, text) = encoding::decode(bytes);
+        let bytes = &text[..];
+
+        let (num_frames_span, lines) = bvh.read_header_bytes(bytes, options, &mut warnings)?;
+        bvh.encoding = encoding;
+        if let Some(skeleton) = skeleton {
+            bvh.share_skeleton(skeleton)?;
+        }
+
+        let motion = &bytes[lines.position()..];
+        let line_num = lines.line_num();
+        bvh.read_motion_bytes(motion, line_num, num_frames_span, options, &mut warnings)?;
 
-        bvh.read_joints(&mut lines)?;
-        bvh.read_motion(&mut lines)?;
+        if options.preserve_syntax {
+            bvh.syntax = SyntaxTree::new(bytes, &bvh).map(Arc::new);
+        }
+
+        Ok((bvh, warnings))
+    }
 
-        Ok(bvh)
+    /// Replace the parsed joints with `skeleton`, if they are the same.
+    fn share_skeleton(&mut self, skeleton: &Arc<Skeleton>) -> Result<(), errors::LoadJointsError> {
+        let (ours, theirs) = (&self.skeleton.joints, &skeleton.joints);
+        if ours != theirs {
+            let index = ours.iter().zip(theirs).take_while(|(a, b)| a == b).count();
+            return Err(errors::LoadJointsError::SkeletonMismatch {
+                joint: JointIndex::new(index),
+            });
+        }
+
+        self.skeleton = Arc::clone(skeleton);
+        Ok(())
+    }
+
+    /// Loads only the skeleton and motion header from the `reader`, without
+    /// parsing any of the motion values.
+    ///
+    /// See [`ParseOptions::parse_header`][`ParseOptions::parse_header`] to
+    /// also check the number of frames.
+    ///
+    /// [`ParseOptions::parse_header`]: parse/struct.ParseOptions.html#method.parse_header
+    #[inline]
+    pub fn read_header<R: BufReadExt>(reader: R) -> Result<Header, LoadError> {
+        ParseOptions::default()
+            .parse_header(reader)
+            .map(|(header, _)| header)
     }
 
     /// Writes the `Bvh` using the `bvh` file format to the `writer`, with
@@ -482,7 +769,9 @@ impl Bvh {
         write::WriteOptions::default().write_to_string(self)
     }
 
-    /// Returns the root joint if it exists, or `None` if the skeleton is empty.
+    /// Returns the root joint of the first hierarchy if it exists, or `None` if the
+    /// skeleton is empty. See [`Bvh::root_joints`][`Bvh::root_joints`] for files with
+    /// more than one hierarchy.
     ///
     /// # Examples
     ///
@@ -511,42 +800,392 @@ impl Bvh {
     ///
     /// assert!(bvh.root_joint().is_some());
     /// ```
+    ///
+    /// [`Bvh::root_joints`]: struct.Bvh.html#method.root_joints
     #[inline]
     pub fn root_joint(&self) -> Option<&Joint> {
-        self.joints.get(0)
+        self.skeleton.root_joint()
+    }
+
+    /// Returns an iterator over the root `Joint` of each hierarchy in the `Bvh`.
+    ///
+    /// Most files contain a single hierarchy, but some exporters write one for
+    /// each actor or prop in a scene. The roots are returned in file order.
+    #[inline]
+    pub fn root_joints(&self) -> impl Iterator<Item = &Joint> + '_ {
+        self.skeleton.root_joints()
+    }
+
+    /// Returns an iterator over the root `Joint` of each hierarchy in the `Bvh`,
+    /// along with the range of motion channels which belong to the joints in
+    /// that hierarchy.
+    ///
+    /// The ranges can be used to slice each `Frame`, and are in file order.
+    #[inline]
+    pub fn root_channel_ranges(&self) -> impl Iterator<Item = (&Joint, Range<usize>)> + '_ {
+        self.skeleton.root_channel_ranges()
     }
 
     /// Returns an iterator over all the `Joint`s in the `Bvh`.
     #[inline]
     pub fn joints(&self) -> Iter<'_, Joint> {
-        self.joints.iter()
+        self.skeleton.joints()
     }
 
     /// Returns a mutable iterator over all the joints in the `Bvh`.
+    ///
+    /// If the `Skeleton` is shared with other clips, the `Bvh` is first given its
+    /// own copy of it.
+    #[inline]
     pub fn joints_mut(&mut self) -> IterMut<'_, Joint> {
-        self.joints.iter_mut()
+        self.skeleton_mut().joints_mut()
+    }
+
+    /// Returns the `Joint` at `index`, or `None` if it is out of bounds.
+    #[inline]
+    pub fn joint(&self, index: JointIndex) -> Option<&Joint> {
+        self.skeleton.joint(index)
+    }
+
+    /// Returns the `Joint` at `index` mutably, or `None` if it is out of bounds.
+    ///
+    /// If the `Skeleton` is shared with other clips, the `Bvh` is first given its
+    /// own copy of it.
+    #[inline]
+    pub fn joint_mut(&mut self, index: JointIndex) -> Option<&mut Joint> {
+        self.skeleton_mut().joint_mut(index)
+    }
+
+    /// Returns the index of the first `Joint` called `name`, or `None` if there
+    /// is no such joint.
+    ///
+    /// The names are compared byte-for-byte. The joints are indexed by name the
+    /// first time this is called, so later lookups do not scan the joints.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::bvh;
+    /// let bvh = bvh! {
+    ///     HIERARCHY
+    ///     ROOT Hips
+    ///     {
+    ///         OFFSET 0.0 0.0 0.0
+    ///         CHANNELS 0
+    ///         JOINT Chest
+    ///         {
+    ///             OFFSET 0.0 0.0 0.0
+    ///             CHANNELS 0
+    ///             End Site
+    ///             {
+    ///                 OFFSET 0.0 0.0 0.0
+    ///             }
+    ///         }
+    ///     }
+    ///     MOTION
+    ///     Frames: 0
+    ///     Frame Time: 0.033333333
+    /// };
+    ///
+    /// let chest = bvh.joint_by_name("Chest").unwrap();
+    /// assert_eq!(bvh[chest].name[..], b"Chest"[..]);
+    /// assert!(bvh.joint_by_name("LeftHand").is_none());
+    /// ```
+    #[inline]
+    pub fn joint_by_name<N: AsRef<[u8]>>(&self, name: N) -> Option<JointIndex> {
+        self.skeleton.joint_by_name(name)
+    }
+
+    /// Returns the joints chosen by `selector`, along with their channels.
+    ///
+    /// See the [`select`][`select`] module for details.
+    ///
+    /// [`select`]: select/index.html
+    #[inline]
+    pub fn select(&self, selector: &Selector<'_>) -> Selection<'_> {
+        selector.select(self)
+    }
+
+    /// Returns an iterator over the `JointIndex` of every `Joint` in the `Bvh`,
+    /// in file order.
+    #[inline]
+    pub fn joint_indices(&self) -> impl DoubleEndedIterator<Item = JointIndex> + ExactSizeIterator {
+        self.skeleton.joint_indices()
+    }
+
+    /// Returns an iterator over the direct children of the `Joint` at `index`,
+    /// in file order.
+    ///
+    /// The children of every joint are cached the first time this (or any of
+    /// the other hierarchy methods) is called, so each lookup is `O(1)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds. This applies to all of the hierarchy
+    /// methods.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::{bvh, JointIndex};
+    /// let bvh = bvh! {
+    ///     HIERARCHY
+    ///     ROOT Hips
+    ///     {
+    ///         OFFSET 0.0 0.0 0.0
+    ///         CHANNELS 0
+    ///         JOINT LeftHip
+    ///         {
+    ///             OFFSET 0.0 0.0 0.0
+    ///             CHANNELS 0
+    ///             End Site
+    ///             {
+    ///                 OFFSET 0.0 0.0 0.0
+    ///             }
+    ///         }
+    ///         JOINT RightHip
+    ///         {
+    ///             OFFSET 0.0 0.0 0.0
+    ///             CHANNELS 0
+    ///             End Site
+    ///             {
+    ///                 OFFSET 0.0 0.0 0.0
+    ///             }
+    ///         }
+    ///     }
+    ///     MOTION
+    ///     Frames: 0
+    ///     Frame Time: 0.033333333
+    /// };
+    ///
+    /// let hips = JointIndex::new(0);
+    /// let children: Vec<_> = bvh.children(hips).map(|idx| &bvh[idx].name[..]).collect();
+    /// assert_eq!(children, [&b"LeftHip"[..], &b"RightHip"[..]]);
+    /// ```
+    #[inline]
+    pub fn children(&self, index: JointIndex) -> Children<'_> {
+        self.skeleton.children(index)
+    }
+
+    /// Returns a depth-first iterator over all of the descendants of the `Joint`
+    /// at `index`, not including the joint itself.
+    ///
+    /// Joints are visited in the same order as they appear in the file.
+    #[inline]
+    pub fn descendants(&self, index: JointIndex) -> DepthFirst<'_> {
+        self.skeleton.descendants(index)
+    }
+
+    /// Returns a breadth-first iterator over all of the descendants of the
+    /// `Joint` at `index`, not including the joint itself.
+    ///
+    /// All of the children of a joint are visited before any of its
+    /// grandchildren.
+    #[inline]
+    pub fn descendants_breadth_first(&self, index: JointIndex) -> BreadthFirst<'_> {
+        self.skeleton.descendants_breadth_first(index)
+    }
+
+    /// Returns an iterator over the ancestors of the `Joint` at `index`, starting
+    /// with its parent and ending with its root. The joint itself is not included.
+    #[inline]
+    pub fn ancestors(&self, index: JointIndex) -> Ancestors<'_> {
+        self.skeleton.ancestors(index)
+    }
+
+    /// Returns an iterator over the path from the `Joint` at `index` up to its
+    /// root, including both the joint and the root.
+    #[inline]
+    pub fn path_to_root(&self, index: JointIndex) -> Ancestors<'_> {
+        self.skeleton.path_to_root(index)
+    }
+
+    /// Returns the deepest `Joint` which is an ancestor of both `a` and `b`.
+    ///
+    /// A joint counts as its own ancestor, so if `a` is an ancestor of `b` then
+    /// `a` is returned. Returns `None` if the joints are in different hierarchies.
+    #[inline]
+    pub fn lowest_common_ancestor(&self, a: JointIndex, b: JointIndex) -> Option<JointIndex> {
+        self.skeleton.lowest_common_ancestor(a, b)
+    }
+
+    /// Returns the joint hierarchy of the `Bvh`.
+    ///
+    /// Clone the `Arc` to load further clips against the same hierarchy with
+    /// [`Bvh::from_bytes_with_skeleton`][`Bvh::from_bytes_with_skeleton`].
+    ///
+    /// [`Bvh::from_bytes_with_skeleton`]: #method.from_bytes_with_skeleton
+    #[inline]
+    pub fn skeleton(&self) -> &Arc<Skeleton> {
+        &self.skeleton
+    }
+
+    /// Returns the joint hierarchy of the `Bvh` mutably, copying it first if it
+    /// is shared with other clips.
+    #[inline]
+    pub(crate) fn skeleton_mut(&mut self) -> &mut Skeleton {
+        Arc::make_mut(&mut self.skeleton)
+    }
+
+    /// Returns the frame time and motion values of the `Bvh`.
+    #[inline]
+    pub const fn motion(&self) -> &Motion {
+        &self.motion
+    }
+
+    /// Returns the frame time and motion values of the `Bvh` mutably.
+    #[inline]
+    pub fn motion_mut(&mut self) -> &mut Motion {
+        &mut self.motion
+    }
+
+    /// Pair a `Skeleton` with a `Motion` to create a `Bvh`.
+    ///
+    /// Returns an error if the number of values in each frame of `motion` is not
+    /// the number of channels of `skeleton`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::{bvh, Bvh, FrameRate, Motion};
+    /// let bvh = bvh! {
+    ///     HIERARCHY
+    ///     ROOT Hips
+    ///     {
+    ///         OFFSET 0.0 0.0 0.0
+    ///         CHANNELS 3 Xposition Yposition Zposition
+    ///         End Site
+    ///         {
+    ///             OFFSET 0.0 0.0 0.0
+    ///         }
+    ///     }
+    ///     MOTION
+    ///     Frames: 0
+    ///     Frame Time: 0.033333333
+    /// };
+    ///
+    /// let mut motion = Motion::new(3, FrameRate::FPS_30);
+    /// motion.push_frame(&[1.0, 2.0, 3.0]);
+    ///
+    /// let (skeleton, _) = bvh.into_parts();
+    /// let bvh = Bvh::from_parts(skeleton, motion)?;
+    /// assert_eq!(bvh.num_frames(), 1);
+    /// # Result::<(), bvh_anim::errors::MismatchedChannelsError>::Ok(())
+    /// ```
+    pub fn from_parts(
+        skeleton: Arc<Skeleton>,
+        motion: Motion,
+    ) -> Result<Self, MismatchedChannelsError> {
+        if skeleton.num_channels() != motion.num_channels() {
+            return Err(MismatchedChannelsError {
+                skeleton_channels: skeleton.num_channels(),
+                motion_channels: motion.num_channels(),
+            });
+        }
+
+        Ok(Bvh {
+            skeleton,
+            motion,
+            ..Bvh::default()
+        })
+    }
+
+    /// Split the `Bvh` into its `Skeleton` and its `Motion`.
+    ///
+    /// The formatting of the file which the `Bvh` was loaded from is discarded.
+    #[inline]
+    pub fn into_parts(self) -> (Arc<Skeleton>, Motion) {
+        (self.skeleton, self.motion)
     }
 
     /// Returns a `Frames` iterator over the frames of the bvh.
+    ///
+    /// See [`Motion::frames`][`Motion::frames`] for how this works with a
+    /// channel-major layout.
+    ///
+    /// [`Motion::frames`]: struct.Motion.html#method.frames
     #[inline]
     pub fn frames(&self) -> Frames<'_> {
-        Frames {
-            motion_values: &self.motion_values[..],
-            num_channels: self.num_channels,
-            num_frames: self.num_frames,
-            curr_frame: 0,
-        }
+        self.motion.frames()
     }
 
     /// Returns a mutable iterator over the frames of the bvh.
+    ///
+    /// If the motion is channel-major, it is first switched back to
+    /// `MotionLayout::FrameMajor`.
     #[inline]
     pub fn frames_mut(&mut self) -> FramesMut<'_> {
-        FramesMut {
-            motion_values: &mut self.motion_values[..],
-            num_channels: self.num_channels,
-            num_frames: self.num_frames,
-            curr_frame: 0,
-        }
+        self.motion.frames_mut()
+    }
+
+    /// Returns the values of `channel` in every frame as a [`Curve`][`Curve`].
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if `channel` is out of bounds.
+    ///
+    /// [`Curve`]: struct.Curve.html
+    #[inline]
+    pub fn curve(&self, channel: &Channel) -> Curve<'_> {
+        self.motion.curve(channel)
+    }
+
+    /// Returns the values of `channel` in every frame as a [`CurveMut`][`CurveMut`],
+    /// which can change them in place.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if `channel` is out of bounds.
+    ///
+    /// [`CurveMut`]: struct.CurveMut.html
+    #[inline]
+    pub fn curve_mut(&mut self, channel: &Channel) -> CurveMut<'_> {
+        self.motion.curve_mut(channel)
+    }
+
+    /// Get the order in which the motion values of the `Bvh` are stored.
+    #[inline]
+    pub fn layout(&self) -> MotionLayout {
+        self.motion.layout()
+    }
+
+    /// Store the motion values of the `Bvh` in the order given by `layout`.
+    ///
+    /// A channel-major layout makes work on whole curves faster, such as
+    /// filtering or computing statistics of each channel.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::{bvh, JointIndex, MotionLayout};
+    /// let mut bvh = bvh! {
+    ///     HIERARCHY
+    ///     ROOT Hips
+    ///     {
+    ///         OFFSET 0.0 0.0 0.0
+    ///         CHANNELS 3 Xposition Yposition Zposition
+    ///         End Site
+    ///         {
+    ///             OFFSET 0.0 10.0 0.0
+    ///         }
+    ///     }
+    ///     MOTION
+    ///     Frames: 2
+    ///     Frame Time: 0.0333333
+    ///     1.0 2.0 3.0
+    ///     4.0 5.0 6.0
+    /// };
+    ///
+    /// bvh.set_layout(MotionLayout::ChannelMajor);
+    /// let y = bvh[JointIndex::new(0)].channels[1];
+    /// assert_eq!(bvh.curve(&y).as_slice(), Some(&[2.0, 5.0][..]));
+    ///
+    /// // The frames are unchanged.
+    /// assert_eq!(bvh.frames().nth(1).unwrap().as_slice(), &[4.0, 5.0, 6.0]);
+    /// ```
+    #[inline]
+    pub fn set_layout(&mut self, layout: MotionLayout) {
+        self.motion.set_layout(layout);
     }
 
     /// Gets the motion value at `frame` and `Channel`.
@@ -555,18 +1194,46 @@ impl Bvh {
     ///
     /// This method will panic if `frame` is greater than `self.num_frames()`.
     #[inline]
-    pub fn get_motion(&self, frame: usize, channel: &Channel) -> f32 {
-        *self.frames().nth(frame).unwrap().index(channel)
+    pub fn get_motion(&self, frame: usize, channel: &Channel) -> Float {
+        *self.motion.value(frame, channel)
     }
 
     /// Returns the motion value at `frame` and `channel` if they are in bounds,
     /// `None` otherwise.
     #[inline]
-    pub fn try_get_motion(&self, frame: usize, channel: &Channel) -> Option<f32> {
-        self.frames()
-            .nth(frame)
-            .and_then(|f| f.get(channel))
-            .map(|m| *m)
+    pub fn try_get_motion(&self, frame: usize, channel: &Channel) -> Option<Float> {
+        self.motion
+            .value_index(frame, channel.motion_index)
+            .map(|index| self.motion.values[index])
+    }
+
+    /// Returns the translation and rotation of `joint` at `frame`.
+    ///
+    /// The rotation carries the `RotationOrder` of the rotation channels of the
+    /// joint. See [`JointMotionMut`][`JointMotionMut`] for an example.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if `frame` is greater than `self.num_frames()`, or if
+    /// `joint` is out of bounds.
+    ///
+    /// [`JointMotionMut`]: struct.JointMotionMut.html
+    #[inline]
+    pub fn joint_motion(&self, frame: usize, joint: JointIndex) -> JointMotion {
+        self.motion.joint_motion(frame, &self.skeleton[joint])
+    }
+
+    /// Returns a mutable view of the translation and rotation of `joint` at
+    /// `frame`, which writes the changed values back into the frame when it is
+    /// dropped.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if `frame` is greater than `self.num_frames()`, or if
+    /// `joint` is out of bounds.
+    #[inline]
+    pub fn joint_motion_mut(&mut self, frame: usize, joint: JointIndex) -> JointMotionMut<'_> {
+        self.motion.joint_motion_mut(frame, &self.skeleton[joint])
     }
 
     /// Updates the `motion` value at `frame` and `channel` to `new_motion`.
@@ -575,7 +1242,7 @@ impl Bvh {
     ///
     /// This method will panic if `frame` is greater than `self.num_frames()`.
     #[inline]
-    pub fn set_motion(&mut self, frame: usize, channel: &Channel, new_motion: f32) {
+    pub fn set_motion(&mut self, frame: usize, channel: &Channel, new_motion: Float) {
         self.try_set_motion(frame, channel, new_motion).unwrap();
     }
 
@@ -590,43 +1257,127 @@ impl Bvh {
         &mut self,
         frame: usize,
         channel: &'a Channel,
-        new_motion: f32,
+        new_motion: Float,
     ) -> Result<(), SetMotionError<'a>> {
-        let m = self
-            .frames_mut()
-            .nth(frame)
-            .ok_or(SetMotionError::BadFrame(frame))
-            .and_then(|f| {
-                f.get_mut(channel)
-                    .ok_or(SetMotionError::BadChannel(channel))
-            })?;
-
-        *m = new_motion;
+        if frame >= self.motion.num_frames {
+            return Err(SetMotionError::BadFrame(frame));
+        }
+        if channel.motion_index >= self.motion.num_channels {
+            return Err(SetMotionError::BadChannel(channel));
+        }
+
+        *self.motion.value_mut(frame, channel) = new_motion;
         Ok(())
     }
 
     /// Get the number of frames in the `Bvh`.
     #[inline]
     pub const fn num_frames(&self) -> usize {
-        self.num_frames
+        self.motion.num_frames
     }
 
     /// Get the number of channels in the `Bvh`.
     #[inline]
     pub const fn num_channels(&self) -> usize {
-        self.num_channels
+        self.motion.num_channels
     }
 
     /// Get the duration each frame should play for in the `Bvh`.
     #[inline]
     pub const fn frame_time(&self) -> &Duration {
-        &self.frame_time
+        self.motion.frame_time()
     }
 
     /// Set the duration each frame should play for in the `Bvh` to `new_frame_time`.
+    ///
+    /// The frame time is snapped to a standard frame rate if it is within a
+    /// nanosecond of one.
     #[inline]
     pub fn set_frame_time(&mut self, new_frame_time: Duration) {
-        self.frame_time = new_frame_time;
+        self.motion.set_frame_time(new_frame_time);
+    }
+
+    /// Get the exact frame rate of the `Bvh`.
+    #[inline]
+    pub const fn frame_rate(&self) -> FrameRate {
+        self.motion.frame_rate()
+    }
+
+    /// Set the frame rate of the `Bvh` to `new_frame_rate`.
+    #[inline]
+    pub fn set_frame_rate(&mut self, new_frame_rate: FrameRate) {
+        self.motion.set_frame_rate(new_frame_rate);
+    }
+
+    /// Get the formatting variations which were found when the `Bvh` was parsed.
+    ///
+    /// Use [`WriteOptions::with_dialect`][`WriteOptions::with_dialect`] to write
+    /// the `Bvh` back out in the same style.
+    ///
+    /// [`WriteOptions::with_dialect`]: write/struct.WriteOptions.html#method.with_dialect
+    #[inline]
+    pub const fn dialect(&self) -> &Dialect {
+        &self.dialect
+    }
+
+    /// Set the `Dialect` of the `Bvh` to `new_dialect`.
+    #[inline]
+    pub fn set_dialect(&mut self, new_dialect: Dialect) {
+        self.dialect = new_dialect;
+    }
+
+    /// Get the text encoding of the file which the `Bvh` was parsed from.
+    ///
+    /// The `Bvh` is always written out as `utf8` without a byte order mark.
+    #[inline]
+    pub const fn encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    /// Get the syntax tree of the file which the `Bvh` was loaded from.
+    ///
+    /// This is only available if the `Bvh` was loaded with
+    /// [`ParseOptions::preserve_syntax`][`ParseOptions::preserve_syntax`] set. While
+    /// it is available, writing the `Bvh` keeps the original text of every value
+    /// which has not been changed.
+    ///
+    /// [`ParseOptions::preserve_syntax`]: parse/struct.ParseOptions.html#structfield.preserve_syntax
+    #[inline]
+    pub fn syntax(&self) -> Option<&SyntaxTree> {
+        self.syntax.as_deref()
+    }
+
+    /// Discard the syntax tree of the `Bvh`, so that it is always written using
+    /// the formatting from `WriteOptions`.
+    #[inline]
+    pub fn clear_syntax(&mut self) {
+        self.syntax = None;
+    }
+}
+
+// The syntax tree, dialect and encoding only record how the file was formatted, so
+// two `Bvh`s with the same contents are equal even if they were loaded from
+// different text.
+impl PartialEq for Bvh {
+    fn eq(&self, other: &Self) -> bool {
+        self.skeleton == other.skeleton && self.motion == other.motion
+    }
+}
+
+impl Index<JointIndex> for Bvh {
+    type Output = Joint;
+    #[inline]
+    fn index(&self, index: JointIndex) -> &Self::Output {
+        &self.skeleton[index]
+    }
+}
+
+impl IndexMut<JointIndex> for Bvh {
+    #[inline]
+    fn index_mut(&mut self, index: JointIndex) -> &mut Self::Output {
+        self.skeleton_mut()
+            .joint_mut(index)
+            .expect("joint index out of bounds")
     }
 }
 
@@ -719,8 +1470,8 @@ pub enum ChannelType {
 }
 
 impl ChannelType {
-    /// Attempt to parse a bvh channel byte string into a `ChannelType`.
-    /// Returns `Err` if the string cannot be parsed.
+    /// Attempt to parse a bvh channel byte string into a `ChannelType`, ignoring
+    /// case. Returns `Err` if the string cannot be parsed.
     ///
     /// # Examples
     ///
@@ -730,6 +1481,10 @@ impl ChannelType {
     ///     ChannelType::from_bytes("Xrotation").unwrap(),
     ///     ChannelType::RotationX);
     ///
+    /// assert_eq!(
+    ///     ChannelType::from_bytes("xposition").unwrap(),
+    ///     ChannelType::PositionX);
+    ///
     /// let err = ChannelType::from_bytes("Hello").unwrap_err();
     /// assert_eq!(err.into_inner(), "Hello");
     /// ```
@@ -738,18 +1493,21 @@ impl ChannelType {
     where
         B: AsRef<[u8]> + ?Sized,
     {
-        let s = s.as_ref();
-        match s {
-            b"Xrotation" => Ok(ChannelType::RotationX),
-            b"Yrotation" => Ok(ChannelType::RotationY),
-            b"Zrotation" => Ok(ChannelType::RotationZ),
-
-            b"Xposition" => Ok(ChannelType::PositionX),
-            b"Yposition" => Ok(ChannelType::PositionY),
-            b"Zposition" => Ok(ChannelType::PositionZ),
+        const CHANNEL_TYPES: [ChannelType; 6] = [
+            ChannelType::RotationX,
+            ChannelType::RotationY,
+            ChannelType::RotationZ,
+            ChannelType::PositionX,
+            ChannelType::PositionY,
+            ChannelType::PositionZ,
+        ];
 
-            _ => Err(ParseChannelError::from(s)),
-        }
+        let s = s.as_ref();
+        CHANNEL_TYPES
+            .iter()
+            .find(|ty| ty.as_str().as_bytes().eq_ignore_ascii_case(s))
+            .cloned()
+            .ok_or_else(|| ParseChannelError::from(s))
     }
 
     /// Returns `true` if this channel corresponds to a rotational
@@ -926,7 +1684,7 @@ impl fmt::Display for Axis {
 /// An iterator over the frames of a `Bvh`.
 #[derive(Debug)]
 pub struct Frames<'a> {
-    motion_values: &'a [f32],
+    motion_values: &'a [Float],
     num_channels: usize,
     num_frames: usize,
     curr_frame: usize,
@@ -959,7 +1717,7 @@ impl<'a> Iterator for Frames<'a> {
 /// A mutable iterator over the frames of a `Bvh`.
 #[derive(Debug)]
 pub struct FramesMut<'a> {
-    motion_values: &'a mut [f32],
+    motion_values: &'a mut [Float],
     num_channels: usize,
     num_frames: usize,
     curr_frame: usize,
@@ -1013,7 +1771,7 @@ fn frames_iter_logic(
 
 /// A wrapper for a slice of motion values, so that they can be indexed by `Channel`.
 #[derive(PartialEq)]
-pub struct Frame([f32]);
+pub struct Frame([Float]);
 
 impl fmt::Debug for Frame {
     #[inline]
@@ -1024,13 +1782,13 @@ impl fmt::Debug for Frame {
 
 impl Frame {
     #[inline]
-    fn from_slice<'a>(frame_motions: &'a [f32]) -> &'a Frame {
-        unsafe { &*(frame_motions as *const [f32] as *const Frame) }
+    fn from_slice<'a>(frame_motions: &'a [Float]) -> &'a Frame {
+        unsafe { &*(frame_motions as *const [Float] as *const Frame) }
     }
 
     #[inline]
-    fn from_mut_slice<'a>(frame_motions: &'a mut [f32]) -> &'a mut Frame {
-        unsafe { &mut *(frame_motions as *mut [f32] as *mut Frame) }
+    fn from_mut_slice<'a>(frame_motions: &'a mut [Float]) -> &'a mut Frame {
+        unsafe { &mut *(frame_motions as *mut [Float] as *mut Frame) }
     }
 
     /// Returns the number of motion values in the `Frame`.
@@ -1047,30 +1805,30 @@ impl Frame {
     /// Returns a reference to the motion element corresponding to `Channel`, or `None`
     /// if out of bounds.
     #[inline]
-    pub fn get(&self, channel: &Channel) -> Option<&f32> {
+    pub fn get(&self, channel: &Channel) -> Option<&Float> {
         self.0.get(channel.motion_index)
     }
 
     /// Returns a mutable reference to the motion element corresponding to `Channel`,
     /// or `None` if out of bounds.
     #[inline]
-    pub fn get_mut(&mut self, channel: &Channel) -> Option<&mut f32> {
+    pub fn get_mut(&mut self, channel: &Channel) -> Option<&mut Float> {
         self.0.get_mut(channel.motion_index)
     }
 
-    /// Get the `Frame` as a slice of `f32` values.
-    pub fn as_slice(&self) -> &[f32] {
+    /// Get the `Frame` as a slice of `Float` values.
+    pub fn as_slice(&self) -> &[Float] {
         &self.0[..]
     }
 
-    /// Get the `Frame` as a mutable slice of `f32` values.
-    pub fn as_mut_slice(&mut self) -> &mut [f32] {
+    /// Get the `Frame` as a mutable slice of `Float` values.
+    pub fn as_mut_slice(&mut self) -> &mut [Float] {
         &mut self.0[..]
     }
 }
 
 impl Index<&Channel> for Frame {
-    type Output = f32;
+    type Output = Float;
     #[inline]
     fn index(&self, channel: &Channel) -> &Self::Output {
         self.0.index(channel.motion_index)
@@ -1083,14 +1841,3 @@ impl IndexMut<&Channel> for Frame {
     }
 }
 
-const NSEC_FACTOR: f64 = 1000_000_000.0;
-
-#[inline]
-fn fraction_seconds_to_duration(x: f64) -> Duration {
-    Duration::from_nanos((x * NSEC_FACTOR) as u64)
-}
-
-#[inline]
-fn duation_to_fractional_seconds(duration: &Duration) -> f64 {
-    duration.subsec_nanos() as f64 / NSEC_FACTOR
-}
@@ -1,4 +1,4 @@
-use crate::{fraction_seconds_to_duration, joint::Joint, Bvh, Channel, ChannelType};
+use crate::{joint::Joint, Bvh, Channel, ChannelType, Float, FrameRate};
 
 #[doc(hidden)]
 #[macro_export]
@@ -38,7 +38,11 @@ macro_rules! match_channels {
 #[macro_export]
 macro_rules! parse_offset {
     ($builder:ident ($ofst_x:literal $ofst_y:literal $ofst_z:literal)) => {
-        let offset = [f32::from($ofst_x), f32::from($ofst_y), f32::from($ofst_z)];
+        let offset = [
+            $crate::Float::from($ofst_x),
+            $crate::Float::from($ofst_y),
+            $crate::Float::from($ofst_z),
+        ];
         $builder.push_joint_offset(offset.into(), false);
     };
 }
@@ -214,9 +218,9 @@ macro_rules! parse_joints_internal {
         }
     )) => {
         let offset = [
-            f32::from($end_x),
-            f32::from($end_y),
-            f32::from($end_z),
+            $crate::Float::from($end_x),
+            $crate::Float::from($end_y),
+            $crate::Float::from($end_z),
         ];
 
         $builder.push_joint_offset(offset.into() , true);
@@ -388,7 +392,7 @@ macro_rules! bvh {
             builder.set_num_frames($num_frames as usize);
             builder.set_frame_time(f64::from($frame_time));
 
-            builder.set_motion_values(vec![ $( f32::from($motion) ),+ ]);
+            builder.set_motion_values(vec![ $( $crate::Float::from($motion) ),+ ]);
 
             assert!(builder.check_valid_motion());
 
@@ -422,7 +426,7 @@ impl BvhLiteralBuilder {
     pub fn push_root(&mut self, name: &str) {
         let mut root = Joint::default();
         root.name = From::from(name.as_bytes());
-        self.bvh.joints.push(root);
+        self.bvh.skeleton_mut().joints.push(root);
         self.current_index += 1;
     }
 
@@ -440,14 +444,19 @@ impl BvhLiteralBuilder {
         }
 
         let dpth = self.current_depth;
-        let parent = get_parent_index(&self.bvh.joints[..], dpth);
+        let parent = get_parent_index(&self.bvh.skeleton.joints[..], dpth);
 
         let mut joint = Joint::default();
         joint.name = From::from(name.as_bytes());
         joint.parent_index = Some(parent);
         joint.depth = dpth;
 
-        self.bvh.joints.push(joint);
+        let joints = &mut self.bvh.skeleton_mut().joints;
+        // Only joints without children may have an end site, as in `JointsBuilder`.
+        if let Some(parent) = joints.get_mut(parent) {
+            parent.end_site = None;
+        }
+        joints.push(joint);
 
         self.current_index += 1;
     }
@@ -458,7 +467,7 @@ impl BvhLiteralBuilder {
         self.current_channel_index += 1;
     }
 
-    pub fn push_joint_offset(&mut self, offset: mint::Vector3<f32>, is_end_site: bool) {
+    pub fn push_joint_offset(&mut self, offset: mint::Vector3<Float>, is_end_site: bool) {
         self.last_joint().map(|joint| {
             if is_end_site {
                 joint.end_site = Some(offset);
@@ -471,38 +480,41 @@ impl BvhLiteralBuilder {
     #[inline]
     pub fn set_frame_time(&mut self, frame_time_secs: f64) {
         self.bvh
-            .set_frame_time(fraction_seconds_to_duration(frame_time_secs));
+            .set_frame_rate(FrameRate::from_secs_f64(frame_time_secs));
     }
 
     #[inline]
     pub fn set_num_frames(&mut self, num_frames: usize) {
         self.num_frames = num_frames;
-        self.bvh.num_channels = self.current_channel_index;
-        self.bvh.num_frames = self.num_frames;
+        self.bvh.skeleton_mut().num_channels = self.current_channel_index;
+        self.bvh.motion.num_channels = self.current_channel_index;
+        self.bvh.motion.num_frames = self.num_frames;
         self.bvh
-            .motion_values
+            .motion
+            .values
             .reserve(self.current_channel_index * self.num_frames);
     }
 
     #[inline]
-    pub fn set_motion_values(&mut self, motion_values: Vec<f32>) {
-        self.bvh.motion_values = motion_values;
+    pub fn set_motion_values(&mut self, motion_values: Vec<Float>) {
+        self.bvh.motion.values = motion_values;
     }
 
     #[inline]
     pub fn check_valid_motion(&self) -> bool {
-        self.bvh.motion_values.len() == self.bvh.num_channels * self.bvh.num_frames
+        self.bvh.motion.values.len() == self.bvh.motion.num_channels * self.bvh.motion.num_frames
     }
 
     #[inline]
     fn last_joint(&mut self) -> Option<&mut Joint> {
-        self.bvh.joints.last_mut()
+        self.bvh.skeleton_mut().joints.last_mut()
     }
 }
 
 #[cfg(test)]
 mod tests {
     // Needed for macros
+    use crate::FrameRate;
     use std::time::Duration;
 
     #[test]
@@ -531,10 +543,10 @@ mod tests {
         };
 
         {
-            use super::{ChannelType, Joint};
+            use super::{ChannelType, Float, Joint};
             use mint::Vector3;
 
-            fn check_joint<V0: Into<Vector3<f32>>, V1: Into<Vector3<f32>>, O: Into<Option<V1>>>(
+            fn check_joint<V0: Into<Vector3<Float>>, V1: Into<Vector3<Float>>, O: Into<Option<V1>>>(
                 joint: &Joint,
                 expected_name: &[u8],
                 expected_offset: V0,
@@ -557,7 +569,7 @@ mod tests {
 
             let mut joints = bvh.joints();
 
-            check_joint::<[_; 3], [f32; 3], _>(
+            check_joint::<[_; 3], [Float; 3], _>(
                 joints.next().unwrap(),
                 b"Base",
                 [0.0, 0.0, 0.0],
@@ -586,6 +598,7 @@ mod tests {
         }
 
         assert_eq!(*bvh.frame_time(), Duration::from_nanos(33333333));
+        assert_eq!(bvh.frame_rate(), FrameRate::FPS_30);
 
         let frames = bvh.frames();
         assert_eq!(frames.len(), 1);
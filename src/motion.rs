@@ -0,0 +1,347 @@
+use crate::{Channel, Curve, CurveMut, Float, FrameRate, Frames, FramesMut};
+use std::{fmt, sync::OnceLock, time::Duration};
+
+/// The order in which the motion values of a [`Motion`][`Motion`] are stored.
+///
+/// [`Motion`]: struct.Motion.html
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MotionLayout {
+    /// The values of each frame are stored together, one frame after another.
+    /// This is the order of the values in a `bvh` file, and is the default.
+    FrameMajor,
+    /// The values of each channel are stored together, one channel after
+    /// another, so that work on a single [`Curve`][`Curve`] reads contiguous
+    /// memory.
+    ///
+    /// A channel-major `Motion` can still be iterated over by frame: the first
+    /// call to `frames()` stores a frame-major copy of the values, which is
+    /// kept until they are next changed.
+    ///
+    /// [`Curve`]: struct.Curve.html
+    ChannelMajor,
+}
+
+impl Default for MotionLayout {
+    #[inline]
+    fn default() -> Self {
+        MotionLayout::FrameMajor
+    }
+}
+
+/// The frames of a single animation clip.
+///
+/// A `Motion` holds the frame time and the motion values of a `Bvh`, but not its
+/// joints, which are kept in a [`Skeleton`][`Skeleton`]. The values of each frame
+/// are laid out in the order of the channels of the skeleton.
+///
+/// The values are stored one frame after another unless the `Motion` is switched
+/// to a channel-major [`MotionLayout`][`MotionLayout`] with
+/// [`Motion::set_layout`][`Motion::set_layout`], which speeds up work on whole
+/// curves such as filtering.
+///
+/// [`Skeleton`]: struct.Skeleton.html
+/// [`MotionLayout`]: enum.MotionLayout.html
+/// [`Motion::set_layout`]: #method.set_layout
+#[derive(Clone, Default)]
+pub struct Motion {
+    /// The motion values of every frame, in the order given by `layout`.
+    pub(crate) values: Vec<Float>,
+    /// The order in which `values` are stored.
+    pub(crate) layout: MotionLayout,
+    /// A frame-major copy of `values` when the layout is channel-major, so that
+    /// the frames can be borrowed. This must be cleared whenever `values` change.
+    pub(crate) frame_major: OnceLock<Vec<Float>>,
+    /// The number of frames in the motion.
+    pub(crate) num_frames: usize,
+    /// The number of values in each frame.
+    pub(crate) num_channels: usize,
+    /// The exact frame rate of the motion.
+    pub(crate) frame_rate: FrameRate,
+    /// The total time it takes to play one frame, kept in sync with `frame_rate`.
+    pub(crate) frame_time: Duration,
+}
+
+impl Motion {
+    /// Create a `Motion` with no frames, for a skeleton with `num_channels`
+    /// channels.
+    #[inline]
+    pub fn new(num_channels: usize, frame_rate: FrameRate) -> Self {
+        Motion {
+            values: Vec::new(),
+            layout: MotionLayout::FrameMajor,
+            frame_major: OnceLock::new(),
+            num_frames: 0,
+            num_channels,
+            frame_rate,
+            frame_time: frame_rate.frame_time(),
+        }
+    }
+
+    /// Append a frame to the end of the motion.
+    ///
+    /// In a channel-major motion this moves the values of every curve but the
+    /// last, so frames should be pushed before switching the layout.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `frame` is not the number of channels.
+    pub fn push_frame(&mut self, frame: &[Float]) {
+        assert_eq!(frame.len(), self.num_channels);
+        match self.layout {
+            MotionLayout::FrameMajor => self.values.extend_from_slice(frame),
+            MotionLayout::ChannelMajor => {
+                // Insert from the last curve back, so that the curves before it
+                // have not moved yet.
+                for (channel, &value) in frame.iter().enumerate().rev() {
+                    self.values.insert((channel + 1) * self.num_frames, value);
+                }
+                self.frame_major.take();
+            }
+        }
+        self.num_frames += 1;
+    }
+
+    /// Get the order in which the motion values are stored.
+    #[inline]
+    pub fn layout(&self) -> MotionLayout {
+        self.layout
+    }
+
+    /// Store the motion values in the order given by `layout`, moving them if
+    /// the layout changes.
+    pub fn set_layout(&mut self, layout: MotionLayout) {
+        if layout == self.layout {
+            return;
+        }
+
+        self.values = match layout {
+            MotionLayout::FrameMajor => self
+                .frame_major
+                .take()
+                .unwrap_or_else(|| transpose(&self.values, self.num_channels, self.num_frames)),
+            MotionLayout::ChannelMajor => {
+                transpose(&self.values, self.num_frames, self.num_channels)
+            }
+        };
+        self.layout = layout;
+    }
+
+    /// Returns a `Frames` iterator over the frames of the motion.
+    ///
+    /// If the motion is channel-major, a frame-major copy of the values is made
+    /// the first time this is called after they change.
+    #[inline]
+    pub fn frames(&self) -> Frames<'_> {
+        let motion_values = match self.layout {
+            MotionLayout::FrameMajor => &self.values[..],
+            MotionLayout::ChannelMajor => &self
+                .frame_major
+                .get_or_init(|| transpose(&self.values, self.num_channels, self.num_frames))[..],
+        };
+        Frames {
+            motion_values,
+            num_channels: self.num_channels,
+            num_frames: self.num_frames,
+            curr_frame: 0,
+        }
+    }
+
+    /// Returns a mutable iterator over the frames of the motion.
+    ///
+    /// The values of a frame must be stored together to be borrowed mutably, so
+    /// a channel-major motion is first switched back to
+    /// [`MotionLayout::FrameMajor`][`MotionLayout::FrameMajor`].
+    ///
+    /// [`MotionLayout::FrameMajor`]: enum.MotionLayout.html#variant.FrameMajor
+    #[inline]
+    pub fn frames_mut(&mut self) -> FramesMut<'_> {
+        self.set_layout(MotionLayout::FrameMajor);
+        FramesMut {
+            motion_values: &mut self.values[..],
+            num_channels: self.num_channels,
+            num_frames: self.num_frames,
+            curr_frame: 0,
+        }
+    }
+
+    /// Returns the values of `channel` in every frame as a [`Curve`][`Curve`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of bounds of the motion.
+    ///
+    /// [`Curve`]: struct.Curve.html
+    #[inline]
+    pub fn curve(&self, channel: &Channel) -> Curve<'_> {
+        let (start, stride) = self.curve_start_and_stride(channel);
+        Curve::new(&self.values, start, stride, self.num_frames)
+    }
+
+    /// Returns the values of `channel` in every frame as a [`CurveMut`][`CurveMut`],
+    /// which can change them in place.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of bounds of the motion.
+    ///
+    /// [`CurveMut`]: struct.CurveMut.html
+    #[inline]
+    pub fn curve_mut(&mut self, channel: &Channel) -> CurveMut<'_> {
+        let (start, stride) = self.curve_start_and_stride(channel);
+        self.frame_major.take();
+        CurveMut::new(&mut self.values, start, stride, self.num_frames)
+    }
+
+    fn curve_start_and_stride(&self, channel: &Channel) -> (usize, usize) {
+        assert!(
+            channel.motion_index < self.num_channels,
+            "channel out of bounds of the motion"
+        );
+        match self.layout {
+            MotionLayout::FrameMajor => (channel.motion_index, self.num_channels),
+            MotionLayout::ChannelMajor => (channel.motion_index * self.num_frames, 1),
+        }
+    }
+
+    /// The index in `values` of the value of the channel at `motion_index` in
+    /// `frame`, or `None` if either is out of bounds.
+    #[inline]
+    pub(crate) fn value_index(&self, frame: usize, motion_index: usize) -> Option<usize> {
+        if frame >= self.num_frames || motion_index >= self.num_channels {
+            return None;
+        }
+        match self.layout {
+            MotionLayout::FrameMajor => Some(frame * self.num_channels + motion_index),
+            MotionLayout::ChannelMajor => Some(motion_index * self.num_frames + frame),
+        }
+    }
+
+    /// The value of `channel` at `frame`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame` or `channel` is out of bounds of the motion.
+    #[inline]
+    pub(crate) fn value(&self, frame: usize, channel: &Channel) -> &Float {
+        let index = self.value_index(frame, channel.motion_index);
+        &self.values[index.expect("motion value out of bounds")]
+    }
+
+    /// The value of `channel` at `frame`, mutably.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame` or `channel` is out of bounds of the motion.
+    #[inline]
+    pub(crate) fn value_mut(&mut self, frame: usize, channel: &Channel) -> &mut Float {
+        let index = self.value_index(frame, channel.motion_index);
+        self.frame_major.take();
+        &mut self.values[index.expect("motion value out of bounds")]
+    }
+
+    /// Call `f` with the values of each frame in turn, in either layout.
+    pub(crate) fn for_each_frame_mut(&mut self, mut f: impl FnMut(&mut [Float])) {
+        match self.layout {
+            MotionLayout::FrameMajor => {
+                for frame in self.values.chunks_exact_mut(self.num_channels) {
+                    f(frame);
+                }
+            }
+            MotionLayout::ChannelMajor => {
+                let mut frame = vec![0.0; self.num_channels];
+                for index in 0..self.num_frames {
+                    let curves = self.values[index..].iter().step_by(self.num_frames);
+                    for (value, &curve_value) in frame.iter_mut().zip(curves) {
+                        *value = curve_value;
+                    }
+                    f(&mut frame);
+                    let curves = self.values[index..].iter_mut().step_by(self.num_frames);
+                    for (curve_value, &value) in curves.zip(&frame) {
+                        *curve_value = value;
+                    }
+                }
+                self.frame_major.take();
+            }
+        }
+    }
+
+    /// Get the number of frames in the motion.
+    #[inline]
+    pub const fn num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    /// Get the number of values in each frame of the motion.
+    #[inline]
+    pub const fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    /// Get the duration each frame should play for.
+    #[inline]
+    pub const fn frame_time(&self) -> &Duration {
+        &self.frame_time
+    }
+
+    /// Set the duration each frame should play for to `new_frame_time`.
+    ///
+    /// The frame time is snapped to a standard frame rate if it is within a
+    /// nanosecond of one.
+    #[inline]
+    pub fn set_frame_time(&mut self, new_frame_time: Duration) {
+        self.set_frame_rate(FrameRate::from_frame_time(new_frame_time));
+    }
+
+    /// Get the exact frame rate of the motion.
+    #[inline]
+    pub const fn frame_rate(&self) -> FrameRate {
+        self.frame_rate
+    }
+
+    /// Set the frame rate of the motion to `new_frame_rate`.
+    #[inline]
+    pub fn set_frame_rate(&mut self, new_frame_rate: FrameRate) {
+        self.frame_rate = new_frame_rate;
+        self.frame_time = new_frame_rate.frame_time();
+    }
+}
+
+impl fmt::Debug for Motion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Motion")
+            .field("values", &self.values)
+            .field("layout", &self.layout)
+            .field("num_frames", &self.num_frames)
+            .field("num_channels", &self.num_channels)
+            .field("frame_rate", &self.frame_rate)
+            .field("frame_time", &self.frame_time)
+            .finish()
+    }
+}
+
+impl PartialEq for Motion {
+    /// Motions are equal if they have the same frames, whatever their layouts.
+    fn eq(&self, other: &Self) -> bool {
+        let same_values = if self.layout == other.layout {
+            self.values == other.values
+        } else {
+            self.frames().eq(other.frames())
+        };
+        self.num_frames == other.num_frames
+            && self.num_channels == other.num_channels
+            && self.frame_rate == other.frame_rate
+            && same_values
+    }
+}
+
+/// Transpose `values`, which are `num_rows` rows of `num_columns` values each.
+fn transpose(values: &[Float], num_rows: usize, num_columns: usize) -> Vec<Float> {
+    let mut transposed = Vec::with_capacity(values.len());
+    if values.is_empty() {
+        return transposed;
+    }
+    for column in 0..num_columns {
+        transposed.extend(values[column..].iter().step_by(num_columns).take(num_rows));
+    }
+    transposed
+}
@@ -1,616 +1,2447 @@
-#![allow(unused)]
+//! Contains options for `bvh` file parsing.
 
-use bstr::ByteSlice;
+use bstr::{io::BufReadExt, ByteSlice};
 use crate::{
-    errors::{LoadJointsError, LoadMotionError},
-    fraction_seconds_to_duration, Axis, Bvh, Channel, ChannelType, EnumeratedLines, Joint,
-    JointName,
+    encoding::StreamDecoder,
+    errors::{LoadError, LoadJointsError, LoadMotionError, Span},
+    tokenizer::{Line, Lines, Token},
+    Axis, Bvh, Channel, ChannelType, Float, Frame, FrameRate, Joint, Skeleton,
 };
-use lexical::{parse, try_parse};
+use lexical::try_parse;
 use mint::Vector3;
-use nom::{
-    alt, char, delimited, digit, do_parse, map, map_res, named, opt, pair, recognize, space, tag,
-    take_while, try_parse, ws, Err as NomErr, IResult,
+use smallvec::SmallVec;
+use std::{
+    borrow::Cow,
+    cmp,
+    convert::TryFrom,
+    fmt,
+    mem,
+    slice::Iter,
+    str,
+    sync::Arc,
+    time::Duration,
 };
-use smallvec::{smallvec, SmallVec};
-use std::{convert::TryFrom, mem, str};
-
-named! {
-    unsigned_float(&[u8]) -> f64,
-    map!(
-        recognize!(alt!(
-            delimited!(digit, tag!("."), opt!(digit))
-                | delimited!(opt!(digit), tag!("."), digit)
-        )),
-        lexical::parse
-    )
-}
-
-named! {
-    float(&[u8]) -> f64,
-    map!(
-        pair!(
-            opt!(alt!(tag!("+") | tag!("-"))),
-            unsigned_float
-        ),
-        |(sign, value): (Option<&[u8]>, f64)| {
-            sign.and_then(|s|
-                if s[0] == (b'-') {
-                    Some(-1f64)
-                } else {
-                    None
-                })
-                .unwrap_or(1f64) * value
-        }
-    )
-}
-
-named! {
-    unsigned_int(&[u8]) -> u64,
-    map!(
-        recognize!(
-            delimited!(space, digit, space)
-        ),
-        lexical::parse
-    )
-}
-
-named! {
-    token(&[u8]) -> &[u8],
-    delimited!(
-        space,
-        take_while!(|ch| char::from(ch).is_alphanumeric()),
-        space
-    )
-}
-
-named!(single_channel(&[u8]) -> ChannelType,
-    map_res!(
-        alt!(
-            tag!("Xposition") |
-            tag!("Yposition") |
-            tag!("Zposition") |
-            tag!("Xrotation") |
-            tag!("Yrotation") |
-            tag!("Zrotation")
-        ),
-        ChannelType::from_bytes
-    )
-);
-
-named! {
-    offset(&[u8]) -> Vector3<f32>,
-    ws!(
-        do_parse!(
-            tag!(b"OFFSET") >>
-            x: float >>
-            y: float >>
-            z: float >>
-            (Vector3 { x: x as f32, y: y as f32, z: z as f32 })
-        )
-    )
-}
-
-named! {
-    end_site(&[u8]) -> Vector3<f32>,
-    ws!(
-        do_parse!(
-            tag!(b"End") >>
-            tag!(b"Site") >>
-            end_site: delimited!(char!('{'), offset, char!('}')) >>
-            (end_site)
-        )
-    )
-}
-/*
-fn heirarchy<'a>(data: &'a [u8]) -> IResult<&'a [u8], Vec<JointData>> {
-    let mut joints = Vec::new();
-    let mut num_channels = 0usize;
-    let mut joint_index = 0usize;
-
-    let mut channels = |data: &'a [u8]| -> IResult<&'a [u8], SmallVec<[Channel; 6]>> {
-        let (mut data, expected_num_channels) = do_parse!(
-            data,
-            tag!(b"CHANNELS") >> num_channels: unsigned_int >> (num_channels)
-        )?;
-
-        let mut out_channels = smallvec![];
-        for i in 0..expected_num_channels {
-            let (new_data, channel_ty) = single_channel(data)?;
-            let channel = Channel::new(channel_ty, num_channels);
-            num_channels += 1;
-            data = new_data;
-        }
-
-        Ok((&*data, out_channels))
-    };
 
-    struct JointMembers {
-        name: JointName,
-        offset: Vector3<f32>,
-        channels: SmallVec<[Channel; 6]>,
-        end_site: Option<Vector3<f32>>,
+#[cfg(feature = "async")]
+use futures_util::io::{AsyncBufRead, AsyncBufReadExt};
+#[cfg(feature = "async")]
+use std::io;
+
+/// Specify how a `bvh` file should be parsed.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub struct ParseOptions {
+    /// How strictly the file should be checked for errors.
+    pub policy: ParsePolicy,
+    /// When only the header is parsed, count the lines in the motion section
+    /// to check the number of frames.
+    pub count_frames: bool,
+    /// Keep the original text of the file in a [`SyntaxTree`][`SyntaxTree`], so
+    /// that writing the `Bvh` again only re-formats the values which were changed.
+    ///
+    /// This is used by [`parse`][`ParseOptions::parse`] and
+    /// [`parse_bytes`][`ParseOptions::parse_bytes`], and keeps a copy of the whole
+    /// file in memory.
+    ///
+    /// [`SyntaxTree`]: ../syntax/struct.SyntaxTree.html
+    /// [`ParseOptions::parse`]: struct.ParseOptions.html#method.parse
+    /// [`ParseOptions::parse_bytes`]: struct.ParseOptions.html#method.parse_bytes
+    pub preserve_syntax: bool,
+    /// Limits on the size of the file, for parsing untrusted input.
+    pub limits: Limits,
+}
+
+impl Default for ParseOptions {
+    #[inline]
+    fn default() -> Self {
+        ParseOptions {
+            policy: Default::default(),
+            count_frames: false,
+            preserve_syntax: false,
+            limits: Limits::new(),
+        }
     }
+}
 
-    let joint_members = |mut data: &'a [u8]| -> IResult<&'a [u8], JointMembers> {
-        let (new_data, name) =
-            do_parse!(data, tag!(b"Joint") >> joint_name: token >> (joint_name))?;
+impl ParseOptions {
+    /// Create a new `ParseOptions` with default values.
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
 
-        let mut offset_and_channels =
-            |data: &'a [u8]| -> IResult<&'a [u8], (Vector3<f32>, SmallVec<[Channel; 6]>)> {
-                do_parse!(data, offst: offset >> chans: channels >> (offst, chans))
-            };
+    /// Sets `policy` on `self` to the new `ParsePolicy`.
+    #[inline]
+    pub const fn with_policy(self, policy: ParsePolicy) -> Self {
+        ParseOptions { policy, ..self }
+    }
 
-        let opt_end_site = |data: &'a [u8]| -> IResult<&'a [u8], Option<Vector3<f32>>> {
-            match end_site(data) {
-                Ok((data, site)) => Ok((data, Some(site))),
-                Err(NomErr::Error(_)) => Ok((data, None)),
-                // @TODO: is this right?
-                Err(e) => Err(e),
-            }
-        };
+    /// Sets `count_frames` on `self` to the new `count_frames`.
+    #[inline]
+    pub const fn with_count_frames(self, count_frames: bool) -> Self {
+        ParseOptions {
+            count_frames,
+            ..self
+        }
+    }
 
-        let (data, members) = do_parse!(
-            data,
-            name: token
-                >> char!('{')
-                >> offst_and_chans: offset_and_channels
-                >> end_site: opt_end_site
-                >> (name, offst_and_chans.0, offst_and_chans.1, end_site)
-        )?;
-
-        let members = JointMembers {
-            name: JointName::from(members.0),
-            offset: members.1,
-            channels: members.2,
-            end_site: members.3,
-        };
+    /// Sets `preserve_syntax` on `self` to the new `preserve_syntax`.
+    #[inline]
+    pub const fn with_preserve_syntax(self, preserve_syntax: bool) -> Self {
+        ParseOptions {
+            preserve_syntax,
+            ..self
+        }
+    }
 
-        Ok((data, members))
-    };
+    /// Sets `limits` on `self` to the new `Limits`.
+    #[inline]
+    pub const fn with_limits(self, limits: Limits) -> Self {
+        ParseOptions { limits, ..self }
+    }
 
-    /*
-    try_parse!(data,
-        do_parse!(
-            tag!("HIERARCHY") >>
-            opt!(
-                tag!("Root") >>
-                root_joint_name: token >>
-                char!('{') >>
-                root_offset: offset >>
-                root_channels: channels >>
-                char!('}') >>
-                ()
-            ) >>
-            ()
-        )
-    );
-    */
-Ok((data, joints))
-}
-
-named! {
-frame_metadata(&[u8]) -> (std::time::Duration, usize),
-ws!(do_parse!(
-tag!("Frames") >>
-char!(':') >>
-num_frames: unsigned_int >>
-tag!("Frame") >>
-tag!("Time") >>
-char!(':') >>
-frame_time: unsigned_float >>
-(fraction_seconds_to_duration(frame_time), num_frames as usize)
-))
-}
-
-fn motion_section(
-data: &[u8],
-num_channels: usize,
-) -> IResult<&[u8], (std::time::Duration, usize, Vec<f32>)> {
-let (frame_time, num_frames) = frame_metadata(data)?;
-let motion_data = Vec::with_capacity(num_channels * num_frames);
-
-for f in 0..num_frames {
-let chomped = do_parse!(data, take_while!(|ch| char::from(ch).is_ascii_whitespace()));
-let (output, motion_val) = float(chomped);
-motion_data.push(motion_val);
-data = output;
-}
-
-Ok((data, (frame_time, num_frames, motion_data)))
-}
-*/
+    /// Loads the `Bvh` from the `reader` with the given options.
+    ///
+    /// Returns the `Bvh` along with any `ParseWarning`s for problems
+    /// which were repaired while parsing.
+    pub fn parse<R: BufReadExt>(
+        &self,
+        mut reader: R,
+    ) -> Result<(Bvh, Vec<ParseWarning>), LoadError> {
+        Bvh::from_reader_with_options_(reader.by_ref(), self, None)
+    }
 
-impl Bvh {
-    // @TODO: Remove panics
-    /// Logic for parsing the data from a `BufRead`.
-    pub(crate) fn read_joints(
-        &mut self,
-        lines: &mut EnumeratedLines<'_>,
-    ) -> Result<(), LoadJointsError> {
-        const HEIRARCHY_KEYWORD: &[u8] = b"HIERARCHY";
-
-        const ROOT_KEYWORD: &[u8] = b"ROOT";
-        const JOINT_KEYWORD: &[u8] = b"JOINT";
-        const ENDSITE_KEYWORDS: &[&[u8]] = &[b"End", b"Site"];
-
-        const OPEN_BRACE: &[u8] = b"{";
-        const CLOSE_BRACE: &[u8] = b"}";
-
-        const OFFSET_KEYWORD: &[u8] = b"OFFSET";
-        const CHANNELS_KEYWORD: &[u8] = b"CHANNELS";
-
-        #[derive(Debug, Eq, PartialEq)]
-        enum ParseMode {
-            NotStarted,
-            InHeirarchy,
-            Finished,
-        }
-
-        #[allow(unused)]
-        #[derive(Eq, PartialEq)]
-        enum NextExpectedLine {
-            Hierarchy,
-            Channels,
-            Offset,
-            OpeningBrace,
-            ClosingBrace,
-            JointName,
-            RootName,
-        }
-
-        let mut joints = vec![];
-        let mut curr_mode = ParseMode::NotStarted;
-        let mut curr_channel = 0usize;
-        let (mut curr_index, mut curr_depth) = (0usize, 0usize);
-        let mut next_expected_line = NextExpectedLine::Hierarchy;
-
-        let mut curr_joint = Joint::default();
-        let mut in_end_site = false;
-        let mut pushed_end_site_joint = false;
-
-        #[inline]
-        fn get_parent_index(joints: &[Joint], for_depth: usize) -> Option<usize> {
-            joints
-                .iter()
-                .enumerate()
-                .rev()
-                .find(|(_, jd)| jd.depth == for_depth.saturating_sub(2))
-                .map(|(i, _)| i)
-        }
-
-        for (line_num, line) in lines {
-            let line = line?;
-            let line = line.trim();
-
-            let mut tokens = line.fields_with(|c: char| c.is_ascii_whitespace() || c == ':');
-
-            let first_token = match tokens.next() {
-                Some(tok) => tok,
-                None => continue,
-            };
+    /// Parse a sequence of bytes as if it were an in-memory `Bvh` file, with
+    /// the given options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::parse::{ParseOptions, ParsePolicy, ParseWarning};
+    /// let bvh_string = br#"
+    ///     HIERARCHY
+    ///     ROOT Hips
+    ///     {
+    ///         OFFSET 0.0 0.0 0.0
+    ///         CHANNELS 3 Xposition Yposition Zposition
+    ///         End Site
+    ///         {
+    ///             OFFSET 0.0 0.0 0.0
+    ///         }
+    ///     }
+    ///     MOTION
+    ///     Frames: 1
+    ///     Frame Time: 0.033333333
+    ///     0.0 0.0
+    /// "#;
+    ///
+    /// let (bvh, warnings) = ParseOptions::new()
+    ///     .with_policy(ParsePolicy::Lenient)
+    ///     .parse_bytes(&bvh_string[..])?;
+    ///
+    /// assert_eq!(bvh.frames().next().unwrap().as_slice(), &[0.0, 0.0, 0.0]);
+    /// match warnings[..] {
+    ///     [ParseWarning::FramePadded { expected: 3, actual: 2, .. }] => {}
+    ///     _ => panic!("unexpected warnings: {:?}", warnings),
+    /// }
+    /// # Result::<(), bvh_anim::errors::LoadError>::Ok(())
+    /// ```
+    #[inline]
+    pub fn parse_bytes<B: AsRef<[u8]>>(
+        &self,
+        bytes: B,
+    ) -> Result<(Bvh, Vec<ParseWarning>), LoadError> {
+        Bvh::from_bytes_with_options_(bytes.as_ref(), self, None)
+    }
 
-            match first_token.as_bytes() {
-                HEIRARCHY_KEYWORD => {
-                    if curr_mode != ParseMode::NotStarted {
-                        panic!("Unexpected hierarchy");
-                    }
-                    curr_mode = ParseMode::InHeirarchy;
-                    next_expected_line = NextExpectedLine::RootName;
+    /// Loads a further clip of an existing skeleton from the `reader` with the
+    /// given options.
+    ///
+    /// See [`Bvh::from_bytes_with_skeleton`][`Bvh::from_bytes_with_skeleton`].
+    ///
+    /// [`Bvh::from_bytes_with_skeleton`]: ../struct.Bvh.html#method.from_bytes_with_skeleton
+    pub fn parse_with_skeleton<R: BufReadExt>(
+        &self,
+        mut reader: R,
+        skeleton: &Arc<Skeleton>,
+    ) -> Result<(Bvh, Vec<ParseWarning>), LoadError> {
+        Bvh::from_reader_with_options_(reader.by_ref(), self, Some(skeleton))
+    }
+
+    /// Loads a further clip of an existing skeleton from the in-memory `bytes`
+    /// with the given options.
+    ///
+    /// See [`Bvh::from_bytes_with_skeleton`][`Bvh::from_bytes_with_skeleton`].
+    ///
+    /// [`Bvh::from_bytes_with_skeleton`]: ../struct.Bvh.html#method.from_bytes_with_skeleton
+    #[inline]
+    pub fn parse_bytes_with_skeleton<B: AsRef<[u8]>>(
+        &self,
+        bytes: B,
+        skeleton: &Arc<Skeleton>,
+    ) -> Result<(Bvh, Vec<ParseWarning>), LoadError> {
+        Bvh::from_bytes_with_options_(bytes.as_ref(), self, Some(skeleton))
+    }
+
+    /// Loads only the skeleton and motion header from the `reader`, without
+    /// parsing any of the motion values.
+    ///
+    /// If `count_frames` is set, the remaining lines are counted to check the
+    /// number of frames. Otherwise, the number of frames is taken from the header.
+    pub fn parse_header<R: BufReadExt>(
+        &self,
+        reader: R,
+    ) -> Result<(Header, Vec<ParseWarning>), LoadError> {
+        let mut frame_reader = FrameReader::with_options(reader, self.clone())?;
+        if self.count_frames {
+            frame_reader.skip_frames()?;
+            frame_reader.header.num_frames = frame_reader.num_frames_read();
+        }
+        Ok((frame_reader.header, frame_reader.warnings))
+    }
+
+    /// Loads the `Bvh` from the asynchronous `reader` with the given options.
+    ///
+    /// The file is parsed with the same rules as [`ParseOptions::parse`][`ParseOptions::parse`].
+    ///
+    /// [`ParseOptions::parse`]: struct.ParseOptions.html#method.parse
+    #[cfg(feature = "async")]
+    pub async fn parse_async<R: AsyncBufRead + Unpin>(
+        &self,
+        mut reader: R,
+    ) -> Result<(Bvh, Vec<ParseWarning>), LoadError> {
+        async fn next_line<R: AsyncBufRead + Unpin>(
+            reader: &mut R,
+            decoder: &mut StreamDecoder,
+            line: &mut Vec<u8>,
+        ) -> io::Result<bool> {
+            line.clear();
+            loop {
+                let input = reader.fill_buf().await?;
+                if input.is_empty() {
+                    decoder.finish(line);
+                    break;
                 }
-                ROOT_KEYWORD => {
-                    if curr_mode != ParseMode::InHeirarchy
-                        || next_expected_line != NextExpectedLine::RootName
-                    {
-                        panic!("Unexpected root: {:?}", curr_mode);
-                    }
 
-                    if let Some(name) = tokens.next() {
-                        curr_joint.name = From::from(name);
-                    } else {
-                        panic!("Missing root name!");
-                    }
+                let (consumed, found_newline) = decoder.decode_line(input, line);
+                reader.consume_unpin(consumed);
+                if found_newline {
+                    break;
                 }
-                OPEN_BRACE => {
-                    curr_depth += 1;
+            }
+            Ok(!line.is_empty())
+        }
+
+        let mut bvh = Bvh::default();
+        let mut warnings = vec![];
+        let mut decoder = StreamDecoder::new();
+        let mut line = vec![];
+        let mut line_num = 0usize;
+
+        let mut joints = JointsParser::new();
+        let mut reuse_line = false;
+        while next_line(&mut reader, &mut decoder, &mut line)
+            .await
+            .map_err(LoadJointsError::from)?
+        {
+            line_num += 1;
+            if joints.parse_line(&Line::new(line_num - 1, &line), self, &mut warnings)? {
+                reuse_line = true;
+                break;
+            }
+        }
+        let dialect = joints.finish(&mut bvh)?;
+        bvh.encoding = decoder.encoding();
+
+        let mut motion_header = MotionHeaderParser::new(false, dialect, self.limits);
+        let num_frames_span = loop {
+            if !mem::replace(&mut reuse_line, false) {
+                if !next_line(&mut reader, &mut decoder, &mut line)
+                    .await
+                    .map_err(LoadMotionError::from)?
+                {
+                    let span = Span::new(line_num.saturating_sub(1), 0, 0);
+                    return Err(motion_header.eof_error(span).into());
                 }
-                CLOSE_BRACE => {
-                    curr_depth -= 1;
-                    if curr_depth == 0 {
-                        // We have closed the brace of the root joint.
-                        curr_mode = ParseMode::Finished;
-                    }
+                line_num += 1;
+            }
 
-                    if in_end_site {
-                        let mut new_joint = mem::replace(&mut curr_joint, Joint::default());
+            let line = Line::new(line_num - 1, &line);
+            if let Some(span) = motion_header.parse_line(&line, &mut bvh)? {
+                break span;
+            }
+        };
 
-                        new_joint.parent_index = get_parent_index(&joints, curr_depth);
-                        new_joint.depth = curr_depth.saturating_sub(1);
+        if !self.is_lenient() {
+            // The header can't be trusted to say how much data follows it.
+            let num_values = bvh.motion.num_channels * bvh.motion.num_frames;
+            bvh.motion.values.reserve(cmp::min(num_values, MAX_RESERVED_MOTION_VALUES));
+        }
 
-                        joints.push(new_joint);
-                        curr_index += 1;
-                        in_end_site = false;
-                        pushed_end_site_joint = true;
-                    }
+        let mut motion = MotionParser::new(bvh.num_channels(), num_frames_span, false);
+        while next_line(&mut reader, &mut decoder, &mut line)
+            .await
+            .map_err(LoadMotionError::from)?
+        {
+            line_num += 1;
+            let line = Line::new(line_num - 1, &line);
+            motion.parse_line(&line, self, &mut bvh.motion.values, &mut warnings)?;
+        }
+
+        bvh.motion.num_frames = motion.finish(bvh.motion.num_frames, self, &mut warnings)?;
+
+        Ok((bvh, warnings))
+    }
+
+    #[inline]
+    fn is_lenient(&self) -> bool {
+        self.policy == ParsePolicy::Lenient
+    }
+}
+
+/// The most motion values to allocate memory for up front when the size of the
+/// input is not known. More values may still be read.
+#[cfg(feature = "async")]
+const MAX_RESERVED_MOTION_VALUES: usize = 1 << 20;
+
+/// Limits on the size of a `bvh` file.
+///
+/// A file which is over any of the limits fails to load with a dedicated error,
+/// before the memory for it is allocated. Set the limits when parsing files from
+/// an untrusted source, so that e.g. a file which claims to have billions of
+/// frames can't exhaust the memory of the process.
+///
+/// By default, nothing is limited except by the size of the address space.
+///
+/// # Examples
+///
+/// ```
+/// # use bvh_anim::{errors::{LoadErrorKind, LoadMotionError}, parse::{Limits, ParseOptions}};
+/// let bvh_string = br#"
+///     HIERARCHY
+///     ROOT Hips
+///     {
+///         OFFSET 0.0 0.0 0.0
+///         CHANNELS 3 Xposition Yposition Zposition
+///         End Site
+///         {
+///             OFFSET 0.0 0.0 0.0
+///         }
+///     }
+///     MOTION
+///     Frames: 99999999999
+///     Frame Time: 0.033333333
+/// "#;
+///
+/// let limits = Limits::new().with_max_frames(100_000);
+/// let err = ParseOptions::new()
+///     .with_limits(limits)
+///     .parse_bytes(&bvh_string[..])
+///     .unwrap_err();
+///
+/// match err.into_kind() {
+///     LoadErrorKind::Motion(LoadMotionError::TooManyFrames { limit, .. }) => {
+///         assert_eq!(limit, 100_000);
+///     }
+///     e => panic!("unexpected error: {:?}", e),
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub struct Limits {
+    /// The maximum number of joints in all of the hierarchies.
+    pub max_joints: usize,
+    /// The maximum depth of a joint. The root joint has a depth of `0`.
+    pub max_depth: usize,
+    /// The maximum number of channels in all of the hierarchies.
+    pub max_channels: usize,
+    /// The maximum number of frames.
+    pub max_frames: usize,
+    /// The maximum number of motion values, which is the number of channels
+    /// multiplied by the number of frames.
+    pub max_motion_values: usize,
+}
+
+impl Default for Limits {
+    #[inline]
+    fn default() -> Self {
+        Limits::new()
+    }
+}
+
+impl Limits {
+    /// Create a new `Limits` which does not limit anything.
+    #[inline]
+    pub const fn new() -> Self {
+        Limits {
+            max_joints: usize::MAX,
+            max_depth: usize::MAX,
+            max_channels: usize::MAX,
+            max_frames: usize::MAX,
+            max_motion_values: usize::MAX,
+        }
+    }
+
+    /// Sets `max_joints` on `self` to the new `max_joints`.
+    #[inline]
+    pub const fn with_max_joints(self, max_joints: usize) -> Self {
+        Limits { max_joints, ..self }
+    }
+
+    /// Sets `max_depth` on `self` to the new `max_depth`.
+    #[inline]
+    pub const fn with_max_depth(self, max_depth: usize) -> Self {
+        Limits { max_depth, ..self }
+    }
+
+    /// Sets `max_channels` on `self` to the new `max_channels`.
+    #[inline]
+    pub const fn with_max_channels(self, max_channels: usize) -> Self {
+        Limits {
+            max_channels,
+            ..self
+        }
+    }
+
+    /// Sets `max_frames` on `self` to the new `max_frames`.
+    #[inline]
+    pub const fn with_max_frames(self, max_frames: usize) -> Self {
+        Limits { max_frames, ..self }
+    }
+
+    /// Sets `max_motion_values` on `self` to the new `max_motion_values`.
+    #[inline]
+    pub const fn with_max_motion_values(self, max_motion_values: usize) -> Self {
+        Limits {
+            max_motion_values,
+            ..self
+        }
+    }
+
+    /// Check that `num_frames` frames of `num_channels` values each are
+    /// within the limits.
+    ///
+    /// Returns the total number of motion values.
+    fn check_motion(
+        &self,
+        num_channels: usize,
+        num_frames: usize,
+        span: Span,
+    ) -> Result<usize, LoadMotionError> {
+        if num_frames > self.max_frames {
+            return Err(LoadMotionError::TooManyFrames {
+                limit: self.max_frames,
+                span,
+            });
+        }
+
+        num_channels
+            .checked_mul(num_frames)
+            .filter(|&num_values| num_values <= self.max_motion_values)
+            .ok_or(LoadMotionError::TooManyMotionValues {
+                limit: self.max_motion_values,
+                span,
+            })
+    }
+}
+
+/// Specify how to handle a `bvh` file which does not match its own
+/// declarations.
+///
+/// By default, this value is set to `ParsePolicy::Strict`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ParsePolicy {
+    /// Return an error if the file is inconsistent.
+    Strict,
+    /// Repair inconsistencies where possible, recording each repair
+    /// as a `ParseWarning`.
+    Lenient,
+}
+
+/// Create a new `ParsePolicy` which is strict.
+impl Default for ParsePolicy {
+    #[inline]
+    fn default() -> Self {
+        ParsePolicy::Strict
+    }
+}
+
+/// A problem which was repaired when parsing a `bvh` file with
+/// `ParsePolicy::Lenient`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ParseWarning {
+    /// The number of channels in a `CHANNELS` section did not match the number
+    /// of channel names which followed it. The channel names were used.
+    ChannelCountMismatch {
+        /// The number of channels declared in the `CHANNELS` section.
+        expected: usize,
+        /// The number of channel names which were found.
+        actual: usize,
+        /// The location of the declared number of channels.
+        span: Span,
+    },
+    /// A frame had too few motion values, and was padded with zeroes.
+    FramePadded {
+        /// The number of channels in the skeleton.
+        expected: usize,
+        /// The number of motion values which were found.
+        actual: usize,
+        /// The location of the frame.
+        span: Span,
+    },
+    /// A frame had too many motion values, and the extra values were dropped.
+    FrameTruncated {
+        /// The number of channels in the skeleton.
+        expected: usize,
+        /// The number of motion values which were found.
+        actual: usize,
+        /// The location of the frame.
+        span: Span,
+    },
+    /// The number of frames in the `Frames` section did not match the number
+    /// of frames which were found. The number of frames found was used.
+    FrameCountMismatch {
+        /// The number of frames declared in the `Frames` section.
+        expected: usize,
+        /// The number of frames which were found.
+        actual: usize,
+        /// The location of the declared number of frames.
+        span: Span,
+    },
+    /// A line in the motion section could not be parsed, and was skipped.
+    SkippedLine {
+        /// The location of the skipped line.
+        span: Span,
+    },
+    /// A `JOINT` followed the `End Site` of its parent. Only joints without
+    /// children have an end site, so the end site was dropped.
+    EndSiteDropped {
+        /// The location of the `JOINT` keyword.
+        span: Span,
+    },
+}
+
+impl ParseWarning {
+    /// Get the location in the source which the warning refers to.
+    #[inline]
+    pub fn span(&self) -> Span {
+        match *self {
+            ParseWarning::ChannelCountMismatch { span, .. }
+            | ParseWarning::FramePadded { span, .. }
+            | ParseWarning::FrameTruncated { span, .. }
+            | ParseWarning::FrameCountMismatch { span, .. }
+            | ParseWarning::SkippedLine { span }
+            | ParseWarning::EndSiteDropped { span } => span,
+        }
+    }
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmtr, "{}: ", self.span())?;
+        match *self {
+            ParseWarning::ChannelCountMismatch {
+                expected, actual, ..
+            } => write!(
+                fmtr,
+                "expected {} channels, found {} channels",
+                expected, actual
+            ),
+            ParseWarning::FramePadded {
+                expected, actual, ..
+            } => write!(
+                fmtr,
+                "expected {} motion values, found {} values; padded with zeroes",
+                expected, actual
+            ),
+            ParseWarning::FrameTruncated {
+                expected, actual, ..
+            } => write!(
+                fmtr,
+                "expected {} motion values, found {} values; extra values dropped",
+                expected, actual
+            ),
+            ParseWarning::FrameCountMismatch {
+                expected, actual, ..
+            } => write!(fmtr, "expected {} frames, found {} frames", expected, actual),
+            ParseWarning::SkippedLine { .. } => {
+                fmtr.write_str("skipped a line which could not be parsed")
+            }
+            ParseWarning::EndSiteDropped { .. } => {
+                fmtr.write_str("found a \"JOINT\" after an \"End Site\"; end site dropped")
+            }
+        }
+    }
+}
+
+/// The variations in formatting which were found in a `bvh` file.
+///
+/// Different exporters write slightly different `bvh` files. The parser accepts
+/// all of the variations described here, and records the ones it found in the
+/// `Dialect` of the loaded `Bvh`. Passing the same `Dialect` to
+/// [`WriteOptions::with_dialect`][`WriteOptions::with_dialect`] writes a file in
+/// the same style.
+///
+/// # Examples
+///
+/// ```
+/// # use bvh_anim::parse::KeywordCase;
+/// let bvh_string = br#"
+///     hierarchy
+///     root Hips
+///     {
+///         offset 0.0 0.0 0.0
+///         channels 3 xposition yposition zposition
+///         end site
+///         {
+///             offset 0.0 0.0 0.0
+///         }
+///     }
+///     motion
+///     frames:1
+///     frame time: 0.033333333
+///     0.0 0.0 0.0
+/// "#;
+///
+/// let bvh = bvh_anim::from_bytes(&bvh_string[..])?;
+/// assert_eq!(bvh.dialect().keyword_case, KeywordCase::Lowercase);
+/// assert_eq!(bvh.dialect().channel_case, KeywordCase::Lowercase);
+/// assert!(bvh.dialect().compact_frames);
+/// # Result::<(), bvh_anim::errors::LoadError>::Ok(())
+/// ```
+///
+/// [`WriteOptions::with_dialect`]: ../write/struct.WriteOptions.html#method.with_dialect
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub struct Dialect {
+    /// The case of keywords such as `HIERARCHY`, `End Site` and `Frame Time`.
+    pub keyword_case: KeywordCase,
+    /// Whether `End Site` was written as `End site`.
+    pub lowercase_site: bool,
+    /// The case of channel names such as `Xposition`.
+    pub channel_case: KeywordCase,
+    /// Whether there was no space between `Frames:` and the number of frames.
+    pub compact_frames: bool,
+    /// Whether the hierarchy or the motion header contained `#` comment lines.
+    /// Comments are skipped by the parser, and are never written.
+    pub has_comments: bool,
+}
+
+impl Default for Dialect {
+    #[inline]
+    fn default() -> Self {
+        Dialect::new()
+    }
+}
+
+impl Dialect {
+    /// Create a new `Dialect` which follows the `bvh` specification.
+    #[inline]
+    pub const fn new() -> Self {
+        Dialect {
+            keyword_case: KeywordCase::Standard,
+            lowercase_site: false,
+            channel_case: KeywordCase::Standard,
+            compact_frames: false,
+            has_comments: false,
+        }
+    }
+
+    /// Sets `keyword_case` on `self` to the new `KeywordCase`.
+    #[inline]
+    pub const fn with_keyword_case(self, keyword_case: KeywordCase) -> Self {
+        Dialect {
+            keyword_case,
+            ..self
+        }
+    }
+
+    /// Sets `lowercase_site` on `self` to the new `lowercase_site`.
+    #[inline]
+    pub const fn with_lowercase_site(self, lowercase_site: bool) -> Self {
+        Dialect {
+            lowercase_site,
+            ..self
+        }
+    }
+
+    /// Sets `channel_case` on `self` to the new `KeywordCase`.
+    #[inline]
+    pub const fn with_channel_case(self, channel_case: KeywordCase) -> Self {
+        Dialect {
+            channel_case,
+            ..self
+        }
+    }
+
+    /// Sets `compact_frames` on `self` to the new `compact_frames`.
+    #[inline]
+    pub const fn with_compact_frames(self, compact_frames: bool) -> Self {
+        Dialect {
+            compact_frames,
+            ..self
+        }
+    }
+
+    /// Returns `true` if the `Dialect` follows the `bvh` specification, ignoring
+    /// any comments.
+    #[inline]
+    pub fn is_standard(&self) -> bool {
+        Dialect {
+            has_comments: false,
+            ..*self
+        } == Dialect::new()
+    }
+}
+
+/// The case used to write keywords or channel names.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum KeywordCase {
+    /// The case used by the `bvh` specification, such as `HIERARCHY`,
+    /// `End Site` and `Xposition`.
+    Standard,
+    /// Lowercase, such as `hierarchy`, `end site` and `xposition`.
+    Lowercase,
+    /// Uppercase, such as `HIERARCHY`, `END SITE` and `XPOSITION`.
+    Uppercase,
+    /// A mixture of cases which does not follow any of the other styles. This
+    /// is written in the `Standard` case.
+    Mixed,
+}
+
+impl Default for KeywordCase {
+    #[inline]
+    fn default() -> Self {
+        KeywordCase::Standard
+    }
+}
+
+impl KeywordCase {
+    /// Apply the case to the `standard` spelling of a keyword.
+    pub(crate) fn apply(self, standard: &'static str) -> Cow<'static, str> {
+        match self {
+            KeywordCase::Standard | KeywordCase::Mixed => Cow::Borrowed(standard),
+            KeywordCase::Lowercase => Cow::Owned(standard.to_ascii_lowercase()),
+            KeywordCase::Uppercase => Cow::Owned(standard.to_ascii_uppercase()),
+        }
+    }
+}
+
+/// Tracks which `KeywordCase`s are consistent with every token seen so far.
+#[derive(Clone, Copy, Debug)]
+struct CaseDetector {
+    candidates: u8,
+}
+
+impl CaseDetector {
+    const STANDARD: u8 = 0b001;
+    const LOWERCASE: u8 = 0b010;
+    const UPPERCASE: u8 = 0b100;
+
+    #[inline]
+    fn new() -> Self {
+        CaseDetector {
+            candidates: Self::STANDARD | Self::LOWERCASE | Self::UPPERCASE,
+        }
+    }
+
+    /// Record a `token` which matched the `standard` spelling, ignoring case.
+    fn observe(&mut self, token: &[u8], standard: &[u8]) {
+        let mut matches = 0;
+        if token == standard {
+            matches |= Self::STANDARD;
+        }
+        if !token.iter().any(u8::is_ascii_uppercase) {
+            matches |= Self::LOWERCASE;
+        }
+        if !token.iter().any(u8::is_ascii_lowercase) {
+            matches |= Self::UPPERCASE;
+        }
+        self.candidates &= matches;
+    }
+
+    fn case(&self) -> KeywordCase {
+        if self.candidates & Self::STANDARD != 0 {
+            KeywordCase::Standard
+        } else if self.candidates & Self::LOWERCASE != 0 {
+            KeywordCase::Lowercase
+        } else if self.candidates & Self::UPPERCASE != 0 {
+            KeywordCase::Uppercase
+        } else {
+            KeywordCase::Mixed
+        }
+    }
+}
+
+/// Builds up the `Dialect` of a file while it is being parsed.
+#[derive(Debug)]
+struct DialectDetector {
+    keywords: CaseDetector,
+    channels: CaseDetector,
+    dialect: Dialect,
+}
+
+impl DialectDetector {
+    fn new() -> Self {
+        DialectDetector {
+            keywords: CaseDetector::new(),
+            channels: CaseDetector::new(),
+            dialect: Dialect::new(),
+        }
+    }
+
+    fn dialect(&self) -> Dialect {
+        Dialect {
+            keyword_case: self.keywords.case(),
+            channel_case: self.channels.case(),
+            ..self.dialect
+        }
+    }
+}
+
+/// The skeleton and motion header of a `bvh` file, without any motion values.
+///
+/// # Examples
+///
+/// ```
+/// # use bvh_anim::Bvh;
+/// let bvh_string = br#"
+///     HIERARCHY
+///     ROOT Hips
+///     {
+///         OFFSET 0.0 0.0 0.0
+///         CHANNELS 3 Xposition Yposition Zposition
+///         End Site
+///         {
+///             OFFSET 0.0 0.0 0.0
+///         }
+///     }
+///     MOTION
+///     Frames: 2
+///     Frame Time: 0.033333333
+///     0.0 0.0 0.0
+///     1.0 2.0 3.0
+/// "#;
+///
+/// let header = Bvh::read_header(&bvh_string[..])?;
+/// assert_eq!(header.num_frames(), 2);
+/// assert_eq!(header.num_channels(), 3);
+/// assert_eq!(header.root_joint().unwrap().name[..], b"Hips"[..]);
+/// # Result::<(), bvh_anim::errors::LoadError>::Ok(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Header {
+    /// Holds the joints and frame time, but no frames.
+    bvh: Bvh,
+    num_frames: usize,
+}
+
+impl Header {
+    /// Returns the root joint if it exists, or `None` if the skeleton is empty.
+    #[inline]
+    pub fn root_joint(&self) -> Option<&Joint> {
+        self.bvh.root_joint()
+    }
+
+    /// Returns an iterator over all the `Joint`s in the skeleton.
+    #[inline]
+    pub fn joints(&self) -> Iter<'_, Joint> {
+        self.bvh.joints()
+    }
+
+    /// Get the number of frames declared in the motion header, or the number
+    /// of frames which were counted if `ParseOptions::count_frames` was set.
+    #[inline]
+    pub const fn num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    /// Get the number of channels in the skeleton.
+    #[inline]
+    pub const fn num_channels(&self) -> usize {
+        self.bvh.motion.num_channels
+    }
+
+    /// Get the duration each frame should play for.
+    #[inline]
+    pub const fn frame_time(&self) -> &Duration {
+        &self.bvh.motion.frame_time
+    }
+
+    /// Get the exact frame rate of the motion.
+    #[inline]
+    pub const fn frame_rate(&self) -> FrameRate {
+        self.bvh.motion.frame_rate
+    }
+
+    /// Returns a `Bvh` which contains the skeleton and frame time, but no frames.
+    #[inline]
+    pub fn as_bvh(&self) -> &Bvh {
+        &self.bvh
+    }
+
+    /// Converts the `Header` into a `Bvh` which contains the skeleton and frame
+    /// time, but no frames.
+    #[inline]
+    pub fn into_bvh(self) -> Bvh {
+        self.bvh
+    }
+}
+
+/// Reads the frames of a `bvh` file one at a time, without loading the whole
+/// motion section into memory.
+///
+/// The hierarchy and the motion header are parsed eagerly when the `FrameReader`
+/// is created, and are available through the [`FrameReader::header`][`FrameReader::header`]
+/// method. Each frame must be on a line of its own.
+///
+/// # Examples
+///
+/// ```
+/// # use bvh_anim::parse::FrameReader;
+/// # use std::io::Cursor;
+/// let bvh_string = br#"
+///     HIERARCHY
+///     ROOT Hips
+///     {
+///         OFFSET 0.0 0.0 0.0
+///         CHANNELS 3 Xposition Yposition Zposition
+///         End Site
+///         {
+///             OFFSET 0.0 0.0 0.0
+///         }
+///     }
+///     MOTION
+///     Frames: 2
+///     Frame Time: 0.033333333
+///     0.0 0.0 0.0
+///     1.0 2.0 3.0
+/// "#;
+///
+/// let mut reader = FrameReader::new(Cursor::new(&bvh_string[..]))?;
+/// assert_eq!(reader.num_frames(), 2);
+///
+/// let mut sum = 0.0;
+/// while let Some(frame) = reader.next_frame() {
+///     sum += frame?.as_slice().iter().sum::<bvh_anim::Float>();
+/// }
+/// assert_eq!(sum, 6.0);
+/// # Result::<(), bvh_anim::errors::LoadError>::Ok(())
+/// ```
+///
+/// [`FrameReader::header`]: struct.FrameReader.html#method.header
+#[derive(Debug)]
+pub struct FrameReader<R> {
+    reader: R,
+    decoder: StreamDecoder,
+    header: Header,
+    options: ParseOptions,
+    warnings: Vec<ParseWarning>,
+    motion: MotionParser,
+    line_num: usize,
+    line: Vec<u8>,
+    frame: Vec<Float>,
+    finished: bool,
+}
+
+impl<R: BufReadExt> FrameReader<R> {
+    /// Parse the hierarchy and motion header from `reader`, and prepare to read
+    /// the frames.
+    #[inline]
+    pub fn new(reader: R) -> Result<Self, LoadError> {
+        Self::with_options(reader, ParseOptions::default())
+    }
+
+    /// Parse the hierarchy and motion header from `reader` with the given options,
+    /// and prepare to read the frames.
+    pub fn with_options(mut reader: R, options: ParseOptions) -> Result<Self, LoadError> {
+        let mut bvh = Bvh::default();
+        let mut warnings = vec![];
+        let mut decoder = StreamDecoder::new();
+
+        let (num_frames_span, line_num) =
+            bvh.read_header_(&mut reader, &mut decoder, &options, &mut warnings)?;
+
+        // The header does not own any frames, so it must not claim to have any.
+        let num_frames = mem::replace(&mut bvh.motion.num_frames, 0);
+
+        Ok(FrameReader {
+            reader,
+            decoder,
+            frame: Vec::with_capacity(bvh.motion.num_channels),
+            motion: MotionParser::new(bvh.motion.num_channels, num_frames_span, true),
+            header: Header { bvh, num_frames },
+            options,
+            warnings,
+            line_num,
+            line: vec![],
+            finished: false,
+        })
+    }
+
+    /// Returns the skeleton and motion header of the file.
+    #[inline]
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns the number of frames declared in the motion header.
+    #[inline]
+    pub fn num_frames(&self) -> usize {
+        self.header.num_frames
+    }
+
+    /// Returns the number of frames which have been read so far.
+    #[inline]
+    pub fn num_frames_read(&self) -> usize {
+        self.motion.num_frames()
+    }
+
+    /// Returns the warnings which have been collected so far.
+    #[inline]
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings[..]
+    }
+
+    /// Read the next frame of motion values.
+    ///
+    /// The returned `Frame` borrows a buffer which is re-used for each frame.
+    /// Returns `None` when all frames have been read, or after an error has
+    /// been returned.
+    pub fn next_frame(&mut self) -> Option<Result<&Frame, LoadError>> {
+        if self.finished {
+            return None;
+        }
+
+        loop {
+            self.line.clear();
+            match self.decoder.read_line(&mut self.reader, &mut self.line) {
+                Ok(0) => {
+                    self.finished = true;
+                    return self.check_num_frames().err().map(Err);
                 }
-                kw if kw == ENDSITE_KEYWORDS[0] => {
-                    if tokens.next() == Some(ENDSITE_KEYWORDS[1]) {
-                        in_end_site = true;
-                    } else {
-                        panic!("Unexpected end keyword");
-                    }
+                Ok(_) => {}
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(LoadMotionError::from(e).into()));
                 }
-                JOINT_KEYWORD => {
-                    if curr_mode != ParseMode::InHeirarchy {
-                        panic!("Unexpected Joint");
-                    }
+            }
 
-                    if !pushed_end_site_joint {
-                        curr_joint.parent_index = get_parent_index(&joints, curr_depth);
-                        curr_joint.depth = curr_depth - 1;
+            let line_num = self.line_num;
+            self.line_num += 1;
+
+            self.frame.clear();
+            let read = self.motion.parse_line(
+                &Line::new(line_num, &self.line),
+                &self.options,
+                &mut self.frame,
+                &mut self.warnings,
+            );
+
+            match read {
+                Ok(true) => return Some(Ok(Frame::from_slice(&self.frame[..]))),
+                Ok(false) => continue,
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+    }
 
-                        let new_joint = mem::replace(&mut curr_joint, Joint::default());
-                        joints.push(new_joint);
+    /// Skip the remaining frames without parsing their motion values.
+    ///
+    /// Blank lines are not counted as frames. Returns the total number of frames
+    /// read, and checks it against the number of frames in the header in the same
+    /// way as [`FrameReader::next_frame`][`FrameReader::next_frame`].
+    ///
+    /// [`FrameReader::next_frame`]: struct.FrameReader.html#method.next_frame
+    pub fn skip_frames(&mut self) -> Result<usize, LoadError> {
+        if self.finished {
+            return Ok(self.num_frames_read());
+        }
+        self.finished = true;
+
+        loop {
+            self.line.clear();
+            match self.decoder.read_line(&mut self.reader, &mut self.line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => return Err(LoadMotionError::from(e).into()),
+            }
 
-                        curr_index += 1;
-                    } else {
-                        pushed_end_site_joint = false;
-                    }
+            let line_num = self.line_num;
+            self.line_num += 1;
 
-                    if let Some(name) = tokens.next() {
-                        curr_joint.name = From::from(name);
-                    } else {
-                        panic!("Missing joint name!");
-                    }
-                }
-                OFFSET_KEYWORD => {
-                    if curr_mode != ParseMode::InHeirarchy {
-                        return Err(LoadJointsError::UnexpectedOffsetSection { line: line_num });
-                    }
+            self.motion.skip_line(&Line::new(line_num, &self.line));
+        }
 
-                    let mut offset = Vector3::from([0.0, 0.0, 0.0]);
-
-                    macro_rules! parse_axis {
-                        ($axis_field:ident, $axis_enum:ident) => {
-                            if let Some(tok) = tokens.next() {
-                                offset.$axis_field = try_parse(tok).map_err(|e| {
-                                    LoadJointsError::ParseOffsetError {
-                                        parse_float_error: e,
-                                        axis: Axis::$axis_enum,
-                                        line: line_num,
-                                    }
-                                })?;
-                            } else {
-                                return Err(LoadJointsError::MissingOffsetAxis {
-                                    axis: Axis::$axis_enum,
-                                    line: line_num,
-                                });
-                            }
-                        };
-                    }
+        self.check_num_frames()?;
+        Ok(self.num_frames_read())
+    }
 
-                    parse_axis!(x, X);
-                    parse_axis!(y, Y);
-                    parse_axis!(z, Z);
+    /// Unwraps the underlying reader from the `FrameReader`.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
 
-                    if in_end_site {
-                        curr_joint.end_site = Some(offset);
-                    } else {
-                        curr_joint.offset = offset;
+    fn check_num_frames(&mut self) -> Result<(), LoadError> {
+        let num_frames = self.header.num_frames;
+        self.motion
+            .finish(num_frames, &self.options, &mut self.warnings)?;
+        Ok(())
+    }
+}
+
+/// An event produced by a [`PushParser`][`PushParser`].
+///
+/// [`PushParser`]: struct.PushParser.html
+#[derive(Debug)]
+pub enum ParseEvent<'a> {
+    /// The hierarchy and the motion header have been parsed. This is always
+    /// the first event, and is only produced once.
+    Header(&'a Header),
+    /// A complete frame of motion values has been parsed.
+    Frame(&'a Frame),
+}
+
+/// The section of the input which a `PushParser` is in.
+#[derive(Debug)]
+enum PushState {
+    Joints(Box<JointsParser>),
+    MotionHeader(MotionHeaderParser),
+    /// The header has been parsed, but not yet returned as an event.
+    HeaderComplete(MotionParser),
+    Frames(MotionParser),
+    /// The input has ended, or an error has been returned.
+    Done,
+}
+
+/// An incremental parser which is fed `bvh` data in arbitrary chunks, such as
+/// from a live motion capture stream over a socket.
+///
+/// Data is given to the parser with [`PushParser::feed`][`PushParser::feed`],
+/// and events are taken out with [`PushParser::next_event`][`PushParser::next_event`].
+/// A [`ParseEvent::Header`][`ParseEvent::Header`] event is produced once the
+/// hierarchy and the motion header are complete, followed by a
+/// [`ParseEvent::Frame`][`ParseEvent::Frame`] event for each complete line of
+/// motion values.
+///
+/// Lines may be split across any number of chunks. Since a stream does not
+/// usually know how many frames it will send, the `Frames` line of the motion
+/// header may be left out, and the number of frames is never checked.
+///
+/// # Examples
+///
+/// ```
+/// # use bvh_anim::parse::{ParseEvent, PushParser};
+/// let chunks: &[&[u8]] = &[
+///     b"HIERARCHY\nROOT Hips\n{\n\tOFFSET 0.0 0.0 0.0\n\tCHANNELS 3 ",
+///     b"Xposition Yposition Zposition\n\tEnd Site\n\t{\n\t\tOFFSET 0.0 0.0 0.0\n",
+///     b"\t}\n}\nMOTION\nFrame Time: 0.033333333\n0.0 0.0 0.0\n1.0 2.",
+///     b"0 3.0\n",
+/// ];
+///
+/// let mut parser = PushParser::new();
+/// let mut frames = vec![];
+///
+/// for chunk in chunks {
+///     parser.feed(chunk);
+///     while let Some(event) = parser.next_event() {
+///         match event? {
+///             ParseEvent::Header(header) => assert_eq!(header.num_channels(), 3),
+///             ParseEvent::Frame(frame) => frames.push(frame.as_slice().to_vec()),
+///         }
+///     }
+/// }
+///
+/// assert_eq!(frames, vec![vec![0.0, 0.0, 0.0], vec![1.0, 2.0, 3.0]]);
+/// # Result::<(), bvh_anim::errors::LoadError>::Ok(())
+/// ```
+///
+/// [`PushParser::feed`]: struct.PushParser.html#method.feed
+/// [`PushParser::next_event`]: struct.PushParser.html#method.next_event
+/// [`ParseEvent::Header`]: enum.ParseEvent.html#variant.Header
+/// [`ParseEvent::Frame`]: enum.ParseEvent.html#variant.Frame
+#[derive(Debug)]
+pub struct PushParser {
+    options: ParseOptions,
+    state: PushState,
+    header: Header,
+    header_complete: bool,
+    warnings: Vec<ParseWarning>,
+    decoder: StreamDecoder,
+    /// The input which has been fed so far, transcoded to `utf8`.
+    buffer: Vec<u8>,
+    /// The start of the data in `buffer` which has not been parsed yet.
+    buffer_start: usize,
+    line_num: usize,
+    frame: Vec<Float>,
+    num_frames_read: usize,
+    finished: bool,
+}
+
+impl Default for PushParser {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PushParser {
+    /// Create a new `PushParser` with the default `ParseOptions`.
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_options(ParseOptions::default())
+    }
+
+    /// Create a new `PushParser` with the given `options`.
+    pub fn with_options(options: ParseOptions) -> Self {
+        PushParser {
+            options,
+            state: PushState::Joints(Box::new(JointsParser::new())),
+            header: Header::default(),
+            header_complete: false,
+            warnings: vec![],
+            decoder: StreamDecoder::new(),
+            buffer: vec![],
+            buffer_start: 0,
+            line_num: 0,
+            frame: vec![],
+            num_frames_read: 0,
+            finished: false,
+        }
+    }
+
+    /// Append a chunk of data to the input.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buffer.drain(..self.buffer_start);
+        self.buffer_start = 0;
+        self.decoder.decode(data, &mut self.buffer);
+    }
+
+    /// Signal that there is no more input. Any partial line left at the end of
+    /// the input will be parsed as a complete line.
+    #[inline]
+    pub fn finish(&mut self) {
+        if !self.finished {
+            self.decoder.finish(&mut self.buffer);
+        }
+        self.finished = true;
+    }
+
+    /// Returns the skeleton and motion header, if they have been parsed.
+    ///
+    /// The number of frames in the header is `0` if the stream did not give one.
+    #[inline]
+    pub fn header(&self) -> Option<&Header> {
+        if self.header_complete {
+            Some(&self.header)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the number of frames which have been parsed so far.
+    #[inline]
+    pub fn num_frames_read(&self) -> usize {
+        self.num_frames_read
+    }
+
+    /// Returns the warnings produced so far. This is always empty with
+    /// `ParsePolicy::Strict`.
+    #[inline]
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    /// Parse the next event from the input which has been fed so far.
+    ///
+    /// Returns `None` if a complete line has not been fed yet, once all of the
+    /// input has been parsed after [`PushParser::finish`][`PushParser::finish`]
+    /// has been called, or after an error has been returned.
+    ///
+    /// [`PushParser::finish`]: struct.PushParser.html#method.finish
+    pub fn next_event(&mut self) -> Option<Result<ParseEvent<'_>, LoadError>> {
+        loop {
+            match self.state {
+                PushState::Done => return None,
+                PushState::HeaderComplete(_) => {
+                    if let PushState::HeaderComplete(motion) =
+                        mem::replace(&mut self.state, PushState::Done)
+                    {
+                        self.state = PushState::Frames(motion);
                     }
+                    return Some(Ok(ParseEvent::Header(&self.header)));
                 }
-                CHANNELS_KEYWORD => {
-                    if curr_mode != ParseMode::InHeirarchy {
-                        return Err(LoadJointsError::UnexpectedChannelsSection { line: line_num });
-                    }
+                _ => {}
+            }
 
-                    let num_channels: usize = tokens
-                        .next()
-                        .ok_or(LoadJointsError::ParseNumChannelsError {
-                            error: None,
-                            line: line_num,
-                        })
-                        .and_then(|tok| match try_parse(tok) {
-                            Ok(c) => Ok(c),
-                            Err(e) => Err(LoadJointsError::ParseNumChannelsError {
-                                error: Some(e),
-                                line: line_num,
-                            }),
-                        })?;
+            let remaining = &self.buffer[self.buffer_start..];
+            let line_len = match remaining.find_byte(b'\n') {
+                Some(newline) => newline + 1,
+                None if self.finished && !remaining.is_empty() => remaining.len(),
+                None if self.finished => return self.end_of_input().map(Err),
+                None => return None,
+            };
 
-                    let mut channels: SmallVec<[Channel; 6]> = Default::default();
-                    channels.reserve(num_channels);
+            let line_num = self.line_num;
+            let line = Line::new(
+                line_num,
+                &self.buffer[self.buffer_start..self.buffer_start + line_len],
+            );
+            self.buffer_start += line_len;
+            self.line_num += 1;
+
+            match self.state {
+                PushState::Joints(ref mut parser) => {
+                    match parser.parse_line(&line, &self.options, &mut self.warnings) {
+                        Ok(false) => continue,
+                        Ok(true) => {}
+                        Err(e) => {
+                            self.state = PushState::Done;
+                            return Some(Err(e.into()));
+                        }
+                    }
 
-                    while let Some(tok) = tokens.next() {
-                        let channel_ty = ChannelType::try_from(tok).map_err(|e| {
-                            LoadJointsError::ParseChannelError {
-                                error: e,
-                                line: line_num,
+                    let prev_state = mem::replace(&mut self.state, PushState::Done);
+                    if let PushState::Joints(parser) = prev_state {
+                        match parser.finish(&mut self.header.bvh) {
+                            Ok(dialect) => {
+                                self.header.bvh.encoding = self.decoder.encoding();
+                                let limits = self.options.limits;
+                                let parser = MotionHeaderParser::new(true, dialect, limits);
+                                self.state = PushState::MotionHeader(parser);
                             }
-                        })?;
-                        let channel = Channel::new(channel_ty, curr_channel);
-                        curr_channel += 1;
-                        channels.push(channel);
+                            Err(e) => return Some(Err(e.into())),
+                        }
                     }
 
-                    curr_joint.channels = channels;
+                    // The line starts the motion section, so parse it again.
+                    self.buffer_start -= line_len;
+                    self.line_num -= 1;
                 }
-                _ => {}
-            }
-
-            if curr_mode == ParseMode::Finished {
-                break;
+                PushState::MotionHeader(ref mut parser) => {
+                    match parser.parse_line(&line, &mut self.header.bvh) {
+                        Ok(None) => {}
+                        Ok(Some(num_frames_span)) => {
+                            // The header does not own any frames, so it must not claim to have any.
+                            let motion = &mut self.header.bvh.motion;
+                            let num_frames = mem::replace(&mut motion.num_frames, 0);
+                            self.header.num_frames = num_frames;
+                            let num_channels = self.header.num_channels();
+                            self.frame.reserve(num_channels);
+                            self.header_complete = true;
+                            let motion = MotionParser::new(num_channels, num_frames_span, true);
+                            self.state = PushState::HeaderComplete(motion);
+                        }
+                        Err(e) => {
+                            self.state = PushState::Done;
+                            return Some(Err(e.into()));
+                        }
+                    }
+                }
+                PushState::Frames(ref mut motion) => {
+                    self.frame.clear();
+                    let read = motion.parse_line(
+                        &line,
+                        &self.options,
+                        &mut self.frame,
+                        &mut self.warnings,
+                    );
+
+                    match read {
+                        Ok(true) => {
+                            self.num_frames_read += 1;
+                            return Some(Ok(ParseEvent::Frame(Frame::from_slice(&self.frame[..]))));
+                        }
+                        Ok(false) => {}
+                        Err(e) => {
+                            self.state = PushState::Done;
+                            return Some(Err(e.into()));
+                        }
+                    }
+                }
+                PushState::HeaderComplete(_) | PushState::Done => unreachable!(),
             }
         }
+    }
 
-        if curr_mode != ParseMode::Finished {
-            return Err(LoadJointsError::MissingRoot);
+    /// Moves to the `Done` state once all of the input has been parsed,
+    /// returning an error if the header was not complete.
+    fn end_of_input(&mut self) -> Option<LoadError> {
+        let span = Span::new(self.line_num.saturating_sub(1), 0, 0);
+        match mem::replace(&mut self.state, PushState::Done) {
+            PushState::Joints(parser) => parser.finish(&mut self.header.bvh).err().map(From::from),
+            PushState::MotionHeader(parser) => Some(parser.eof_error(span).into()),
+            _ => None,
         }
+    }
+}
 
-        self.joints = joints;
-        self.num_channels = curr_channel;
+pub(crate) const HEIRARCHY_KEYWORD: &[u8] = b"HIERARCHY";
+
+pub(crate) const ROOT_KEYWORD: &[u8] = b"ROOT";
+pub(crate) const JOINT_KEYWORD: &[u8] = b"JOINT";
+pub(crate) const ENDSITE_KEYWORDS: &[&[u8]] = &[b"End", b"Site"];
+
+pub(crate) const OPEN_BRACE: &[u8] = b"{";
+pub(crate) const CLOSE_BRACE: &[u8] = b"}";
+
+pub(crate) const OFFSET_KEYWORD: &[u8] = b"OFFSET";
+pub(crate) const CHANNELS_KEYWORD: &[u8] = b"CHANNELS";
+
+pub(crate) const MOTION_KEYWORD: &[u8] = b"MOTION";
+pub(crate) const FRAMES_KEYWORD: &[u8] = b"Frames";
+const FRAME_TIME_KEYWORDS: &[&[u8]] = &[b"Frame", b"Time"];
+const FRAME_TIME_KEYWORD_COMPACT: &[u8] = b"FrameTime";
+
+/// The keywords which may start a line in the hierarchy section.
+const HIERARCHY_KEYWORDS: &[&[u8]] = &[
+    HEIRARCHY_KEYWORD,
+    ROOT_KEYWORD,
+    JOINT_KEYWORD,
+    ENDSITE_KEYWORDS[0],
+    OFFSET_KEYWORD,
+    CHANNELS_KEYWORD,
+];
+
+#[derive(Debug, Eq, PartialEq)]
+enum ParseMode {
+    NotStarted,
+    InHeirarchy,
+    Finished,
+}
 
-        Ok(())
+#[allow(unused)]
+#[derive(Debug, Eq, PartialEq)]
+enum NextExpectedLine {
+    Hierarchy,
+    Channels,
+    Offset,
+    OpeningBrace,
+    ClosingBrace,
+    JointName,
+    RootName,
+}
+
+/// Find the parent of a joint at `for_depth`, searching only the joints of the
+/// hierarchy which starts at `root_index`.
+#[inline]
+fn get_parent_index(joints: &[Joint], root_index: usize, for_depth: usize) -> Option<usize> {
+    joints[root_index..]
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, jd)| jd.depth == for_depth.saturating_sub(2))
+        .map(|(i, _)| i + root_index)
+}
+
+/// Parses the hierarchy section of a `bvh` file, one line at a time.
+#[derive(Debug)]
+struct JointsParser {
+    joints: Vec<Joint>,
+    curr_mode: ParseMode,
+    curr_channel: usize,
+    curr_depth: usize,
+    next_expected_line: NextExpectedLine,
+    curr_joint: Joint,
+    /// The index of the root joint of the current hierarchy.
+    root_index: usize,
+    in_end_site: bool,
+    /// Whether `curr_joint` has been pushed. A joint is pushed once its first
+    /// child, its end site or its closing brace is reached.
+    pushed_curr_joint: bool,
+    last_span: Span,
+    dialect: DialectDetector,
+}
+
+impl JointsParser {
+    fn new() -> Self {
+        JointsParser {
+            joints: vec![],
+            curr_mode: ParseMode::NotStarted,
+            curr_channel: 0,
+            curr_depth: 0,
+            next_expected_line: NextExpectedLine::Hierarchy,
+            curr_joint: Joint::default(),
+            root_index: 0,
+            in_end_site: false,
+            pushed_curr_joint: false,
+            last_span: Span::default(),
+            dialect: DialectDetector::new(),
+        }
     }
 
-    pub(crate) fn read_motion(
+    /// Parse a single line of the hierarchy.
+    ///
+    /// Returns `Ok(true)` if the hierarchy ended before this line, in which case
+    /// the line has not been consumed and should be parsed as part of the motion
+    /// section.
+    fn parse_line(
         &mut self,
-        lines: &mut EnumeratedLines<'_>,
-    ) -> Result<(), LoadMotionError> {
-        const MOTION_KEYWORD: &[u8] = b"MOTION";
-        const FRAMES_KEYWORD: &[u8] = b"Frames";
-        const FRAME_TIME_KEYWORDS: &[&[u8]] = &[b"Frame", b"Time:"];
+        line: &Line<'_>,
+        options: &ParseOptions,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<bool, LoadJointsError> {
+        if line.is_comment() {
+            self.dialect.dialect.has_comments = true;
+            return Ok(false);
+        }
 
-        macro_rules! last_line_num {
-            () => {
-                lines.last_enumerator().unwrap_or(0)
-            };
+        self.last_span = line.trimmed().span_after();
+
+        let mut tokens = line.tokens();
+
+        let first_token = match tokens.next() {
+            Some(tok) => tok,
+            None => return Ok(false),
+        };
+
+        let keyword_span = first_token.span();
+
+        // Keywords are matched regardless of case, so that files from exporters
+        // which write e.g. `hierarchy` or `Joint` can still be loaded.
+        let keyword = HIERARCHY_KEYWORDS
+            .iter()
+            .find(|kw| kw.eq_ignore_ascii_case(first_token.bytes()))
+            .cloned();
+        if let Some(kw) = keyword {
+            self.dialect.keywords.observe(first_token.bytes(), kw);
         }
 
-        lines
-            .next_non_empty_line()
-            .ok_or(LoadMotionError::MissingMotionSection {
-                line: last_line_num!(),
-            })
-            .and_then(|(line_num, line)| {
-                let line = line?;
-                let line = line.trim();
-                if line == MOTION_KEYWORD {
-                    Ok(())
+        match keyword.unwrap_or_else(|| first_token.bytes()) {
+            HEIRARCHY_KEYWORD => {
+                if self.curr_mode != ParseMode::NotStarted {
+                    return Err(LoadJointsError::UnexpectedHierarchySection {
+                        span: keyword_span,
+                    });
+                }
+                self.curr_mode = ParseMode::InHeirarchy;
+                self.next_expected_line = NextExpectedLine::RootName;
+            }
+            ROOT_KEYWORD => {
+                // Another root may follow once the previous hierarchy is closed.
+                let after_root = self.curr_mode == ParseMode::Finished;
+                if !after_root
+                    && (self.curr_mode != ParseMode::InHeirarchy
+                        || self.next_expected_line != NextExpectedLine::RootName)
+                {
+                    return Err(LoadJointsError::UnexpectedRoot { span: keyword_span });
+                }
+
+                self.check_num_joints(&options.limits, keyword_span)?;
+                self.curr_mode = ParseMode::InHeirarchy;
+                self.root_index = self.joints.len();
+                self.pushed_curr_joint = false;
+
+                if let Some(name) = tokens.next() {
+                    self.curr_joint.name = From::from(name.bytes());
                 } else {
-                    Err(LoadMotionError::MissingMotionSection { line: line_num })
+                    return Err(LoadJointsError::MissingJointName {
+                        span: first_token.span_after(),
+                    });
                 }
-            })?;
 
-        self.num_frames = lines
-            .next_non_empty_line()
-            .ok_or(LoadMotionError::MissingNumFrames {
-                parse_error: None,
-                line: last_line_num!(),
-            })
-            .and_then(|(line_num, line)| {
-                let line = line?;
-                let line = line.trim();
-                let mut tokens = line.fields_with(|c: char| c.is_ascii_whitespace() || c == ':');
-
-                if tokens.next() != Some(FRAMES_KEYWORD) {
-                    return Err(LoadMotionError::MissingNumFrames {
-                        parse_error: None,
-                        line: line_num,
+                self.next_expected_line = NextExpectedLine::OpeningBrace;
+            }
+            OPEN_BRACE => {
+                if self.curr_mode != ParseMode::InHeirarchy
+                    || self.next_expected_line == NextExpectedLine::RootName
+                {
+                    return Err(LoadJointsError::UnexpectedOpeningBrace {
+                        span: keyword_span,
                     });
                 }
+                self.curr_depth += 1;
+            }
+            CLOSE_BRACE => {
+                self.curr_depth = self.curr_depth
+                    .checked_sub(1)
+                    .ok_or(LoadJointsError::UnexpectedClosingBrace { span: keyword_span })?;
+                if self.curr_depth == 0 {
+                    // We have closed the brace of the root joint.
+                    self.curr_mode = ParseMode::Finished;
+                }
 
-                let parse_num_frames = |token: Option<&[u8]>| {
-                    if let Some(num_frames) = token.and_then(|b| str::from_utf8(b).ok()) {
-                        try_parse::<usize, _>(num_frames)
-                            .map_err(|e| LoadMotionError::MissingNumFrames {
-                                parse_error: Some(e),
-                                line: line_num,
-                            })
-                            .map_err(Into::into)
-                    } else {
-                        Err(LoadMotionError::MissingNumFrames {
-                            parse_error: None,
-                            line: line_num,
-                        })
-                    }
-                };
+                if self.in_end_site {
+                    // The end site is a field of the joint it closes, so the joint can
+                    // only be pushed now that it is known.
+                    let mut new_joint = mem::replace(&mut self.curr_joint, Joint::default());
+
+                    new_joint.parent_index =
+                        get_parent_index(&self.joints, self.root_index, self.curr_depth);
+                    new_joint.depth = self.curr_depth.saturating_sub(1);
+
+                    self.joints.push(new_joint);
+                    self.in_end_site = false;
+                    self.pushed_curr_joint = true;
+                } else if !self.pushed_curr_joint {
+                    // A joint with no children or end site has been closed.
+                    let mut new_joint = mem::take(&mut self.curr_joint);
+
+                    new_joint.parent_index =
+                        get_parent_index(&self.joints, self.root_index, self.curr_depth + 1);
+                    new_joint.depth = self.curr_depth;
+
+                    self.joints.push(new_joint);
+                    self.pushed_curr_joint = true;
+                }
+            }
+            kw if kw == ENDSITE_KEYWORDS[0] => {
+                // Once the current joint has been pushed, there is no joint left open
+                // which could take an end site: either it already has one, or the end
+                // site follows the children of a joint.
+                if self.curr_mode != ParseMode::InHeirarchy
+                    || self.curr_depth == 0
+                    || self.in_end_site
+                    || self.pushed_curr_joint
+                {
+                    return Err(LoadJointsError::UnexpectedEndSite { span: keyword_span });
+                }
 
                 match tokens.next() {
-                    Some(tok) if tok == b":" => parse_num_frames(tokens.next()),
-                    Some(tok) => parse_num_frames(Some(tok)),
-                    None => Err(LoadMotionError::MissingNumFrames {
-                        parse_error: None,
-                        line: line_num,
-                    }),
+                    Some(site) if site.bytes().eq_ignore_ascii_case(ENDSITE_KEYWORDS[1]) => {
+                        if site.bytes() == b"site" && first_token.bytes() != b"end" {
+                            self.dialect.dialect.lowercase_site = true;
+                        } else {
+                            self.dialect.keywords.observe(site.bytes(), ENDSITE_KEYWORDS[1]);
+                        }
+                        self.in_end_site = true;
+                    }
+                    _ => return Err(LoadJointsError::MalformedEndSite { span: keyword_span }),
+                }
+            }
+            JOINT_KEYWORD => {
+                if self.curr_mode != ParseMode::InHeirarchy
+                    || self.curr_depth == 0
+                    || self.in_end_site
+                {
+                    return Err(LoadJointsError::UnexpectedJoint { span: keyword_span });
                 }
-            })?;
 
-        self.frame_time = lines
-            .next_non_empty_line()
-            .ok_or(LoadMotionError::MissingFrameTime {
-                parse_error: None,
-                line: last_line_num!(),
-            })
-            .and_then(|(line_num, line)| {
-                let line = line?;
-                let mut tokens = line.fields();
+                if self.pushed_curr_joint
+                    && self.joints.last().map(Joint::depth) == Some(self.curr_depth - 1)
+                {
+                    // A joint with an end site can't have children, but some exporters
+                    // write the end site before the children of a joint.
+                    if !options.is_lenient() {
+                        return Err(LoadJointsError::UnexpectedJoint { span: keyword_span });
+                    }
+                    if let Some(parent) = self.joints.last_mut() {
+                        parent.end_site = None;
+                    }
+                    warnings.push(ParseWarning::EndSiteDropped { span: keyword_span });
+                }
 
-                let frame_time_kw = tokens.next();
-                if frame_time_kw == FRAME_TIME_KEYWORDS.get(0).map(|b| *b) {
-                    // do nothing
+                if !self.pushed_curr_joint {
+                    self.curr_joint.parent_index =
+                        get_parent_index(&self.joints, self.root_index, self.curr_depth);
+                    self.curr_joint.depth = self.curr_depth - 1;
+
+                    let new_joint = mem::replace(&mut self.curr_joint, Joint::default());
+                    self.joints.push(new_joint);
                 } else {
-                    return Err(LoadMotionError::MissingFrameTime {
-                        parse_error: None,
-                        line: line_num,
+                    self.pushed_curr_joint = false;
+                }
+
+                self.check_num_joints(&options.limits, keyword_span)?;
+                if self.curr_depth > options.limits.max_depth {
+                    return Err(LoadJointsError::HierarchyTooDeep {
+                        limit: options.limits.max_depth,
+                        span: keyword_span,
                     });
                 }
 
-                let frame_time_kw = tokens.next();
-                if frame_time_kw == FRAME_TIME_KEYWORDS.get(1).map(|b| *b) {
-                    // do nothing
+                if let Some(name) = tokens.next() {
+                    self.curr_joint.name = From::from(name.bytes());
                 } else {
-                    return Err(LoadMotionError::MissingFrameTime {
-                        parse_error: None,
-                        line: line_num,
+                    return Err(LoadJointsError::MissingJointName {
+                        span: first_token.span_after(),
                     });
                 }
+            }
+            OFFSET_KEYWORD => {
+                if self.curr_mode != ParseMode::InHeirarchy {
+                    return Err(LoadJointsError::UnexpectedOffsetSection { span: keyword_span });
+                }
 
-                let parse_frame_time = |token: Option<&[u8]>| {
-                    if let Some(frame_time) = token {
-                        let frame_time_secs = try_parse::<f64, _>(frame_time).map_err(|e| {
-                            LoadMotionError::MissingFrameTime {
-                                parse_error: Some(e),
-                                line: line_num,
-                            }
-                        })?;
-                        Ok(fraction_seconds_to_duration(frame_time_secs))
+                let mut offset = [0.0; 3];
+                let mut prev_token = first_token;
+                for (value, &axis) in offset.iter_mut().zip(&[Axis::X, Axis::Y, Axis::Z]) {
+                    let tok = tokens.next().ok_or(LoadJointsError::MissingOffsetAxis {
+                        axis,
+                        span: prev_token.span_after(),
+                    })?;
+                    *value = try_parse(tok.bytes()).map_err(|e| {
+                        LoadJointsError::ParseOffsetError {
+                            parse_float_error: e,
+                            axis,
+                            span: tok.span(),
+                        }
+                    })?;
+                    prev_token = tok;
+                }
+                let offset = Vector3::from(offset);
+
+                if self.in_end_site {
+                    self.curr_joint.end_site = Some(offset);
+                } else {
+                    self.curr_joint.offset = offset;
+                }
+            }
+            CHANNELS_KEYWORD => {
+                if self.curr_mode != ParseMode::InHeirarchy {
+                    return Err(LoadJointsError::UnexpectedChannelsSection {
+                        span: keyword_span,
+                    });
+                }
+
+                let num_channels_token = tokens.next().ok_or(
+                    LoadJointsError::ParseNumChannelsError {
+                        error: None,
+                        span: first_token.span_after(),
+                    },
+                )?;
+                let num_channels_span = num_channels_token.span();
+                let num_channels: usize = try_parse(num_channels_token.bytes()).map_err(|e| {
+                    LoadJointsError::ParseNumChannelsError {
+                        error: Some(e),
+                        span: num_channels_span,
+                    }
+                })?;
+
+                let mut channels: SmallVec<[Channel; 6]> = Default::default();
+
+                while let Some(tok) = tokens.next() {
+                    let channel_ty = ChannelType::try_from(tok.bytes()).map_err(|e| {
+                        LoadJointsError::ParseChannelError {
+                            error: e,
+                            span: tok.span(),
+                        }
+                    })?;
+                    self.dialect.channels.observe(tok.bytes(), channel_ty.as_str().as_bytes());
+                    if self.curr_channel >= options.limits.max_channels {
+                        return Err(LoadJointsError::TooManyChannels {
+                            limit: options.limits.max_channels,
+                            span: tok.span(),
+                        });
+                    }
+                    let channel = Channel::new(channel_ty, self.curr_channel);
+                    self.curr_channel += 1;
+                    channels.push(channel);
+                }
+
+                if channels.len() != num_channels {
+                    if options.is_lenient() {
+                        warnings.push(ParseWarning::ChannelCountMismatch {
+                            expected: num_channels,
+                            actual: channels.len(),
+                            span: num_channels_span,
+                        });
                     } else {
-                        Err(LoadMotionError::MissingFrameTime {
-                            parse_error: None,
-                            line: line_num,
-                        })
+                        return Err(LoadJointsError::ChannelCountMismatch {
+                            expected: num_channels,
+                            actual: channels.len(),
+                            span: num_channels_span,
+                        });
                     }
-                };
+                }
 
-                match tokens.next() {
-                    Some(tok) if tok == b":" => parse_frame_time(tokens.next()),
-                    Some(tok) => parse_frame_time(Some(tok)),
-                    None => Err(LoadMotionError::MissingNumFrames {
-                        parse_error: None,
-                        line: line_num,
-                    }),
+                self.curr_joint.channels = channels;
+            }
+            _ if self.curr_mode == ParseMode::Finished => {
+                // The line is not part of the hierarchy, so it must start the
+                // motion section.
+                return Ok(true);
+            }
+            _ => {}
+        }
+
+        Ok(false)
+    }
+
+    /// Check that a new joint can be started without going over the limit.
+    ///
+    /// Every joint before the new one has already been pushed.
+    fn check_num_joints(&self, limits: &Limits, span: Span) -> Result<(), LoadJointsError> {
+        if self.joints.len() >= limits.max_joints {
+            return Err(LoadJointsError::TooManyJoints {
+                limit: limits.max_joints,
+                span,
+            });
+        }
+        Ok(())
+    }
+
+    /// Move the parsed joints into `bvh`, or return an error if the hierarchy
+    /// is incomplete.
+    ///
+    /// Returns the dialect detected so far, to be completed by the motion header.
+    fn finish(self, bvh: &mut Bvh) -> Result<DialectDetector, LoadJointsError> {
+        if self.curr_mode != ParseMode::Finished {
+            return Err(LoadJointsError::MissingRoot {
+                span: self.last_span,
+            });
+        }
+
+        bvh.skeleton = Arc::new(Skeleton::from_joints(self.joints, self.curr_channel));
+        bvh.motion.num_channels = self.curr_channel;
+
+        Ok(self.dialect)
+    }
+}
+
+/// The next line expected in the motion header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum MotionHeaderLine {
+    Motion,
+    Frames,
+    FrameTime,
+}
+
+/// Parses the `MOTION`, `Frames` and `Frame Time` lines of a `bvh` file, one
+/// line at a time.
+#[derive(Debug)]
+struct MotionHeaderParser {
+    next_line: MotionHeaderLine,
+    num_frames_span: Span,
+    /// Whether the `Frames` line may be left out, for streams which do not
+    /// know how many frames they contain.
+    frames_optional: bool,
+    dialect: DialectDetector,
+    limits: Limits,
+}
+
+impl MotionHeaderParser {
+    fn new(frames_optional: bool, dialect: DialectDetector, limits: Limits) -> Self {
+        MotionHeaderParser {
+            next_line: MotionHeaderLine::Motion,
+            num_frames_span: Span::default(),
+            frames_optional,
+            dialect,
+            limits,
+        }
+    }
+
+    /// Parse a single line of the motion header into `bvh`.
+    ///
+    /// Returns the location of the number of frames once the whole header has
+    /// been parsed.
+    fn parse_line(
+        &mut self,
+        line: &Line<'_>,
+        bvh: &mut Bvh,
+    ) -> Result<Option<Span>, LoadMotionError> {
+        if line.is_blank() {
+            return Ok(None);
+        }
+
+        if line.is_comment() {
+            self.dialect.dialect.has_comments = true;
+            return Ok(None);
+        }
+
+        match self.next_line {
+            MotionHeaderLine::Motion => {
+                let trimmed = line.trimmed();
+                if !trimmed.bytes().eq_ignore_ascii_case(MOTION_KEYWORD) {
+                    return Err(LoadMotionError::MissingMotionSection {
+                        span: trimmed.span(),
+                    });
+                }
+                self.dialect.keywords.observe(trimmed.bytes(), MOTION_KEYWORD);
+                self.next_line = MotionHeaderLine::Frames;
+                Ok(None)
+            }
+            MotionHeaderLine::Frames if self.frames_optional && is_frame_time_line(line) => {
+                bvh.motion.num_frames = 0;
+                self.num_frames_span = Span::new(line.num(), 0, 0);
+                bvh.motion.set_frame_rate(parse_frame_time(line, &mut self.dialect)?);
+                bvh.dialect = self.dialect.dialect();
+                Ok(Some(self.num_frames_span))
+            }
+            MotionHeaderLine::Frames => {
+                let (num_frames, span) = parse_num_frames(line, &mut self.dialect)?;
+                self.limits.check_motion(bvh.motion.num_channels, num_frames, span)?;
+                bvh.motion.num_frames = num_frames;
+                self.num_frames_span = span;
+                self.next_line = MotionHeaderLine::FrameTime;
+                Ok(None)
+            }
+            MotionHeaderLine::FrameTime => {
+                bvh.motion.set_frame_rate(parse_frame_time(line, &mut self.dialect)?);
+                bvh.dialect = self.dialect.dialect();
+                Ok(Some(self.num_frames_span))
+            }
+        }
+    }
+
+    /// Get the error for when the input ends before the motion header is complete.
+    fn eof_error(&self, span: Span) -> LoadMotionError {
+        match self.next_line {
+            MotionHeaderLine::Motion => LoadMotionError::MissingMotionSection { span },
+            MotionHeaderLine::Frames => LoadMotionError::MissingNumFrames {
+                parse_error: None,
+                span,
+            },
+            MotionHeaderLine::FrameTime => LoadMotionError::MissingFrameTime {
+                parse_error: None,
+                span,
+            },
+        }
+    }
+}
+
+/// Parses the motion values which follow the motion header, one line at a time.
+#[derive(Debug)]
+struct MotionParser {
+    num_channels: usize,
+    num_frames_span: Span,
+    /// Whether each line must hold one whole frame. Otherwise, the values of a
+    /// strict file may be split between lines in any way.
+    whole_frames: bool,
+    num_frames: usize,
+    num_values: usize,
+}
+
+impl MotionParser {
+    fn new(num_channels: usize, num_frames_span: Span, whole_frames: bool) -> Self {
+        MotionParser {
+            num_channels,
+            num_frames_span,
+            whole_frames,
+            num_frames: 0,
+            num_values: 0,
+        }
+    }
+
+    /// Parse a single line of motion values, appending the values to `values`.
+    ///
+    /// Returns `Ok(true)` if the line held a whole frame. Lines of a strict file
+    /// which do not need to hold whole frames always return `Ok(false)`.
+    fn parse_line(
+        &mut self,
+        line: &Line<'_>,
+        options: &ParseOptions,
+        values: &mut Vec<Float>,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<bool, LoadMotionError> {
+        let span = line.trimmed().span();
+        if !self.whole_frames && !options.is_lenient() {
+            let start = values.len();
+            read_motion_line(line, values)?;
+            self.num_values += values.len() - start;
+            if self.num_values > options.limits.max_motion_values {
+                return Err(LoadMotionError::TooManyMotionValues {
+                    limit: options.limits.max_motion_values,
+                    span,
+                });
+            }
+            return Ok(false);
+        }
+
+        let read = read_frame(line, self.num_channels, options, values, warnings)?;
+        if read {
+            self.num_frames += 1;
+            self.num_values += self.num_channels;
+            options
+                .limits
+                .check_motion(self.num_channels, self.num_frames, span)?;
+        }
+        Ok(read)
+    }
+
+    /// Count a line as a frame without parsing its values.
+    ///
+    /// Returns `true` unless the line is blank or a comment.
+    fn skip_line(&mut self, line: &Line<'_>) -> bool {
+        if line.is_blank() || line.is_comment() {
+            return false;
+        }
+        self.num_frames += 1;
+        self.num_values += self.num_channels;
+        true
+    }
+
+    /// The number of whole frames which have been read.
+    #[inline]
+    fn num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    /// Check what was read against the `num_frames` of the motion header, once
+    /// the input has ended.
+    ///
+    /// In lenient mode a mismatch is only a warning. Returns the number of frames
+    /// to trust: those which were read in lenient mode, or else `num_frames`.
+    fn finish(
+        &self,
+        num_frames: usize,
+        options: &ParseOptions,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<usize, LoadMotionError> {
+        if !options.is_lenient() {
+            check_motion_count(
+                self.num_values,
+                self.num_channels,
+                num_frames,
+                self.num_frames_span,
+            )?;
+            return Ok(num_frames);
+        }
+
+        if self.num_frames != num_frames {
+            warnings.push(ParseWarning::FrameCountMismatch {
+                expected: num_frames,
+                actual: self.num_frames,
+                span: self.num_frames_span,
+            });
+        }
+        Ok(self.num_frames)
+    }
+}
+
+/// Check that the number of motion values read matches the motion header.
+fn check_motion_count(
+    num_values: usize,
+    num_channels: usize,
+    num_frames: usize,
+    num_frames_span: Span,
+) -> Result<(), LoadMotionError> {
+    let expected_total_motion_values = num_channels * num_frames;
+    if num_values != expected_total_motion_values {
+        return Err(LoadMotionError::MotionCountMismatch {
+            actual_total_motion_values: num_values,
+            expected_total_motion_values,
+            expected_num_frames: num_frames,
+            expected_num_clips: num_channels,
+            span: num_frames_span,
+        });
+    }
+
+    Ok(())
+}
+
+/// Parse the `Frames` line of the motion header.
+///
+/// Returns the number of frames and its location.
+fn parse_num_frames(
+    line: &Line<'_>,
+    dialect: &mut DialectDetector,
+) -> Result<(usize, Span), LoadMotionError> {
+    let mut tokens = line.keyword_tokens();
+
+    let keyword = match tokens.next() {
+        Some(keyword) if keyword.bytes().eq_ignore_ascii_case(FRAMES_KEYWORD) => keyword,
+        keyword => {
+            return Err(LoadMotionError::MissingNumFrames {
+                parse_error: None,
+                span: keyword.unwrap_or_else(|| line.trimmed()).span(),
+            });
+        }
+    };
+    dialect.keywords.observe(keyword.bytes(), FRAMES_KEYWORD);
+
+    // Some exporters write `Frames:123`, without a space after the colon.
+    let after_keyword = keyword.span_after().column;
+    if let [b':', c, ..] = line.bytes_from(after_keyword) {
+        if !c.is_ascii_whitespace() {
+            dialect.dialect.compact_frames = true;
+        }
+    }
+
+    match tokens.next() {
+        Some(token) => try_parse::<usize, _>(token.bytes())
+            .map(|num_frames| (num_frames, token.span()))
+            .map_err(|e| LoadMotionError::MissingNumFrames {
+                parse_error: Some(e),
+                span: token.span(),
+            }),
+        None => Err(LoadMotionError::MissingNumFrames {
+            parse_error: None,
+            span: keyword.span_after(),
+        }),
+    }
+}
+
+/// Returns `true` if the `line` starts with the `Frame Time` keyword.
+pub(crate) fn is_frame_time_line(line: &Line<'_>) -> bool {
+    let first_token = line.keyword_tokens().next().map(|tok| tok.bytes()).unwrap_or_default();
+    first_token.eq_ignore_ascii_case(FRAME_TIME_KEYWORDS[0])
+        || first_token.eq_ignore_ascii_case(FRAME_TIME_KEYWORD_COMPACT)
+}
+
+/// Parse the `Frame Time` line of the motion header.
+///
+/// The keyword may be written as `Frame Time:`, `Frame Time :`, `Frame time:`
+/// or `FrameTime:`.
+fn parse_frame_time(
+    line: &Line<'_>,
+    dialect: &mut DialectDetector,
+) -> Result<FrameRate, LoadMotionError> {
+    let mut tokens = line.keyword_tokens();
+
+    let missing_frame_time = |token: Option<Token<'_>>| {
+        let span = match token {
+            Some(token) => token.span(),
+            None => line.trimmed().span_after(),
+        };
+        LoadMotionError::MissingFrameTime {
+            parse_error: None,
+            span,
+        }
+    };
+
+    match tokens.next() {
+        Some(kw) if kw.bytes().eq_ignore_ascii_case(FRAME_TIME_KEYWORD_COMPACT) => {}
+        Some(kw) if kw.bytes().eq_ignore_ascii_case(FRAME_TIME_KEYWORDS[0]) => {
+            dialect.keywords.observe(kw.bytes(), FRAME_TIME_KEYWORDS[0]);
+            match tokens.next() {
+                Some(kw) if kw.bytes().eq_ignore_ascii_case(FRAME_TIME_KEYWORDS[1]) => {
+                    dialect.keywords.observe(kw.bytes(), FRAME_TIME_KEYWORDS[1]);
+                }
+                kw => return Err(missing_frame_time(kw)),
+            }
+        }
+        kw => return Err(missing_frame_time(kw)),
+    }
+
+    match tokens.next() {
+        Some(frame_time) => {
+            let frame_time_secs = try_parse::<f64, _>(frame_time.bytes()).map_err(|e| {
+                LoadMotionError::MissingFrameTime {
+                    parse_error: Some(e),
+                    span: frame_time.span(),
                 }
             })?;
+            // Read the digits as written where possible, rather than the rounded `f64`.
+            Ok(FrameRate::from_decimal(frame_time.bytes())
+                .unwrap_or_else(|| FrameRate::from_secs_f64(frame_time_secs)))
+        }
+        None => Err(missing_frame_time(None)),
+    }
+}
+
+impl Bvh {
+    /// Read the hierarchy and the motion header from the `reader`.
+    ///
+    /// Returns the location of the number of frames, and the number of the
+    /// first line after the motion header.
+    pub(crate) fn read_header_(
+        &mut self,
+        reader: &mut dyn BufReadExt,
+        decoder: &mut StreamDecoder,
+        options: &ParseOptions,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<(Span, usize), LoadError> {
+        let mut line_buf = vec![];
+        let mut line_num = 0;
+
+        let mut joints = JointsParser::new();
+        let mut found_motion = false;
+        while decoder
+            .read_line(reader, &mut line_buf)
+            .map_err(LoadJointsError::from)?
+            != 0
+        {
+            if joints.parse_line(&Line::new(line_num, &line_buf), options, warnings)? {
+                found_motion = true;
+                break;
+            }
+            line_buf.clear();
+            line_num += 1;
+        }
+        let dialect = joints.finish(self)?;
+        self.encoding = decoder.encoding();
+
+        let mut motion_header = MotionHeaderParser::new(false, dialect, options.limits);
+        if found_motion {
+            loop {
+                let line = Line::new(line_num, &line_buf);
+                if let Some(num_frames_span) = motion_header.parse_line(&line, self)? {
+                    return Ok((num_frames_span, line_num + 1));
+                }
+                line_buf.clear();
+                if decoder
+                    .read_line(reader, &mut line_buf)
+                    .map_err(LoadMotionError::from)?
+                    == 0
+                {
+                    break;
+                }
+                line_num += 1;
+            }
+        }
+
+        let eof_span = Span::new(line_num, 0, 0);
+        Err(motion_header.eof_error(eof_span).into())
+    }
+
+    /// Read the hierarchy and the motion header from the in-memory `data`.
+    ///
+    /// Returns the location of the number of frames, and the remaining lines
+    /// after the motion header.
+    pub(crate) fn read_header_bytes<'a>(
+        &mut self,
+        data: &'a [u8],
+        options: &ParseOptions,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<(Span, Lines<'a>), LoadError> {
+        let mut lines = Lines::new(data, 0);
+
+        let mut joints = JointsParser::new();
+        let mut motion_line = None;
+        for line in &mut lines {
+            if joints.parse_line(&line, options, warnings)? {
+                motion_line = Some(line);
+                break;
+            }
+        }
+        let dialect = joints.finish(self)?;
+
+        let mut motion_header = MotionHeaderParser::new(false, dialect, options.limits);
+        let mut last_line_num = 0;
+        while let Some(line) = motion_line.take().or_else(|| lines.next()) {
+            last_line_num = line.num();
+            if let Some(num_frames_span) = motion_header.parse_line(&line, self)? {
+                return Ok((num_frames_span, lines));
+            }
+        }
 
-        let expected_total_motion_values = self.num_channels * self.num_frames;
+        let eof_span = Span::new(last_line_num, 0, 0);
+        Err(motion_header.eof_error(eof_span).into())
+    }
+
+    /// Read the motion values which follow the motion header from the in-memory
+    /// `data`.
+    ///
+    /// Lines are numbered from `first_line_num`.
+    pub(crate) fn read_motion_bytes(
+        &mut self,
+        data: &[u8],
+        first_line_num: usize,
+        num_frames_span: Span,
+        options: &ParseOptions,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<(), LoadMotionError> {
+        let num_channels = self.motion.num_channels;
+
+        #[cfg(feature = "rayon")]
+        {
+            if !options.is_lenient() {
+                self.read_motion_values_parallel(data, first_line_num)?;
+                let num_values = self.motion.values.len();
+                return check_motion_count(
+                    num_values,
+                    num_channels,
+                    self.motion.num_frames,
+                    num_frames_span,
+                );
+            }
+        }
+
+        if !options.is_lenient() {
+            // Every value takes at least two bytes including its separator, so the
+            // size of the data bounds the number of values, whatever the header says.
+            let max_num_values = data.len() / 2 + 1;
+            let num_values = num_channels * self.motion.num_frames;
+            self.motion
+                .values
+                .reserve(cmp::min(num_values, max_num_values));
+        }
+
+        let mut motion = MotionParser::new(num_channels, num_frames_span, false);
+        for line in Lines::new(data, first_line_num) {
+            motion.parse_line(&line, options, &mut self.motion.values, warnings)?;
+        }
+
+        self.motion.num_frames = motion.finish(self.motion.num_frames, options, warnings)?;
+        Ok(())
+    }
+
+    /// Parse the motion values in `data` in parallel.
+    ///
+    /// The data is split into line-aligned chunks. The values in each chunk are
+    /// counted first, so that every chunk can then be parsed directly into its
+    /// own slice of `motion_values`.
+    #[cfg(feature = "rayon")]
+    fn read_motion_values_parallel(
+        &mut self,
+        data: &[u8],
+        first_line_num: usize,
+    ) -> Result<(), LoadMotionError> {
+        use rayon::prelude::*;
+
+        const MIN_CHUNK_LEN: usize = 64 * 1024;
+
+        let chunk_len = cmp::max(
+            data.len() / (rayon::current_num_threads() * 4).max(1),
+            MIN_CHUNK_LEN,
+        );
+
+        let mut chunks = vec![];
+        let mut remaining = data;
+        while !remaining.is_empty() {
+            let min_len = cmp::min(chunk_len, remaining.len());
+            let split = remaining[min_len..]
+                .find_byte(b'\n')
+                .map_or(remaining.len(), |newline| min_len + newline + 1);
+            let (chunk, rest) = remaining.split_at(split);
+            chunks.push(chunk);
+            remaining = rest;
+        }
 
-        self.motion_values.reserve(expected_total_motion_values);
+        // The number of lines and values in each chunk.
+        let counts = chunks
+            .par_iter()
+            .map(|chunk| {
+                let mut lines = Lines::new(chunk, 0);
+                let num_values = (&mut lines)
+                    .filter(|line| !line.is_comment())
+                    .map(|line| line.tokens().count())
+                    .sum::<usize>();
+                (lines.line_num(), num_values)
+            })
+            .collect::<Vec<_>>();
+
+        let total_values = counts.iter().map(|&(_, num_values)| num_values).sum();
+        self.motion.values.clear();
+        self.motion.values.resize(total_values, 0.0);
+
+        let mut jobs = Vec::with_capacity(chunks.len());
+        let mut motion_values = &mut self.motion.values[..];
+        let mut line_num = first_line_num;
+        for (chunk, &(num_lines, num_values)) in chunks.iter().zip(&counts) {
+            let (values, rest) = motion_values.split_at_mut(num_values);
+            jobs.push((*chunk, values, line_num));
+            motion_values = rest;
+            line_num += num_lines;
+        }
 
-        for (line_num, line) in lines {
-            let line = line?;
-            let tokens = line.fields();
-            for (channel_index, token) in tokens.enumerate() {
-                let motion = try_parse::<f32, _>(token).map_err(|e| {
-                    LoadMotionError::ParseMotionSection {
-                        parse_error: e,
-                        channel_index,
-                        line: line_num,
+        let results = jobs
+            .into_par_iter()
+            .map(|(chunk, values, first_line_num)| {
+                let mut values = values.iter_mut();
+                for line in Lines::new(chunk, first_line_num) {
+                    if line.is_comment() {
+                        continue;
                     }
-                })?;
-                self.motion_values.push(motion);
+                    for (channel_index, token) in line.tokens().enumerate() {
+                        let motion = try_parse::<Float, _>(token.bytes()).map_err(|e| {
+                            LoadMotionError::ParseMotionSection {
+                                parse_error: e,
+                                channel_index,
+                                span: token.span(),
+                            }
+                        })?;
+                        if let Some(value) = values.next() {
+                            *value = motion;
+                        }
+                    }
+                }
+                Ok(())
+            })
+            .collect::<Vec<Result<(), LoadMotionError>>>();
+
+        // Report the error which is earliest in the file.
+        results.into_iter().collect()
+    }
+}
+
+/// Parse a line of motion values, appending the values to `motion_values`.
+///
+/// Unlike `read_frame`, the line does not need to contain a whole frame.
+fn read_motion_line(
+    line: &Line<'_>,
+    motion_values: &mut Vec<Float>,
+) -> Result<(), LoadMotionError> {
+    let mut tokens = line.tokens().peekable();
+    if matches!(tokens.peek(), Some(token) if token.bytes().starts_with(b"#")) {
+        return Ok(());
+    }
+
+    for (channel_index, token) in tokens.enumerate() {
+        let motion = try_parse::<Float, _>(token.bytes()).map_err(|e| {
+            LoadMotionError::ParseMotionSection {
+                parse_error: e,
+                channel_index,
+                span: token.span(),
+            }
+        })?;
+        motion_values.push(motion);
+    }
+
+    Ok(())
+}
+
+/// Parse a line which contains a single frame of motion values, appending the
+/// values to `motion_values`.
+///
+/// Returns `Ok(true)` if a frame was read, or `Ok(false)` if the line was blank
+/// or was skipped.
+fn read_frame(
+    line: &Line<'_>,
+    num_channels: usize,
+    options: &ParseOptions,
+    motion_values: &mut Vec<Float>,
+    warnings: &mut Vec<ParseWarning>,
+) -> Result<bool, LoadMotionError> {
+    let frame_start = motion_values.len();
+    let span = line.trimmed().span();
+
+    let mut tokens = line.tokens().peekable();
+    if matches!(tokens.peek(), Some(token) if token.bytes().starts_with(b"#")) {
+        return Ok(false);
+    }
+
+    for (channel_index, token) in tokens.enumerate() {
+        match try_parse::<Float, _>(token.bytes()) {
+            Ok(motion) => motion_values.push(motion),
+            Err(_) if options.is_lenient() => {
+                motion_values.truncate(frame_start);
+                warnings.push(ParseWarning::SkippedLine { span });
+                return Ok(false);
+            }
+            Err(e) => {
+                return Err(LoadMotionError::ParseMotionSection {
+                    parse_error: e,
+                    channel_index,
+                    span: token.span(),
+                });
             }
         }
+    }
+
+    let num_values = motion_values.len() - frame_start;
+    if num_values == 0 {
+        return Ok(false);
+    }
 
-        if self.motion_values.len() != self.num_channels * self.num_frames {
-            return Err(LoadMotionError::MotionCountMismatch {
-                actual_total_motion_values: self.motion_values.len(),
-                expected_total_motion_values,
-                expected_num_frames: self.num_frames,
-                expected_num_clips: self.num_channels,
+    if num_values != num_channels {
+        if !options.is_lenient() {
+            return Err(LoadMotionError::FrameLengthMismatch {
+                expected: num_channels,
+                actual: num_values,
+                span,
+            });
+        } else if num_values < num_channels {
+            warnings.push(ParseWarning::FramePadded {
+                expected: num_channels,
+                actual: num_values,
+                span,
+            });
+        } else {
+            warnings.push(ParseWarning::FrameTruncated {
+                expected: num_channels,
+                actual: num_values,
+                span,
             });
         }
 
-        Ok(())
+        motion_values.resize(frame_start + num_channels, 0.0);
     }
+
+    Ok(true)
 }
@@ -0,0 +1,320 @@
+//! Selection of joints by name.
+//!
+//! A [`Selector`][`Selector`] describes a set of joints, and is evaluated against
+//! a `Bvh` with [`Bvh::select`][`Bvh::select`]:
+//!
+//! ```
+//! # use bvh_anim::{select::Selector, Bvh};
+//! let bvh = Bvh::from_bytes(&br#"
+//!     HIERARCHY
+//!     ROOT mixamorig:Hips
+//!     {
+//!         OFFSET 0.0 0.0 0.0
+//!         CHANNELS 3 Xposition Yposition Zposition
+//!         JOINT mixamorig:LeftUpLeg
+//!         {
+//!             OFFSET 0.0 0.0 0.0
+//!             CHANNELS 3 Zrotation Xrotation Yrotation
+//!             JOINT mixamorig:LeftLeg
+//!             {
+//!                 OFFSET 0.0 0.0 0.0
+//!                 CHANNELS 3 Zrotation Xrotation Yrotation
+//!                 End Site
+//!                 {
+//!                     OFFSET 0.0 0.0 0.0
+//!                 }
+//!             }
+//!         }
+//!         JOINT mixamorig:RightUpLeg
+//!         {
+//!             OFFSET 0.0 0.0 0.0
+//!             CHANNELS 3 Zrotation Xrotation Yrotation
+//!             End Site
+//!             {
+//!                 OFFSET 0.0 0.0 0.0
+//!             }
+//!         }
+//!     }
+//!     MOTION
+//!     Frames: 0
+//!     Frame Time: 0.033333333
+//! "#[..])?;
+//!
+//! let left = bvh.select(&Selector::glob("Left*").strip_namespace());
+//! assert_eq!(left.len(), 2);
+//! assert_eq!(left.channels().count(), 6);
+//!
+//! let left_leg = bvh.select(&Selector::name("mixamorig:LeftUpLeg").subtree());
+//! assert_eq!(left_leg.indices(), left.indices());
+//! # Result::<(), bvh_anim::errors::LoadError>::Ok(())
+//! ```
+//!
+//! [`Selector`]: struct.Selector.html
+//! [`Bvh::select`]: ../struct.Bvh.html#method.select
+
+use crate::{Bvh, Channel, Joint, JointIndex};
+use std::slice;
+
+/// A description of a set of joints in a `Bvh`.
+///
+/// Each `Selector` matches the names of joints against a pattern. The name of
+/// each joint can optionally have a namespace removed before it is matched, and
+/// the selection can be extended to the descendants of each matched joint.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Selector<'a> {
+    pattern: Pattern<'a>,
+    prefix: Prefix<'a>,
+    subtree: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum Pattern<'a> {
+    All,
+    Name(&'a [u8]),
+    Glob(&'a [u8]),
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum Prefix<'a> {
+    Keep,
+    Strip(&'a [u8]),
+    Namespace,
+}
+
+impl<'a> Selector<'a> {
+    /// Select every joint.
+    #[inline]
+    pub const fn all() -> Self {
+        Selector::with_pattern(Pattern::All)
+    }
+
+    /// Select the joints whose names are exactly `name`.
+    #[inline]
+    pub fn name<N: AsRef<[u8]> + ?Sized>(name: &'a N) -> Self {
+        Selector::with_pattern(Pattern::Name(name.as_ref()))
+    }
+
+    /// Select the joints whose names match the glob `pattern`.
+    ///
+    /// In the pattern, `*` matches any sequence of bytes (including none), and `?`
+    /// matches any single byte. All other bytes match themselves.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::select::Selector;
+    /// assert!(Selector::glob("Left*").matches(b"LeftHand"));
+    /// assert!(Selector::glob("*Hand").matches(b"LeftHand"));
+    /// assert!(Selector::glob("Left?and").matches(b"LeftHand"));
+    /// assert!(!Selector::glob("Left*").matches(b"RightHand"));
+    /// ```
+    #[inline]
+    pub fn glob<P: AsRef<[u8]> + ?Sized>(pattern: &'a P) -> Self {
+        Selector::with_pattern(Pattern::Glob(pattern.as_ref()))
+    }
+
+    #[inline]
+    const fn with_pattern(pattern: Pattern<'a>) -> Self {
+        Selector {
+            pattern,
+            prefix: Prefix::Keep,
+            subtree: false,
+        }
+    }
+
+    /// Remove `prefix` from the start of each joint name before it is matched.
+    ///
+    /// Names which do not start with `prefix` are matched unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::select::Selector;
+    /// let selector = Selector::name("Hips").strip_prefix("mixamorig:");
+    /// assert!(selector.matches(b"mixamorig:Hips"));
+    /// assert!(selector.matches(b"Hips"));
+    /// assert!(!selector.matches(b"Character1:Hips"));
+    /// ```
+    #[inline]
+    pub fn strip_prefix<P: AsRef<[u8]> + ?Sized>(mut self, prefix: &'a P) -> Self {
+        self.prefix = Prefix::Strip(prefix.as_ref());
+        self
+    }
+
+    /// Remove the namespace from each joint name before it is matched.
+    ///
+    /// The namespace is everything up to and including the last `:` in the name,
+    /// as written by exporters such as Mixamo (`mixamorig:Hips`) and Maya.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::select::Selector;
+    /// let selector = Selector::name("Hips").strip_namespace();
+    /// assert!(selector.matches(b"mixamorig:Hips"));
+    /// assert!(selector.matches(b"Character1:Hips"));
+    /// assert!(selector.matches(b"Hips"));
+    /// ```
+    #[inline]
+    pub fn strip_namespace(mut self) -> Self {
+        self.prefix = Prefix::Namespace;
+        self
+    }
+
+    /// Also select every descendant of each joint which matches the pattern.
+    #[inline]
+    pub fn subtree(mut self) -> Self {
+        self.subtree = true;
+        self
+    }
+
+    /// Returns `true` if a joint called `name` matches the pattern of the
+    /// `Selector`.
+    ///
+    /// This does not take [`Selector::subtree`][`Selector::subtree`] into account.
+    ///
+    /// [`Selector::subtree`]: #method.subtree
+    pub fn matches(&self, name: &[u8]) -> bool {
+        let name = match self.prefix {
+            Prefix::Keep => name,
+            Prefix::Strip(prefix) => name.strip_prefix(prefix).unwrap_or(name),
+            Prefix::Namespace => match name.iter().rposition(|&b| b == b':') {
+                Some(pos) => &name[pos + 1..],
+                None => name,
+            },
+        };
+
+        match self.pattern {
+            Pattern::All => true,
+            Pattern::Name(expected) => name == expected,
+            Pattern::Glob(pattern) => glob_matches(pattern, name),
+        }
+    }
+
+    pub(crate) fn select<'b>(&self, bvh: &'b Bvh) -> Selection<'b> {
+        let mut selected: Vec<bool> = bvh.joints().map(|joint| self.matches(&joint.name)).collect();
+
+        if self.subtree {
+            for i in 0..selected.len() {
+                if selected[i] {
+                    for descendant in bvh.descendants(JointIndex::new(i)) {
+                        selected[descendant.get()] = true;
+                    }
+                }
+            }
+        }
+
+        let indices = selected
+            .iter()
+            .enumerate()
+            .filter(|&(_, &selected)| selected)
+            .map(|(i, _)| JointIndex::new(i))
+            .collect();
+
+        Selection { bvh, indices }
+    }
+}
+
+impl Default for Selector<'_> {
+    #[inline]
+    fn default() -> Self {
+        Selector::all()
+    }
+}
+
+/// Match `name` against a glob `pattern` containing `*` and `?` wildcards.
+fn glob_matches(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // The position in the pattern after the last `*`, and the position in the
+    // name which it has been matched up to.
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(&b) if b == b'?' || b == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, n));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+/// The joints of a `Bvh` which were chosen by a [`Selector`][`Selector`].
+///
+/// The joints are in file order, and each joint appears at most once.
+///
+/// Created by [`Bvh::select`][`Bvh::select`].
+///
+/// [`Selector`]: struct.Selector.html
+/// [`Bvh::select`]: ../struct.Bvh.html#method.select
+#[derive(Clone, Debug)]
+pub struct Selection<'a> {
+    bvh: &'a Bvh,
+    indices: Vec<JointIndex>,
+}
+
+impl<'a> Selection<'a> {
+    /// Returns the indices of the selected joints.
+    #[inline]
+    pub fn indices(&self) -> &[JointIndex] {
+        &self.indices
+    }
+
+    /// Returns an iterator over the selected joints and their indices.
+    #[inline]
+    pub fn joints(&self) -> impl Iterator<Item = (JointIndex, &'a Joint)> + '_ {
+        let bvh = self.bvh;
+        self.indices.iter().map(move |&idx| (idx, &bvh[idx]))
+    }
+
+    /// Returns an iterator over the channels of all of the selected joints.
+    ///
+    /// The motion index of each `Channel` can be used to look up its value in
+    /// a `Frame`.
+    #[inline]
+    pub fn channels(&self) -> impl Iterator<Item = &'a Channel> + '_ {
+        self.joints().flat_map(|(_, joint)| joint.channels.iter())
+    }
+
+    /// Returns the number of selected joints.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Returns `true` if no joints were selected.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Consume the `Selection`, returning the indices of the selected joints.
+    #[inline]
+    pub fn into_indices(self) -> Vec<JointIndex> {
+        self.indices
+    }
+}
+
+impl<'a, 's> IntoIterator for &'s Selection<'a> {
+    type Item = &'s JointIndex;
+    type IntoIter = slice::Iter<'s, JointIndex>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.indices.iter()
+    }
+}
@@ -0,0 +1,258 @@
+use crate::{
+    hierarchy::{Ancestors, BreadthFirst, ChildLists, Children, DepthFirst},
+    Joint, JointIndex, JointName,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    iter,
+    ops::{Index, Range},
+    slice::{Iter, IterMut},
+    sync::OnceLock,
+};
+
+/// The joint hierarchy of a `Bvh`.
+///
+/// Clips of the same character often share an identical hierarchy, so a
+/// `Skeleton` is held in an [`Arc`][`Arc`] and can be shared between many
+/// [`Bvh`][`Bvh`]s. See [`Bvh::from_bytes_with_skeleton`][`Bvh::from_bytes_with_skeleton`]
+/// to load a clip against an existing `Skeleton`.
+///
+/// Editing the joints of a `Bvh` whose `Skeleton` is shared gives it its own
+/// copy of the `Skeleton` first.
+///
+/// [`Arc`]: https://doc.rust-lang.org/std/sync/struct.Arc.html
+/// [`Bvh`]: struct.Bvh.html
+/// [`Bvh::from_bytes_with_skeleton`]: struct.Bvh.html#method.from_bytes_with_skeleton
+#[derive(Clone, Default, Debug)]
+pub struct Skeleton {
+    /// The list of joints. If the root joint exists, it is always at
+    /// index `0`.
+    pub(crate) joints: Vec<Joint>,
+    /// The number of `Channel`s of all of the joints.
+    pub(crate) num_channels: usize,
+    /// The children of each joint, built the first time they are needed.
+    child_lists: OnceLock<ChildLists>,
+    /// The index of the first joint with each name, built the first time it is needed.
+    names: OnceLock<HashMap<JointName, JointIndex>>,
+}
+
+impl Skeleton {
+    /// Create an empty `Skeleton`.
+    #[inline]
+    pub const fn new() -> Self {
+        Skeleton {
+            joints: Vec::new(),
+            num_channels: 0,
+            child_lists: OnceLock::new(),
+            names: OnceLock::new(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn from_joints(joints: Vec<Joint>, num_channels: usize) -> Self {
+        Skeleton {
+            joints,
+            num_channels,
+            ..Skeleton::new()
+        }
+    }
+
+    /// Returns the root joint of the first hierarchy if it exists, or `None` if
+    /// the `Skeleton` is empty.
+    #[inline]
+    pub fn root_joint(&self) -> Option<&Joint> {
+        self.joints.first()
+    }
+
+    /// Returns an iterator over the root `Joint` of each hierarchy, in file order.
+    #[inline]
+    pub fn root_joints(&self) -> impl Iterator<Item = &Joint> + '_ {
+        self.joints.iter().filter(|joint| joint.is_root())
+    }
+
+    /// Returns an iterator over the root `Joint` of each hierarchy, along with the
+    /// range of motion channels which belong to the joints in that hierarchy.
+    pub fn root_channel_ranges(&self) -> impl Iterator<Item = (&Joint, Range<usize>)> + '_ {
+        let mut joints = self.joints.iter().peekable();
+        let mut start = 0;
+
+        iter::from_fn(move || {
+            let root = joints.next()?;
+            let mut num_channels = root.channels.len();
+            while let Some(joint) = joints.next_if(|joint| !joint.is_root()) {
+                num_channels += joint.channels.len();
+            }
+
+            let range = start..start + num_channels;
+            start = range.end;
+            Some((root, range))
+        })
+    }
+
+    /// Returns an iterator over all the `Joint`s of the `Skeleton`.
+    #[inline]
+    pub fn joints(&self) -> Iter<'_, Joint> {
+        self.joints.iter()
+    }
+
+    pub(crate) fn joints_mut(&mut self) -> IterMut<'_, Joint> {
+        self.names = OnceLock::new();
+        self.joints.iter_mut()
+    }
+
+    /// Get the number of joints in the `Skeleton`.
+    #[inline]
+    pub fn num_joints(&self) -> usize {
+        self.joints.len()
+    }
+
+    /// Get the number of channels of all of the joints in the `Skeleton`.
+    #[inline]
+    pub const fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    /// Returns the `Joint` at `index`, or `None` if it is out of bounds.
+    #[inline]
+    pub fn joint(&self, index: JointIndex) -> Option<&Joint> {
+        self.joints.get(index.get())
+    }
+
+    pub(crate) fn joint_mut(&mut self, index: JointIndex) -> Option<&mut Joint> {
+        self.names = OnceLock::new();
+        self.joints.get_mut(index.get())
+    }
+
+    /// Returns an iterator over the `JointIndex` of every `Joint`, in file order.
+    #[inline]
+    pub fn joint_indices(&self) -> impl DoubleEndedIterator<Item = JointIndex> + ExactSizeIterator {
+        (0..self.joints.len()).map(JointIndex::new)
+    }
+
+    /// Returns the index of the first `Joint` called `name`, or `None` if there
+    /// is no such joint.
+    pub fn joint_by_name<N: AsRef<[u8]>>(&self, name: N) -> Option<JointIndex> {
+        let names = self.names.get_or_init(|| {
+            let mut names = HashMap::with_capacity(self.joints.len());
+            for (i, joint) in self.joints.iter().enumerate() {
+                names
+                    .entry(joint.name.clone())
+                    .or_insert(JointIndex::new(i));
+            }
+            names
+        });
+        names.get(name.as_ref()).copied()
+    }
+
+    /// Returns an iterator over the direct children of the `Joint` at `index`,
+    /// in file order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds. This applies to all of the hierarchy
+    /// methods.
+    #[inline]
+    pub fn children(&self, index: JointIndex) -> Children<'_> {
+        self.assert_joint_index(index);
+        Children {
+            iter: self.child_lists().children(index).iter(),
+        }
+    }
+
+    /// Returns a depth-first iterator over all of the descendants of the `Joint`
+    /// at `index`, not including the joint itself.
+    pub fn descendants(&self, index: JointIndex) -> DepthFirst<'_> {
+        self.assert_joint_index(index);
+        let child_lists = self.child_lists();
+        DepthFirst {
+            child_lists,
+            stack: child_lists.children(index).iter().rev().copied().collect(),
+        }
+    }
+
+    /// Returns a breadth-first iterator over all of the descendants of the
+    /// `Joint` at `index`, not including the joint itself.
+    pub fn descendants_breadth_first(&self, index: JointIndex) -> BreadthFirst<'_> {
+        self.assert_joint_index(index);
+        let child_lists = self.child_lists();
+        BreadthFirst {
+            child_lists,
+            queue: child_lists
+                .children(index)
+                .iter()
+                .copied()
+                .collect::<VecDeque<_>>(),
+        }
+    }
+
+    /// Returns an iterator over the ancestors of the `Joint` at `index`, starting
+    /// with its parent and ending with its root.
+    #[inline]
+    pub fn ancestors(&self, index: JointIndex) -> Ancestors<'_> {
+        Ancestors {
+            joints: &self.joints,
+            next: self.joints[index.get()].parent(),
+        }
+    }
+
+    /// Returns an iterator over the path from the `Joint` at `index` up to its
+    /// root, including both the joint and the root.
+    #[inline]
+    pub fn path_to_root(&self, index: JointIndex) -> Ancestors<'_> {
+        self.assert_joint_index(index);
+        Ancestors {
+            joints: &self.joints,
+            next: Some(index),
+        }
+    }
+
+    /// Returns the deepest `Joint` which is an ancestor of both `a` and `b`, or
+    /// `None` if they are in different hierarchies.
+    pub fn lowest_common_ancestor(&self, a: JointIndex, b: JointIndex) -> Option<JointIndex> {
+        let depth_of = |index: JointIndex| self.joints[index.get()].depth();
+        let (mut a, mut b) = (a, b);
+        while depth_of(a) > depth_of(b) {
+            a = self.joints[a.get()].parent()?;
+        }
+        while depth_of(b) > depth_of(a) {
+            b = self.joints[b.get()].parent()?;
+        }
+        while a != b {
+            a = self.joints[a.get()].parent()?;
+            b = self.joints[b.get()].parent()?;
+        }
+        Some(a)
+    }
+
+    #[inline]
+    fn child_lists(&self) -> &ChildLists {
+        self.child_lists
+            .get_or_init(|| ChildLists::new(&self.joints))
+    }
+
+    #[inline]
+    fn assert_joint_index(&self, index: JointIndex) {
+        assert!(
+            index.get() < self.joints.len(),
+            "joint index {} out of bounds for a skeleton with {} joints",
+            index,
+            self.joints.len()
+        );
+    }
+}
+
+// The caches are built from the joints, so they do not need to be compared.
+impl PartialEq for Skeleton {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.joints == other.joints && self.num_channels == other.num_channels
+    }
+}
+
+impl Index<JointIndex> for Skeleton {
+    type Output = Joint;
+    #[inline]
+    fn index(&self, index: JointIndex) -> &Self::Output {
+        &self.joints[index.get()]
+    }
+}
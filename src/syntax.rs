@@ -0,0 +1,464 @@
+//! Contains a lossless syntax tree of a loaded `bvh` file.
+//!
+//! The syntax tree keeps the original text of every token, along with the
+//! whitespace, comments and line endings between them. When a `Bvh` which
+//! was loaded with [`ParseOptions::preserve_syntax`][`ParseOptions::preserve_syntax`]
+//! is written out, any values which have not been edited are written using their
+//! original text, so that an unmodified file is written back byte-for-byte.
+//!
+//! [`ParseOptions::preserve_syntax`]: ../parse/struct.ParseOptions.html#structfield.preserve_syntax
+
+use bstr::{BStr, ByteSlice};
+use crate::{
+    parse::{
+        is_frame_time_line, CHANNELS_KEYWORD, CLOSE_BRACE, ENDSITE_KEYWORDS,
+        FRAMES_KEYWORD, HEIRARCHY_KEYWORD, JOINT_KEYWORD, MOTION_KEYWORD, OFFSET_KEYWORD,
+        OPEN_BRACE, ROOT_KEYWORD,
+    },
+    tokenizer::Line,
+    write::LineTerminator,
+    Bvh, Joint,
+};
+use smallvec::SmallVec;
+use std::{fmt, slice};
+
+/// A lossless syntax tree of a `bvh` file, which records the kind of each line
+/// and the original text of each token.
+///
+/// # Examples
+///
+/// ```
+/// # use bvh_anim::{parse::ParseOptions, syntax::LineKind};
+/// let bvh_string = b"HIERARCHY
+/// ROOT Hips
+/// {
+///     OFFSET 0.0 0.0 0.0
+///     CHANNELS 3 Xposition Yposition Zposition
+///     End Site
+///     {
+///         OFFSET 0.0 0.0 0.0
+///     }
+/// }
+/// MOTION
+/// Frames: 1
+/// Frame Time: 0.033333333
+/// 0.0 0.0     0.0
+/// ";
+///
+/// let (bvh, _) = ParseOptions::new()
+///     .with_preserve_syntax(true)
+///     .parse_bytes(&bvh_string[..])?;
+///
+/// let syntax = bvh.syntax().unwrap();
+/// let last_line = syntax.lines().last().unwrap();
+/// assert_eq!(last_line.kind(), LineKind::MotionValues(0));
+/// assert_eq!(last_line.tokens().nth(2).unwrap().leading_trivia(), "     ");
+///
+/// assert_eq!(bvh.to_bstring(), &bvh_string[..]);
+/// # Result::<(), bvh_anim::errors::LoadError>::Ok(())
+/// ```
+#[derive(Clone, Eq, PartialEq)]
+pub struct SyntaxTree {
+    source: Vec<u8>,
+    lines: Vec<LineData>,
+    /// The shape of each joint at load time, to check whether the hierarchy
+    /// has been restructured since.
+    joint_shapes: Vec<JointShape>,
+    num_channels: usize,
+    num_values: usize,
+}
+
+impl SyntaxTree {
+    /// Build the syntax tree of the `source` which `bvh` was parsed from.
+    ///
+    /// Returns `None` if the lines of the `source` cannot be matched up with
+    /// the joints, end sites and motion values in `bvh`.
+    pub(crate) fn new(source: &[u8], bvh: &Bvh) -> Option<Self> {
+        let mut classifier = LineClassifier::default();
+        let mut lines = vec![];
+
+        let mut start = 0;
+        for raw_line in source.lines_with_terminator() {
+            let end = start + raw_line.len();
+            let text = raw_line.strip_suffix(b"\n").unwrap_or(raw_line);
+            let text = text.strip_suffix(b"\r").unwrap_or(text);
+
+            lines.push(LineData {
+                start,
+                content_end: start + text.len(),
+                end,
+                kind: classifier.classify(text),
+            });
+            start = end;
+        }
+
+        if classifier.section != Section::Motion
+            || classifier.num_joints != bvh.skeleton.joints.len()
+            || classifier.num_values != bvh.motion.values.len()
+        {
+            return None;
+        }
+
+        // A lenient parse may have dropped an `End Site`, in which case its lines
+        // no longer belong to the joint.
+        let mut has_end_site = vec![false; classifier.num_joints];
+        for line in &lines {
+            if let LineKind::EndSite(index) = line.kind {
+                has_end_site[index] = true;
+            }
+        }
+        if has_end_site
+            .iter()
+            .zip(&bvh.skeleton.joints)
+            .any(|(&has_end_site, joint)| has_end_site != joint.has_end_site())
+        {
+            return None;
+        }
+
+        Some(SyntaxTree {
+            source: source.to_vec(),
+            lines,
+            joint_shapes: bvh.skeleton.joints.iter().map(JointShape::new).collect(),
+            num_channels: bvh.motion.num_channels,
+            num_values: classifier.num_values,
+        })
+    }
+
+    /// Returns the original text of the file.
+    #[inline]
+    pub fn source(&self) -> &BStr {
+        self.source.as_bstr()
+    }
+
+    /// Returns an iterator over the lines of the file.
+    #[inline]
+    pub fn lines(&self) -> Lines<'_> {
+        Lines {
+            source: &self.source[..],
+            lines: self.lines.iter(),
+        }
+    }
+
+    /// Returns the line terminator used by the first line of the file, or `None`
+    /// if the file is a single line.
+    pub fn line_terminator(&self) -> Option<LineTerminator> {
+        self.lines
+            .iter()
+            .find(|line| line.end > line.content_end)
+            .map(|line| match line.end - line.content_end {
+                1 => LineTerminator::Unix,
+                _ => LineTerminator::Windows,
+            })
+    }
+
+    /// Returns `true` if the joints of `bvh` still have the same structure as
+    /// when the file was loaded, so that each line of the hierarchy refers to the
+    /// same joint.
+    pub(crate) fn hierarchy_matches(&self, bvh: &Bvh) -> bool {
+        self.joint_shapes.len() == bvh.skeleton.joints.len()
+            && self
+                .joint_shapes
+                .iter()
+                .zip(&bvh.skeleton.joints)
+                .all(|(shape, joint)| *shape == JointShape::new(joint))
+    }
+
+    /// The number of channels in the file when it was loaded.
+    #[inline]
+    pub(crate) fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    /// The number of motion values in the file when it was loaded.
+    #[inline]
+    pub(crate) fn num_values(&self) -> usize {
+        self.num_values
+    }
+}
+
+impl fmt::Debug for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyntaxTree")
+            .field("len", &self.source.len())
+            .field("lines", &self.lines.len())
+            .finish()
+    }
+}
+
+/// What a line of a `bvh` file contains.
+///
+/// Variants which refer to a joint contain the index of the joint in
+/// [`Bvh::joints`][`Bvh::joints`].
+///
+/// [`Bvh::joints`]: ../struct.Bvh.html#method.joints
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LineKind {
+    /// A line which only contains whitespace.
+    Blank,
+    /// A `#` comment.
+    Comment,
+    /// The `HIERARCHY` keyword.
+    Hierarchy,
+    /// The `ROOT` or `JOINT` line which names a joint.
+    Joint(usize),
+    /// The `End Site` line of a joint.
+    EndSite(usize),
+    /// The `OFFSET` of a joint.
+    Offset(usize),
+    /// The `OFFSET` of the end site of a joint.
+    EndSiteOffset(usize),
+    /// The `CHANNELS` of a joint.
+    Channels(usize),
+    /// An opening brace.
+    OpenBrace,
+    /// A closing brace.
+    CloseBrace,
+    /// The `MOTION` keyword.
+    Motion,
+    /// The `Frames` line of the motion header.
+    Frames,
+    /// The `Frame Time` line of the motion header.
+    FrameTime,
+    /// A line of motion values, which contains the index of its first value in
+    /// the motion values of the `Bvh`.
+    MotionValues(usize),
+    /// A line which was ignored by the parser.
+    Unknown,
+}
+
+/// An iterator over the lines of a `SyntaxTree`.
+#[derive(Clone, Debug)]
+pub struct Lines<'a> {
+    source: &'a [u8],
+    lines: slice::Iter<'a, LineData>,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = SyntaxLine<'a>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let source = self.source;
+        self.lines.next().map(|line| SyntaxLine {
+            text: &source[line.start..line.content_end],
+            terminator: &source[line.content_end..line.end],
+            kind: line.kind,
+        })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.lines.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Lines<'_> {}
+
+/// A single line of a `SyntaxTree`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SyntaxLine<'a> {
+    text: &'a [u8],
+    terminator: &'a [u8],
+    kind: LineKind,
+}
+
+impl<'a> SyntaxLine<'a> {
+    /// Returns what the line contains.
+    #[inline]
+    pub fn kind(&self) -> LineKind {
+        self.kind
+    }
+
+    /// Returns the text of the line, without the line terminator.
+    #[inline]
+    pub fn text(&self) -> &'a BStr {
+        self.text.as_bstr()
+    }
+
+    /// Returns the line terminator, which is empty for the last line of a
+    /// file which does not end with a new line.
+    #[inline]
+    pub fn terminator(&self) -> &'a BStr {
+        self.terminator.as_bstr()
+    }
+
+    /// Returns an iterator over the whitespace-separated tokens of the line.
+    #[inline]
+    pub fn tokens(&self) -> Tokens<'a> {
+        Tokens { rest: self.text }
+    }
+
+    /// Returns the whitespace after the last token of the line.
+    #[inline]
+    pub fn trailing_trivia(&self) -> &'a BStr {
+        self.text[self.text.trim_end().len()..].as_bstr()
+    }
+}
+
+/// An iterator over the tokens of a `SyntaxLine`.
+#[derive(Clone, Debug)]
+pub struct Tokens<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.rest.iter().position(|c| !c.is_ascii_whitespace())?;
+        let end = self.rest[start..]
+            .iter()
+            .position(u8::is_ascii_whitespace)
+            .map_or(self.rest.len(), |len| start + len);
+
+        let token = Token {
+            leading_trivia: &self.rest[..start],
+            text: &self.rest[start..end],
+        };
+        self.rest = &self.rest[end..];
+        Some(token)
+    }
+}
+
+/// A token of a `SyntaxLine`, along with the whitespace before it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Token<'a> {
+    leading_trivia: &'a [u8],
+    text: &'a [u8],
+}
+
+impl<'a> Token<'a> {
+    /// Returns the whitespace before the token.
+    #[inline]
+    pub fn leading_trivia(&self) -> &'a BStr {
+        self.leading_trivia.as_bstr()
+    }
+
+    /// Returns the original text of the token.
+    #[inline]
+    pub fn text(&self) -> &'a BStr {
+        self.text.as_bstr()
+    }
+}
+
+/// The location and kind of a line in the source.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct LineData {
+    start: usize,
+    content_end: usize,
+    end: usize,
+    kind: LineKind,
+}
+
+/// The parts of a joint which determine which lines refer to it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct JointShape {
+    depth: usize,
+    parent_index: Option<usize>,
+    has_end_site: bool,
+    motion_indices: SmallVec<[usize; 6]>,
+}
+
+impl JointShape {
+    fn new(joint: &Joint) -> Self {
+        JointShape {
+            depth: joint.depth,
+            parent_index: joint.parent_index,
+            has_end_site: joint.end_site.is_some(),
+            motion_indices: joint.channels.iter().map(|ch| ch.motion_index()).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Section {
+    Hierarchy,
+    MotionHeader,
+    Motion,
+}
+
+impl Default for Section {
+    #[inline]
+    fn default() -> Self {
+        Section::Hierarchy
+    }
+}
+
+/// Works out the `LineKind` of each line, following the same rules as the parser.
+#[derive(Debug, Default)]
+struct LineClassifier {
+    section: Section,
+    num_joints: usize,
+    curr_joint: usize,
+    depth: usize,
+    end_site_depth: Option<usize>,
+    num_values: usize,
+}
+
+impl LineClassifier {
+    fn classify(&mut self, text: &[u8]) -> LineKind {
+        let line = Line::new(0, text);
+        if line.is_blank() {
+            return LineKind::Blank;
+        } else if line.is_comment() {
+            return LineKind::Comment;
+        }
+
+        let keyword = line.keyword_tokens().next().map_or(&[][..], |token| token.bytes());
+        let is = |kw: &[u8]| keyword.eq_ignore_ascii_case(kw);
+
+        match self.section {
+            Section::Hierarchy => {
+                if is(HEIRARCHY_KEYWORD) {
+                    LineKind::Hierarchy
+                } else if is(ROOT_KEYWORD) || is(JOINT_KEYWORD) {
+                    self.curr_joint = self.num_joints;
+                    self.num_joints += 1;
+                    LineKind::Joint(self.curr_joint)
+                } else if is(ENDSITE_KEYWORDS[0]) {
+                    self.end_site_depth = Some(self.depth);
+                    LineKind::EndSite(self.curr_joint)
+                } else if keyword == OPEN_BRACE {
+                    self.depth += 1;
+                    LineKind::OpenBrace
+                } else if keyword == CLOSE_BRACE {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.end_site_depth == Some(self.depth) {
+                        self.end_site_depth = None;
+                    }
+                    LineKind::CloseBrace
+                } else if is(OFFSET_KEYWORD) {
+                    if self.end_site_depth.is_some() {
+                        LineKind::EndSiteOffset(self.curr_joint)
+                    } else {
+                        LineKind::Offset(self.curr_joint)
+                    }
+                } else if is(CHANNELS_KEYWORD) {
+                    LineKind::Channels(self.curr_joint)
+                } else if self.depth == 0
+                    && line.trimmed().bytes().eq_ignore_ascii_case(MOTION_KEYWORD)
+                {
+                    self.section = Section::MotionHeader;
+                    LineKind::Motion
+                } else {
+                    LineKind::Unknown
+                }
+            }
+            Section::MotionHeader => {
+                if is(FRAMES_KEYWORD) {
+                    LineKind::Frames
+                } else if is_frame_time_line(&line) {
+                    self.section = Section::Motion;
+                    LineKind::FrameTime
+                } else {
+                    LineKind::Unknown
+                }
+            }
+            Section::Motion => {
+                let first_value = self.num_values;
+                self.num_values += line.tokens().count();
+                LineKind::MotionValues(first_value)
+            }
+        }
+    }
+}
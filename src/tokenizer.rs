@@ -0,0 +1,292 @@
+//! A tokenizer which splits the bytes of a `bvh` file into lines and tokens
+//! in a single pass, without allocating.
+//!
+//! Only ASCII whitespace separates tokens, so the input does not need to be
+//! valid `utf8`, and no characters need to be decoded.
+
+use bstr::ByteSlice;
+use crate::errors::Span;
+
+/// An iterator over the lines of a `bvh` file, which keeps track of the
+/// line number.
+#[derive(Clone, Debug)]
+pub(crate) struct Lines<'a> {
+    data: &'a [u8],
+    position: usize,
+    line_num: usize,
+}
+
+impl<'a> Lines<'a> {
+    /// Create a new iterator over the lines of `data`, where the first line
+    /// has the number `first_line_num`.
+    #[inline]
+    pub(crate) fn new(data: &'a [u8], first_line_num: usize) -> Self {
+        Lines {
+            data,
+            position: 0,
+            line_num: first_line_num,
+        }
+    }
+
+    /// The number of bytes of the data which have been consumed so far.
+    #[inline]
+    pub(crate) fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The number of the next line.
+    #[inline]
+    pub(crate) fn line_num(&self) -> usize {
+        self.line_num
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = Line<'a>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.data[self.position..];
+        if rest.is_empty() {
+            return None;
+        }
+
+        let (bytes, len) = match rest.find_byte(b'\n') {
+            Some(newline) => (&rest[..newline], newline + 1),
+            None => (rest, rest.len()),
+        };
+
+        let line = Line::new(self.line_num, bytes);
+        self.position += len;
+        self.line_num += 1;
+        Some(line)
+    }
+}
+
+/// A single line of a `bvh` file, without its line terminator.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Line<'a> {
+    num: usize,
+    bytes: &'a [u8],
+}
+
+impl<'a> Line<'a> {
+    /// Create a new line numbered `num` from `bytes`, ignoring any line terminator.
+    #[inline]
+    pub(crate) fn new(num: usize, bytes: &'a [u8]) -> Self {
+        let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+        let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        Line { num, bytes }
+    }
+
+    /// The number of the line.
+    #[inline]
+    pub(crate) fn num(&self) -> usize {
+        self.num
+    }
+
+    /// The bytes of the line from `column` onwards.
+    #[inline]
+    pub(crate) fn bytes_from(&self, column: usize) -> &'a [u8] {
+        &self.bytes[column..]
+    }
+
+    /// The line with any whitespace at either end removed.
+    #[inline]
+    pub(crate) fn trimmed(&self) -> Token<'a> {
+        let start = self
+            .bytes
+            .iter()
+            .position(|c| !c.is_ascii_whitespace())
+            .unwrap_or(self.bytes.len());
+        let end = self
+            .bytes
+            .iter()
+            .rposition(|c| !c.is_ascii_whitespace())
+            .map_or(start, |last| last + 1);
+        Token {
+            bytes: &self.bytes[start..end],
+            line_num: self.num,
+            column: start,
+        }
+    }
+
+    /// Returns `true` if the line only contains whitespace.
+    #[inline]
+    pub(crate) fn is_blank(&self) -> bool {
+        self.bytes.iter().all(u8::is_ascii_whitespace)
+    }
+
+    /// Returns `true` if the line is a `#` comment.
+    #[inline]
+    pub(crate) fn is_comment(&self) -> bool {
+        self.trimmed().bytes.first() == Some(&b'#')
+    }
+
+    /// Returns an iterator over the whitespace-separated tokens of the line.
+    #[inline]
+    pub(crate) fn tokens(&self) -> Tokens<'a> {
+        Tokens {
+            line: *self,
+            position: 0,
+            split_colons: false,
+        }
+    }
+
+    /// Returns an iterator over the tokens of the line, which are separated by
+    /// whitespace or `:`, as in the motion header.
+    #[inline]
+    pub(crate) fn keyword_tokens(&self) -> Tokens<'a> {
+        Tokens {
+            line: *self,
+            position: 0,
+            split_colons: true,
+        }
+    }
+}
+
+/// An iterator over the tokens of a `Line`.
+#[derive(Clone, Debug)]
+pub(crate) struct Tokens<'a> {
+    line: Line<'a>,
+    position: usize,
+    split_colons: bool,
+}
+
+impl Tokens<'_> {
+    #[inline]
+    fn is_separator(&self, c: u8) -> bool {
+        c.is_ascii_whitespace() || (self.split_colons && c == b':')
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.line.bytes;
+
+        let mut start = self.position;
+        while start < bytes.len() && self.is_separator(bytes[start]) {
+            start += 1;
+        }
+        if start == bytes.len() {
+            self.position = start;
+            return None;
+        }
+
+        let mut end = start + 1;
+        while end < bytes.len() && !self.is_separator(bytes[end]) {
+            end += 1;
+        }
+
+        self.position = end;
+        Some(Token {
+            bytes: &bytes[start..end],
+            line_num: self.line.num,
+            column: start,
+        })
+    }
+}
+
+/// A token, along with its location in the file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Token<'a> {
+    bytes: &'a [u8],
+    line_num: usize,
+    column: usize,
+}
+
+impl<'a> Token<'a> {
+    /// The text of the token.
+    #[inline]
+    pub(crate) fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// The location of the token.
+    #[inline]
+    pub(crate) fn span(&self) -> Span {
+        Span::new(self.line_num, self.column, self.bytes.len())
+    }
+
+    /// An empty location just past the end of the token.
+    #[inline]
+    pub(crate) fn span_after(&self) -> Span {
+        Span::new(self.line_num, self.column + self.bytes.len(), 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_track_line_numbers_and_offsets() {
+        let data = b"HIERARCHY\r\n\nROOT Hips";
+        let mut lines = Lines::new(data, 1);
+
+        let first = lines.next().unwrap();
+        assert_eq!((first.num(), first.bytes_from(0)), (1, &b"HIERARCHY"[..]));
+        assert_eq!(lines.position(), 11);
+
+        let blank = lines.next().unwrap();
+        assert_eq!(blank.num(), 2);
+        assert!(blank.is_blank());
+
+        let root = lines.next().unwrap();
+        assert_eq!((root.num(), root.bytes_from(5)), (3, &b"Hips"[..]));
+        assert_eq!((lines.position(), lines.line_num()), (data.len(), 4));
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn tokens_have_columns_and_spans() {
+        let line = Line::new(7, b"\tOFFSET  1.0 -2\t3 \n");
+        let tokens = line.tokens().collect::<Vec<_>>();
+
+        let texts = tokens.iter().map(Token::bytes).collect::<Vec<_>>();
+        assert_eq!(texts, [&b"OFFSET"[..], b"1.0", b"-2", b"3"]);
+
+        let spans = tokens.iter().map(Token::span).collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [
+                Span::new(7, 1, 6),
+                Span::new(7, 9, 3),
+                Span::new(7, 13, 2),
+                Span::new(7, 16, 1),
+            ]
+        );
+        assert_eq!(tokens[3].span_after(), Span::new(7, 17, 0));
+    }
+
+    #[test]
+    fn keyword_tokens_split_on_colons() {
+        let line = Line::new(1, b"Frame Time:0.0333");
+        let spans = line
+            .keyword_tokens()
+            .map(|token| (token.bytes(), token.span()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [
+                (&b"Frame"[..], Span::new(1, 0, 5)),
+                (b"Time", Span::new(1, 6, 4)),
+                (b"0.0333", Span::new(1, 11, 6)),
+            ]
+        );
+    }
+
+    #[test]
+    fn trimmed_keeps_its_column() {
+        let line = Line::new(2, b"   # a comment  ");
+        assert!(line.is_comment());
+        assert_eq!(line.trimmed().bytes(), b"# a comment");
+        assert_eq!(line.trimmed().span(), Span::new(2, 3, 11));
+
+        let blank = Line::new(3, b" \t ");
+        assert_eq!(blank.trimmed().span(), Span::new(3, 3, 0));
+    }
+}
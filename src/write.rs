@@ -2,18 +2,29 @@
 
 //! Contains options for `bvh` file formatting.
 
-use bstr::{BStr, BString, B};
-use crate::{duation_to_fractional_seconds, Bvh, Frame, Frames, Joint};
+use bstr::{BStr, BString, ByteSlice, B};
+use crate::{
+    compression::{self, Compression},
+    encoding::{self, TextEncoding},
+    parse::Dialect,
+    syntax::{LineKind, SyntaxLine, SyntaxTree},
+    Bvh, ChannelType, Float, Frame, FrameRate, Frames, Joint, Motion,
+};
+use lexical::try_parse;
 use mint::Vector3;
 use smallvec::SmallVec;
 use std::{
-    fmt,
+    borrow::Cow,
+    cmp, fmt,
     io::{self, Write},
     iter, mem,
     slice::Iter,
     num::NonZeroUsize,
 };
 
+#[cfg(feature = "async")]
+use futures_util::io::{AsyncWrite, AsyncWriteExt};
+
 /// Specify formatting options for writing a `Bvh`.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct WriteOptions {
@@ -27,6 +38,38 @@ pub struct WriteOptions {
     pub frame_time_significant_figures: usize,
     /// Number of significant figures to use when writing `MOTION` values.
     pub motion_values_significant_figures: usize,
+    /// Write every `OFFSET` and `MOTION` value, and the `Frame Time`, with as many
+    /// digits as are needed to read back exactly the same value, instead of a fixed
+    /// number of figures.
+    ///
+    /// This keeps the full precision of the values through repeated load and
+    /// write cycles. It is `true` by default when the `f64` feature is enabled,
+    /// and `false` otherwise.
+    pub full_precision: bool,
+    /// The case of keywords and channel names, and the spacing of the `Frames`
+    /// line. Use [`Bvh::dialect`][`Bvh::dialect`] to write a file in the same
+    /// style as it was loaded.
+    ///
+    /// [`Bvh::dialect`]: ../struct.Bvh.html#method.dialect
+    pub dialect: Dialect,
+    /// If the `Bvh` was loaded with its [`SyntaxTree`][`SyntaxTree`], keep the
+    /// original text of every value which has not changed, so that an unmodified
+    /// `Bvh` is written back byte-for-byte.
+    ///
+    /// Values which have changed are formatted using the other options, but follow
+    /// the line terminator and `Dialect` of the original file. If the joints have
+    /// been restructured, the whole hierarchy is re-formatted. This is `true` by
+    /// default.
+    ///
+    /// [`SyntaxTree`]: ../syntax/struct.SyntaxTree.html
+    pub preserve_syntax: bool,
+    /// The compression format to use when writing with [`write`][`WriteOptions::write`]
+    /// or [`write_async`][`WriteOptions::write_async`]. This is `Compression::None`
+    /// by default.
+    ///
+    /// [`WriteOptions::write`]: #method.write
+    /// [`WriteOptions::write_async`]: #method.write_async
+    pub compression: Compression,
     #[doc(hidden)]
     _nonexhaustive: (),
 }
@@ -40,6 +83,10 @@ impl Default for WriteOptions {
             offset_significant_figures: 5,
             frame_time_significant_figures: 7,
             motion_values_significant_figures: 2,
+            full_precision: cfg!(feature = "f64"),
+            dialect: Dialect::new(),
+            preserve_syntax: true,
+            compression: Compression::None,
             _nonexhaustive: (),
         }
     }
@@ -53,31 +100,72 @@ impl WriteOptions {
     }
 
     /// Output the `Bvh` file to the `writer` with the given options.
+    ///
+    /// The output is compressed with the format of `compression`.
     pub fn write<W: Write>(&self, bvh: &Bvh, writer: &mut W) -> io::Result<()> {
+        compression::write_compressed(self.compression, writer, |mut writer| {
+            self.write_uncompressed(bvh, &mut writer)
+        })
+    }
+
+    fn write_uncompressed<W: Write>(&self, bvh: &Bvh, writer: &mut W) -> io::Result<()> {
+        writer.write_all(byte_order_mark(bvh))?;
+        if let Some(syntax) = self.syntax_to_preserve(bvh) {
+            self.write_preserved(bvh, syntax, writer)?;
+            return writer.flush();
+        }
+
         let mut curr_chunk = vec![];
-        let mut curr_bytes_written = 0usize;
-        let mut curr_string_len = 0usize;
         let mut iter_state = WriteOptionsIterState::new();
 
-        while self.next_chunk(bvh, &mut curr_chunk, &mut iter_state) != false {
-            let bytes: &[u8] = curr_chunk.as_ref();
-            curr_string_len += bytes.len();
-            curr_bytes_written += writer.write(bytes)?;
-
-            if curr_bytes_written != curr_string_len {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "Data has been dropped while writing to file",
-                ));
-            }
+        while self.next_chunk(bvh, &mut curr_chunk, &mut iter_state) {
+            writer.write_all(&curr_chunk)?;
         }
         writer.flush()
     }
 
+    /// Output the `Bvh` file to the asynchronous `writer` with the given options.
+    #[cfg(feature = "async")]
+    pub async fn write_async<W: AsyncWrite + Unpin>(
+        &self,
+        bvh: &Bvh,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        if self.compression != Compression::None {
+            let mut compressed = vec![];
+            self.write(bvh, &mut compressed)?;
+            writer.write_all(&compressed).await?;
+            return writer.flush().await;
+        }
+
+        if self.syntax_to_preserve(bvh).is_some() {
+            writer.write_all(&self.write_to_string(bvh)).await?;
+            return writer.flush().await;
+        }
+
+        writer.write_all(byte_order_mark(bvh)).await?;
+        let mut curr_chunk = vec![];
+        let mut iter_state = WriteOptionsIterState::new();
+
+        while self.next_chunk(bvh, &mut curr_chunk, &mut iter_state) {
+            writer.write_all(&curr_chunk).await?;
+        }
+        writer.flush().await
+    }
+
     /// Output the `Bvh` file to the `string` with the given options.
+    ///
+    /// The string is never compressed, whatever the value of `compression`.
     pub fn write_to_string(&self, bvh: &Bvh) -> BString {
         let mut curr_chunk = vec![];
-        let mut out_string = vec![];
+        let mut out_string = byte_order_mark(bvh).to_vec();
+
+        if let Some(syntax) = self.syntax_to_preserve(bvh) {
+            self.write_preserved(bvh, syntax, &mut out_string)
+                .expect("writing to a `Vec` cannot fail");
+            return BString::from(out_string);
+        }
+
         let mut iter_state = WriteOptionsIterState::new();
 
         while self.next_chunk(bvh, &mut curr_chunk, &mut iter_state) != false {
@@ -135,6 +223,129 @@ impl WriteOptions {
         }
     }
 
+    /// Sets `full_precision` on `self` to the new `full_precision`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::{bvh, write::WriteOptions};
+    /// let bvh = bvh! {
+    ///     HIERARCHY
+    ///     ROOT Hips
+    ///     {
+    ///         OFFSET 0.0 0.0 0.0
+    ///         CHANNELS 3 Xposition Yposition Zposition
+    ///         End Site
+    ///         {
+    ///             OFFSET 0.0 0.0 0.0
+    ///         }
+    ///     }
+    ///     MOTION
+    ///     Frames: 1
+    ///     Frame Time: 0.033333333
+    ///     1234.5625 0.0 0.125
+    /// };
+    ///
+    /// let written = WriteOptions::new().with_full_precision(true).write_to_string(&bvh);
+    /// assert!(written.to_string().contains("1234.5625 0 0.125"));
+    /// ```
+    #[inline]
+    pub const fn with_full_precision(self, full_precision: bool) -> Self {
+        WriteOptions {
+            full_precision,
+            ..self
+        }
+    }
+
+    /// Sets `dialect` on `self` to the new `Dialect`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::{bvh, write::WriteOptions};
+    /// # use bvh_anim::parse::{Dialect, KeywordCase};
+    /// let bvh = bvh! {
+    ///     HIERARCHY
+    ///     ROOT Hips
+    ///     {
+    ///         OFFSET 0.0 0.0 0.0
+    ///         CHANNELS 3 Xposition Yposition Zposition
+    ///         End Site
+    ///         {
+    ///             OFFSET 0.0 0.0 0.0
+    ///         }
+    ///     }
+    ///     MOTION
+    ///     Frames: 0
+    ///     Frame Time: 0.033333333
+    /// };
+    ///
+    /// let dialect = Dialect::new()
+    ///     .with_keyword_case(KeywordCase::Lowercase)
+    ///     .with_compact_frames(true);
+    /// let written = WriteOptions::new().with_dialect(dialect).write_to_string(&bvh);
+    /// let written = written.to_string();
+    /// assert!(written.starts_with("hierarchy"));
+    /// assert!(written.contains("frames:0"));
+    /// ```
+    #[inline]
+    pub const fn with_dialect(self, dialect: Dialect) -> Self {
+        WriteOptions { dialect, ..self }
+    }
+
+    /// Sets `preserve_syntax` on `self` to the new `preserve_syntax`.
+    #[inline]
+    pub const fn with_preserve_syntax(self, preserve_syntax: bool) -> Self {
+        WriteOptions {
+            preserve_syntax,
+            ..self
+        }
+    }
+
+    /// Sets `compression` on `self` to the new `compression`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bvh_anim::{bvh, compression::Compression, write::WriteOptions};
+    /// # let bvh = bvh! {
+    /// #     HIERARCHY
+    /// #     ROOT Hips
+    /// #     {
+    /// #         OFFSET 0.0 0.0 0.0
+    /// #         CHANNELS 3 Xposition Yposition Zposition
+    /// #         End Site
+    /// #         {
+    /// #             OFFSET 0.0 0.0 0.0
+    /// #         }
+    /// #     }
+    /// #     MOTION
+    /// #     Frames: 0
+    /// #     Frame Time: 0.033333333
+    /// # };
+    /// let options = WriteOptions::new().with_compression(Compression::Gzip);
+    ///
+    /// let mut compressed = vec![];
+    /// match options.write(&bvh, &mut compressed) {
+    ///     Ok(()) => assert_eq!(Compression::detect(&compressed), Compression::Gzip),
+    ///     // The `gzip` feature is not enabled.
+    ///     Err(_) => assert!(!Compression::Gzip.is_supported()),
+    /// }
+    /// ```
+    #[inline]
+    pub const fn with_compression(self, compression: Compression) -> Self {
+        WriteOptions {
+            compression,
+            ..self
+        }
+    }
+
+    /// Get the `standard` spelling of a keyword in the case of the `dialect`.
+    #[inline]
+    fn keyword(&self, standard: &'static str) -> Cow<'static, str> {
+        self.dialect.keyword_case.apply(standard)
+    }
+
     // @TODO: Refactor all of this
     /// Get the next text chunk of the written bvh file. This function is
     /// structured so that the `chunk` string can be continually
@@ -157,7 +368,7 @@ impl WriteOptions {
         match *iter_state {
             WriteOptionsIterState::WriteHierarchy { ref mut written } => {
                 if !*written {
-                    *chunk = b"HIERARCHY".to_vec();
+                    *chunk = self.keyword("HIERARCHY").into_owned().into_bytes();
                     chunk.extend_from_slice(self.line_terminator.as_bstr().as_ref());
                     *written = true;
                 } else {
@@ -196,10 +407,11 @@ impl WriteOptions {
                             // allocation
                             chunk.extend(self.indent.prefix_chars(depth));
                             if joint.is_root() {
-                                chunk.extend_from_slice(b"ROOT ");
+                                chunk.extend_from_slice(self.keyword("ROOT").as_bytes());
                             } else {
-                                chunk.extend_from_slice(b"JOINT ");
+                                chunk.extend_from_slice(self.keyword("JOINT").as_bytes());
                             }
+                            chunk.push(b' ');
                             chunk.extend_from_slice(joint.name.as_ref());
                             chunk.extend_from_slice(terminator);
                             chunk.extend(self.indent.prefix_chars(depth));
@@ -215,13 +427,11 @@ impl WriteOptions {
 
                             let Vector3 { x, y, z } = joint.offset;
                             let offset_str = format!(
-                                "OFFSET {:.*} {:.*} {:.*}",
-                                self.offset_significant_figures,
-                                x,
-                                self.offset_significant_figures,
-                                y,
-                                self.offset_significant_figures,
-                                z,
+                                "{} {} {} {}",
+                                self.keyword("OFFSET"),
+                                self.offset_value(x),
+                                self.offset_value(y),
+                                self.offset_value(z),
                             );
                             chunk.extend_from_slice(offset_str.as_bytes());
                             chunk.extend_from_slice(terminator);
@@ -236,11 +446,16 @@ impl WriteOptions {
                             let channels_str = channels
                                 .iter()
                                 .map(|ch| ch.channel_type().as_str())
+                                .map(|name| self.dialect.channel_case.apply(name))
                                 .collect::<SmallVec<[_; 6]>>()
                                 .join(" ");
 
-                            let channels_str =
-                                format!("CHANNELS {} {}", channels.len(), channels_str);
+                            let channels_str = format!(
+                                "{} {} {}",
+                                self.keyword("CHANNELS"),
+                                channels.len(),
+                                channels_str
+                            );
                             chunk.extend_from_slice(channels_str.as_bytes());
                             chunk.extend_from_slice(terminator);
                             *wrote_channels = true;
@@ -249,7 +464,14 @@ impl WriteOptions {
                             if let Some(end_site) = joint.end_site {
                                 let Vector3 { x, y, z } = end_site;
                                 chunk.extend(self.indent.prefix_chars(depth));
-                                chunk.extend_from_slice(b"End Site");
+                                let site = if self.dialect.lowercase_site {
+                                    Cow::Borrowed("site")
+                                } else {
+                                    self.keyword("Site")
+                                };
+                                chunk.extend_from_slice(self.keyword("End").as_bytes());
+                                chunk.push(b' ');
+                                chunk.extend_from_slice(site.as_bytes());
                                 chunk.extend_from_slice(terminator);
 
                                 chunk.extend(self.indent.prefix_chars(depth));
@@ -258,13 +480,11 @@ impl WriteOptions {
 
                                 chunk.extend(self.indent.prefix_chars(depth + 1));
                                 let offset_str = format!(
-                                    "OFFSET {:.*} {:.*} {:.*}",
-                                    self.offset_significant_figures,
-                                    x,
-                                    self.offset_significant_figures,
-                                    y,
-                                    self.offset_significant_figures,
-                                    z,
+                                    "{} {} {} {}",
+                                    self.keyword("OFFSET"),
+                                    self.offset_value(x),
+                                    self.offset_value(y),
+                                    self.offset_value(z),
                                 );
                                 chunk.extend_from_slice(offset_str.as_bytes());
                                 chunk.extend_from_slice(terminator);
@@ -272,27 +492,27 @@ impl WriteOptions {
                                 chunk.extend(self.indent.prefix_chars(depth));
                                 chunk.push(b'}');
                                 chunk.extend_from_slice(terminator);
+                            }
 
-                                let next_joint = joints.next();
-                                let prev_joint = mem::replace(current_joint, next_joint).unwrap();
-
-                                let (curr_depth, mut depth_difference) =
-                                    if let Some(ref curr_j) = *current_joint {
-                                        let curr_depth = curr_j.depth;
-                                        (curr_depth, Some(prev_joint.depth - curr_depth))
-                                    } else {
-                                        (0, Some(prev_joint.depth))
-                                    };
-
-                                while let Some(d) = depth_difference {
-                                    chunk.extend(self.indent.prefix_chars(curr_depth + d));
-                                    chunk.push(b'}');
-                                    chunk.extend_from_slice(terminator);
-                                    depth_difference =
-                                        depth_difference.and_then(|d| d.checked_sub(1));
-                                }
-                            } else {
-                                *current_joint = joints.next();
+                            let next_joint = joints.next();
+                            let prev_joint = mem::replace(current_joint, next_joint).unwrap();
+
+                            // Close the braces of every joint which the next joint is not
+                            // nested in. A leaf may not have an end site, so this can't
+                            // depend on whether one was written.
+                            let (curr_depth, mut depth_difference) =
+                                if let Some(ref curr_j) = *current_joint {
+                                    let curr_depth = curr_j.depth;
+                                    (curr_depth, prev_joint.depth.checked_sub(curr_depth))
+                                } else {
+                                    (0, Some(prev_joint.depth))
+                                };
+
+                            while let Some(d) = depth_difference {
+                                chunk.extend(self.indent.prefix_chars(curr_depth + d));
+                                chunk.push(b'}');
+                                chunk.extend_from_slice(terminator);
+                                depth_difference = depth_difference.and_then(|d| d.checked_sub(1));
                             }
                             *wrote_name = false;
                             *wrote_offset = false;
@@ -304,7 +524,7 @@ impl WriteOptions {
             }
             WriteOptionsIterState::WriteMotion { ref mut written } => {
                 if !*written {
-                    *chunk = b"MOTION".to_vec();
+                    *chunk = self.keyword("MOTION").into_owned().into_bytes();
                     chunk.extend_from_slice(terminator);
                     *written = true;
                 } else {
@@ -313,7 +533,9 @@ impl WriteOptions {
             }
             WriteOptionsIterState::WriteNumFrames { ref mut written } => {
                 if !*written {
-                    *chunk = format!("Frames: {}", bvh.num_frames()).into_bytes();
+                    let separator = if self.dialect.compact_frames { ":" } else { ": " };
+                    *chunk = format!("{}{}{}", self.keyword("Frames"), separator, bvh.num_frames())
+                        .into_bytes();
                     chunk.extend_from_slice(terminator);
                     *written = true;
                 } else {
@@ -323,9 +545,10 @@ impl WriteOptions {
             WriteOptionsIterState::WriteFrameTime { ref mut written } => {
                 if !*written {
                     *chunk = format!(
-                        "Frame Time: {:.*}",
-                        self.frame_time_significant_figures,
-                        duation_to_fractional_seconds(bvh.frame_time())
+                        "{} {}: {}",
+                        self.keyword("Frame"),
+                        self.keyword("Time"),
+                        self.frame_time_value(bvh.frame_rate())
                     )
                     .into_bytes();
                     chunk.extend_from_slice(terminator);
@@ -344,15 +567,7 @@ impl WriteOptions {
             } => match current_frame {
                 None => return false,
                 Some(frame) => {
-                    let motion_values = frame
-                        .as_slice()
-                        .iter()
-                        .map(|motion| {
-                            format!("{:.*}", self.motion_values_significant_figures, motion)
-                        })
-                        .collect::<Vec<_>>()
-                        .join(" ");
-                    *chunk = motion_values.into_bytes();
+                    self.format_frame(frame.as_slice(), chunk);
                     chunk.extend_from_slice(terminator);
                     *current_frame = frames.next();
                 }
@@ -361,6 +576,327 @@ impl WriteOptions {
 
         true
     }
+
+    /// Format the motion values of a single frame onto the end of `out`.
+    fn format_frame(&self, motion_values: &[Float], out: &mut Vec<u8>) {
+        for (i, motion) in motion_values.iter().enumerate() {
+            if i != 0 {
+                out.push(b' ');
+            }
+            let _ = write!(out, "{}", self.motion_value(*motion));
+        }
+    }
+
+    /// Format the whole hierarchy section of the `bvh` onto the end of `out`.
+    fn format_hierarchy(&self, bvh: &Bvh, out: &mut Vec<u8>) {
+        let mut chunk = vec![];
+        let mut iter_state = WriteOptionsIterState::new();
+        while self.next_chunk(bvh, &mut chunk, &mut iter_state) {
+            if let WriteOptionsIterState::WriteMotion { .. } = iter_state {
+                break;
+            }
+            out.extend_from_slice(&chunk);
+        }
+    }
+
+    /// Returns the `SyntaxTree` of the `bvh` if it should be used for writing.
+    #[inline]
+    fn syntax_to_preserve<'a>(&self, bvh: &'a Bvh) -> Option<&'a SyntaxTree> {
+        if self.preserve_syntax {
+            bvh.syntax()
+        } else {
+            None
+        }
+    }
+
+    /// Output the `bvh` to the `writer` by walking the lines of its `syntax`, and
+    /// re-formatting only the values which have changed since it was loaded.
+    fn write_preserved<W: Write>(
+        &self,
+        bvh: &Bvh,
+        syntax: &SyntaxTree,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        let options = WriteOptions {
+            line_terminator: syntax.line_terminator().unwrap_or(self.line_terminator),
+            dialect: *bvh.dialect(),
+            ..self.clone()
+        };
+        let terminator = options.line_terminator.as_bstr().as_bytes();
+
+        let hierarchy_matches = syntax.hierarchy_matches(bvh);
+        let values_match = syntax.num_channels() == bvh.motion.num_channels;
+
+        let mut out = vec![];
+        let mut in_hierarchy = true;
+        let mut wrote_hierarchy = false;
+        let mut wrote_frames = false;
+        let mut at_line_start = true;
+
+        for line in syntax.lines() {
+            out.clear();
+            let kind = line.kind();
+            if kind == LineKind::Motion {
+                in_hierarchy = false;
+            }
+
+            match kind {
+                // Keep the comments before the hierarchy, but re-format the rest of it.
+                LineKind::Blank | LineKind::Comment if in_hierarchy && !wrote_hierarchy => {
+                    write_line(&mut out, &line, |_, _| None);
+                }
+                _ if in_hierarchy && !hierarchy_matches => {
+                    if !mem::replace(&mut wrote_hierarchy, true) {
+                        options.format_hierarchy(bvh, &mut out);
+                    }
+                }
+                LineKind::Joint(index) => {
+                    let name = &bvh.skeleton.joints[index].name[..];
+                    write_line(&mut out, &line, |i, token| match i {
+                        1 if token != name => Some(name.to_vec()),
+                        _ => None,
+                    });
+                }
+                LineKind::Offset(index) => {
+                    let Vector3 { x, y, z } = bvh.skeleton.joints[index].offset;
+                    options.write_offset_line(&mut out, &line, [x, y, z]);
+                }
+                LineKind::EndSiteOffset(index) => match bvh.skeleton.joints[index].end_site {
+                    Some(Vector3 { x, y, z }) => {
+                        options.write_offset_line(&mut out, &line, [x, y, z]);
+                    }
+                    None => write_line(&mut out, &line, |_, _| None),
+                },
+                LineKind::Channels(index) => {
+                    let channels = &bvh.skeleton.joints[index].channels[..];
+                    write_line(&mut out, &line, |i, token| match i {
+                        0 => None,
+                        1 if try_parse::<usize, _>(token).ok() == Some(channels.len()) => None,
+                        1 => Some(channels.len().to_string().into_bytes()),
+                        _ => {
+                            let ty = channels.get(i - 2)?.channel_type();
+                            if ChannelType::from_bytes(token).ok() == Some(ty) {
+                                None
+                            } else {
+                                let name = options.dialect.channel_case.apply(ty.as_str());
+                                Some(name.into_owned().into_bytes())
+                            }
+                        }
+                    });
+                }
+                LineKind::Frames => {
+                    let last = line.tokens().count().saturating_sub(1);
+                    write_line(&mut out, &line, |i, token| {
+                        let (prefix, value) = split_value(token);
+                        let num_frames = bvh.num_frames();
+                        if i != last || try_parse::<usize, _>(value).ok() == Some(num_frames) {
+                            None
+                        } else {
+                            Some([prefix, num_frames.to_string().as_bytes()].concat())
+                        }
+                    });
+                }
+                LineKind::FrameTime => {
+                    let last = line.tokens().count().saturating_sub(1);
+                    let frame_rate = bvh.frame_rate();
+                    write_line(&mut out, &line, |i, token| {
+                        let (prefix, value) = split_value(token);
+                        if i != last || FrameRate::from_decimal(value) == Some(frame_rate) {
+                            None
+                        } else {
+                            let value = options.frame_time_value(frame_rate);
+                            Some([prefix, value.as_bytes()].concat())
+                        }
+                    });
+                }
+                LineKind::MotionValues(_) if !values_match => {
+                    // The channels have changed, so the old lines do not line up
+                    // with the frames any more.
+                    if !mem::replace(&mut wrote_frames, true) {
+                        for frame in bvh.frames() {
+                            options.format_frame(frame.as_slice(), &mut out);
+                            out.extend_from_slice(terminator);
+                        }
+                    }
+                }
+                LineKind::MotionValues(first_value) => {
+                    options.write_motion_line(&mut out, &line, first_value, &bvh.motion);
+                }
+                _ => write_line(&mut out, &line, |_, _| None),
+            }
+
+            if !out.is_empty() {
+                at_line_start = out.ends_with(b"\n");
+                writer.write_all(&out)?;
+            }
+        }
+
+        // Write any frames which were added after the file was loaded.
+        out.clear();
+        let num_channels = bvh.num_channels();
+        let num_written = if values_match { syntax.num_values() } else { 0 };
+        let num_frames_written = cmp::min(
+            num_written.checked_div(num_channels).unwrap_or(0),
+            bvh.num_frames(),
+        );
+        if num_channels != 0
+            && (values_match || !wrote_frames)
+            && num_frames_written < bvh.num_frames()
+        {
+            if !at_line_start {
+                out.extend_from_slice(terminator);
+            }
+            for frame in bvh.frames().skip(num_frames_written) {
+                options.format_frame(frame.as_slice(), &mut out);
+                out.extend_from_slice(terminator);
+            }
+            writer.write_all(&out)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write an `OFFSET` line, re-formatting any axes which differ from `offset`.
+    fn write_offset_line(&self, out: &mut Vec<u8>, line: &SyntaxLine<'_>, offset: [Float; 3]) {
+        write_line(out, line, |i, token| match i {
+            1..=3 => self.format_changed(token, self.offset_value(offset[i - 1])),
+            _ => None,
+        });
+    }
+
+    /// Write a line of motion values, re-formatting any values which have changed
+    /// and leaving out any values which have been removed.
+    ///
+    /// `first_value` is the index of the first value of the line in the file, which
+    /// is frame-major whatever the layout of the `motion`.
+    fn write_motion_line(
+        &self,
+        out: &mut Vec<u8>,
+        line: &SyntaxLine<'_>,
+        first_value: usize,
+        motion: &Motion,
+    ) {
+        let num_channels = motion.num_channels();
+        if num_channels == 0 {
+            return;
+        }
+
+        let values = (first_value..).map_while(|value| {
+            let index = motion.value_index(value / num_channels, value % num_channels)?;
+            Some(motion.values[index])
+        });
+
+        let mut wrote_value = false;
+        for (token, value) in line.tokens().zip(values) {
+            out.extend_from_slice(token.leading_trivia());
+            match self.format_changed(token.text(), self.motion_value(value)) {
+                Some(text) => out.extend_from_slice(&text),
+                None => out.extend_from_slice(token.text()),
+            }
+            wrote_value = true;
+        }
+
+        if wrote_value {
+            out.extend_from_slice(line.trailing_trivia());
+            out.extend_from_slice(line.terminator());
+        }
+    }
+
+    /// Returns the formatted `value` if it differs from the value of the `token`.
+    fn format_changed(&self, token: &[u8], value: FormatValue) -> Option<Vec<u8>> {
+        if try_parse::<Float, _>(token).ok() == Some(value.value) {
+            None
+        } else {
+            Some(value.to_string().into_bytes())
+        }
+    }
+
+    /// Format an `OFFSET` value with the options.
+    #[inline]
+    fn offset_value(&self, value: Float) -> FormatValue {
+        self.format_value(value, self.offset_significant_figures)
+    }
+
+    /// Format a `MOTION` value with the options.
+    #[inline]
+    fn motion_value(&self, value: Float) -> FormatValue {
+        self.format_value(value, self.motion_values_significant_figures)
+    }
+
+    /// Format the `Frame Time` value of `frame_rate` with the options.
+    ///
+    /// The value is always computed from the exact frame rate, so a frame rate
+    /// which was snapped when it was parsed is written with all of its figures.
+    #[inline]
+    fn frame_time_value(&self, frame_rate: FrameRate) -> String {
+        let frame_time = frame_rate.frame_time_secs();
+        if self.full_precision {
+            frame_time.to_string()
+        } else {
+            format!("{:.*}", self.frame_time_significant_figures, frame_time)
+        }
+    }
+
+    #[inline]
+    fn format_value(&self, value: Float, figures: usize) -> FormatValue {
+        FormatValue {
+            value,
+            figures: if self.full_precision { None } else { Some(figures) },
+        }
+    }
+}
+
+/// Displays an `OFFSET` or `MOTION` value with a fixed number of figures, or
+/// with the shortest text which reads back as the same value if `figures` is `None`.
+#[derive(Clone, Copy, Debug)]
+struct FormatValue {
+    value: Float,
+    figures: Option<usize>,
+}
+
+impl fmt::Display for FormatValue {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.figures {
+            Some(figures) => write!(f, "{:.*}", figures, self.value),
+            None => fmt::Display::fmt(&self.value, f),
+        }
+    }
+}
+
+/// The byte order mark to write before the text of `bvh`, which is only written
+/// if the file was loaded from `utf8` with a byte order mark. The output is always
+/// `utf8`, so a `utf16` file is written without one.
+#[inline]
+fn byte_order_mark(bvh: &Bvh) -> &'static [u8] {
+    match bvh.encoding() {
+        TextEncoding::Utf8WithBom => encoding::UTF8_BOM,
+        _ => b"",
+    }
+}
+
+/// Write the `line` onto the end of `out`, keeping all of its whitespace, but
+/// replacing the text of each token for which `replace` returns `Some`.
+fn write_line<F>(out: &mut Vec<u8>, line: &SyntaxLine<'_>, mut replace: F)
+where
+    F: FnMut(usize, &[u8]) -> Option<Vec<u8>>,
+{
+    for (i, token) in line.tokens().enumerate() {
+        out.extend_from_slice(token.leading_trivia());
+        match replace(i, token.text()) {
+            Some(text) => out.extend_from_slice(&text),
+            None => out.extend_from_slice(token.text()),
+        }
+    }
+    out.extend_from_slice(line.trailing_trivia());
+    out.extend_from_slice(line.terminator());
+}
+
+/// Split a token such as `Frames:10` into the text up to the last `:`, and the
+/// value after it.
+fn split_value(token: &[u8]) -> (&[u8], &[u8]) {
+    let value_start = token.rfind_byte(b':').map_or(0, |colon| colon + 1);
+    token.split_at(value_start)
 }
 
 enum WriteOptionsIterState<'a> {
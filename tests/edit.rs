@@ -0,0 +1,593 @@
+use bvh_anim::{
+    bvh, errors::EditJointsError, Bvh, ChannelType, Float, JointIndex, Motion, MotionLayout,
+};
+use pretty_assertions::assert_eq;
+use std::{collections::HashMap, fs::File, io::BufReader};
+
+fn load_mocapbank() -> Bvh {
+    File::open("./data/test_mocapbank.bvh")
+        .map(BufReader::new)
+        .map(bvh_anim::from_reader)
+        .unwrap()
+        .unwrap()
+}
+
+/// The motion values of each channel, keyed by the name of its joint and its
+/// type. Joints whose names are not unique are skipped.
+fn channel_values(bvh: &Bvh) -> HashMap<(Vec<u8>, ChannelType), Vec<Float>> {
+    let mut values = HashMap::new();
+    for joint in bvh.joints() {
+        if bvh
+            .joints()
+            .filter(|other| other.name == joint.name)
+            .count()
+            > 1
+        {
+            continue;
+        }
+
+        for channel in &joint.channels {
+            let motion = bvh.frames().map(|frame| frame[channel]).collect();
+            values.insert((joint.name.to_vec(), channel.channel_type()), motion);
+        }
+    }
+    values
+}
+
+/// Check the invariants which the writer and the hierarchy methods rely on.
+fn assert_consistent(bvh: &Bvh) {
+    let mut motion_index = 0;
+    for idx in bvh.joint_indices() {
+        let joint = &bvh[idx];
+        let expected_depth = joint.parent().map_or(0, |parent| {
+            assert!(parent < idx);
+            bvh[parent].depth() + 1
+        });
+        assert_eq!(joint.depth(), expected_depth);
+        assert_eq!(joint.end_site().is_some(), bvh.children(idx).len() == 0);
+
+        for channel in &joint.channels {
+            assert_eq!(channel.motion_index(), motion_index);
+            motion_index += 1;
+        }
+    }
+    assert_eq!(motion_index, bvh.num_channels());
+    assert!(bvh
+        .frames()
+        .all(|frame| frame.as_slice().len() == bvh.num_channels()));
+
+    // The edited bvh survives a round trip through the writer.
+    let reparsed = bvh_anim::from_bytes(&bvh.to_bstring()[..]).unwrap();
+    assert!(reparsed
+        .joints()
+        .map(|j| (&j.name, j.parent_index(), j.channels.len()))
+        .eq(bvh
+            .joints()
+            .map(|j| (&j.name, j.parent_index(), j.channels.len()))));
+}
+
+#[test]
+fn insert_joint() {
+    let mut bvh = load_mocapbank();
+    let before = channel_values(&bvh);
+    let num_joints = bvh.joints().len();
+
+    let wrist = bvh.joint_by_name("LeftWrist").unwrap();
+    let hand = bvh
+        .insert_joint(
+            wrist,
+            "LeftHand",
+            [0.0, -5.0, 0.0].into(),
+            &[ChannelType::RotationZ, ChannelType::RotationX],
+        )
+        .unwrap();
+
+    assert_consistent(&bvh);
+    assert_eq!(bvh.joints().len(), num_joints + 1);
+    assert_eq!(hand, JointIndex::new(wrist.get() + 1));
+    assert_eq!(bvh[hand].parent(), Some(wrist));
+    assert_eq!(bvh.joint_by_name("LeftHand"), Some(hand));
+    assert!(bvh[wrist].end_site().is_none());
+    assert!(bvh
+        .frames()
+        .all(|frame| bvh[hand].channels.iter().all(|ch| frame[ch] == 0.0)));
+
+    let mut after = channel_values(&bvh);
+    after.remove(&(b"LeftHand".to_vec(), ChannelType::RotationZ));
+    after.remove(&(b"LeftHand".to_vec(), ChannelType::RotationX));
+    assert!(after == before);
+
+    assert_eq!(
+        bvh.insert_joint(JointIndex::new(100), "Bad", [0.0; 3].into(), &[]),
+        Err(EditJointsError::BadJoint(JointIndex::new(100)))
+    );
+
+    let num_joints = bvh.joints().count();
+    assert_eq!(
+        bvh.insert_joint(
+            hand,
+            "LeftFinger",
+            [0.0; 3].into(),
+            &[
+                ChannelType::RotationX,
+                ChannelType::RotationY,
+                ChannelType::RotationX
+            ],
+        ),
+        Err(EditJointsError::DuplicateChannel {
+            joint: JointIndex::new(hand.get() + 1),
+            channel_type: ChannelType::RotationX,
+        })
+    );
+    assert_eq!(bvh.joints().count(), num_joints);
+}
+
+#[test]
+fn remove_joint() {
+    let mut bvh = load_mocapbank();
+    let mut before = channel_values(&bvh);
+
+    let knee = bvh.joint_by_name("LeftKnee").unwrap();
+    let hip = bvh[knee].parent().unwrap();
+    let ankle = bvh.children(knee).next().unwrap();
+    let expected_offset = {
+        let (a, b) = (bvh[knee].offset, bvh[ankle].offset);
+        [a.x + b.x, a.y + b.y, a.z + b.z]
+    };
+
+    bvh.remove_joint(knee).unwrap();
+    assert_consistent(&bvh);
+
+    assert_eq!(bvh.joint_by_name("LeftKnee"), None);
+    let ankle = bvh.joint_by_name("LeftAnkle").unwrap();
+    assert_eq!(bvh[ankle].parent(), Some(hip));
+    assert_eq!(<[Float; 3]>::from(bvh[ankle].offset), expected_offset);
+
+    before.retain(|(name, _), _| name != b"LeftKnee");
+    assert!(channel_values(&bvh) == before);
+
+    // Removing the last child of a joint caps it with an end site.
+    let ankle_tip = {
+        let joint = &bvh[ankle];
+        let end = joint.end_site().unwrap();
+        [
+            joint.offset.x + end.x,
+            joint.offset.y + end.y,
+            joint.offset.z + end.z,
+        ]
+    };
+    bvh.remove_joint(ankle).unwrap();
+    assert_consistent(&bvh);
+    assert_eq!(bvh.children(hip).len(), 0);
+    assert_eq!(<[Float; 3]>::from(bvh[hip].end_site().unwrap()), ankle_tip);
+}
+
+#[test]
+fn reparent_joint() {
+    let mut bvh = load_mocapbank();
+    let before = channel_values(&bvh);
+
+    let hip = bvh.joint_by_name("LeftHip").unwrap();
+    let head = bvh.joint_by_name("Head").unwrap();
+    assert_eq!(
+        bvh.reparent_joint(hip, bvh.joint_by_name("LeftAnkle").unwrap()),
+        Err(EditJointsError::WouldCreateCycle {
+            joint: hip,
+            new_parent: bvh.joint_by_name("LeftAnkle").unwrap(),
+        })
+    );
+    assert_eq!(
+        bvh.reparent_joint(hip, hip),
+        Err(EditJointsError::WouldCreateCycle {
+            joint: hip,
+            new_parent: hip,
+        })
+    );
+
+    bvh.reparent_joint(hip, head).unwrap();
+    assert_consistent(&bvh);
+    assert!(channel_values(&bvh) == before);
+
+    let hip = bvh.joint_by_name("LeftHip").unwrap();
+    let head = bvh.joint_by_name("Head").unwrap();
+    assert_eq!(bvh[hip].parent(), Some(head));
+    assert_eq!(bvh[hip].depth(), bvh[head].depth() + 1);
+    let subtree: Vec<_> = bvh
+        .descendants(hip)
+        .map(|idx| bvh[idx].name.to_vec())
+        .collect();
+    assert_eq!(subtree, [b"LeftKnee".to_vec(), b"LeftAnkle".to_vec()]);
+
+    // Moving a joint up to its grandparent.
+    let ankle = bvh.joint_by_name("LeftAnkle").unwrap();
+    bvh.reparent_joint(ankle, hip).unwrap();
+    assert_consistent(&bvh);
+    assert!(channel_values(&bvh) == before);
+    let hip = bvh.joint_by_name("LeftHip").unwrap();
+    assert_eq!(bvh.children(hip).len(), 2);
+}
+
+#[test]
+fn remove_joint_merging_rotation() {
+    let mut bvh = bvh! {
+        HIERARCHY
+        ROOT Hips
+        {
+            OFFSET 0.0 0.0 0.0
+            CHANNELS 3 Xposition Yposition Zposition
+            JOINT Spine
+            {
+                OFFSET 0.0 10.0 0.0
+                CHANNELS 3 Zrotation Xrotation Yrotation
+                JOINT Chest
+                {
+                    OFFSET 0.0 0.0 0.0
+                    CHANNELS 3 Zrotation Xrotation Yrotation
+                    End Site
+                    {
+                        OFFSET 0.0 10.0 0.0
+                    }
+                }
+                JOINT Tail
+                {
+                    OFFSET 0.0 0.0 0.0
+                    CHANNELS 3 Yrotation Xrotation Zrotation
+                    End Site
+                    {
+                        OFFSET 0.0 -10.0 0.0
+                    }
+                }
+            }
+        }
+        MOTION
+        Frames: 3
+        Frame Time: 0.033333333
+        1.0 2.0 3.0 30.0 0.0 0.0 20.0 0.0 0.0 0.0 0.0 0.0
+        1.0 2.0 3.0 90.0 0.0 0.0 0.0 90.0 0.0 0.0 0.0 0.0
+        1.0 2.0 3.0 0.0 60.0 0.0 0.0 0.0 0.0 0.0 0.0 45.0
+    };
+
+    let mut unsupported = bvh.clone();
+    let chest = unsupported.joint_by_name("Chest").unwrap();
+    unsupported.joint_mut(chest).unwrap().channels.truncate(1);
+    let spine = unsupported.joint_by_name("Spine").unwrap();
+    let copy = unsupported.clone();
+    assert_eq!(
+        unsupported.remove_joint_merging_rotation(spine),
+        Err(EditJointsError::UnsupportedRotationChannels { joint: chest })
+    );
+    assert_eq!(unsupported, copy);
+
+    let spine = bvh.joint_by_name("Spine").unwrap();
+    bvh.remove_joint_merging_rotation(spine).unwrap();
+    assert_consistent(&bvh);
+
+    // Chest is rotated by Z, X then Y; Tail by Y, X then Z.
+    let expected: [[Float; 9]; 3] = [
+        [1.0, 2.0, 3.0, 50.0, 0.0, 0.0, 0.0, 0.0, 30.0],
+        [1.0, 2.0, 3.0, 90.0, 90.0, 0.0, 0.0, 0.0, 90.0],
+        [1.0, 2.0, 3.0, 0.0, 60.0, 0.0, 0.0, 60.0, 45.0],
+    ];
+    for (frame, expected) in bvh.frames().zip(expected.iter()) {
+        for (&value, &expected) in frame.as_slice().iter().zip(expected.iter()) {
+            assert!(
+                (value - expected).abs() < 1e-3,
+                "{:?} != {:?}",
+                frame,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn add_and_remove_channels() {
+    use ChannelType::*;
+
+    let mut bvh = load_mocapbank();
+    let before = channel_values(&bvh);
+    let num_channels = bvh.num_channels();
+
+    let elbow = bvh.joint_by_name("LeftElbow").unwrap();
+    assert_eq!(
+        bvh.add_channels(elbow, &[PositionX, PositionX]),
+        Err(EditJointsError::DuplicateChannel {
+            joint: elbow,
+            channel_type: PositionX,
+        })
+    );
+    assert_eq!(
+        bvh.add_channels(elbow, &[RotationZ]),
+        Err(EditJointsError::DuplicateChannel {
+            joint: elbow,
+            channel_type: RotationZ,
+        })
+    );
+
+    let mut num_added = 0;
+    for idx in bvh.joint_indices().collect::<Vec<_>>() {
+        if !bvh[idx].has_channel(PositionX) {
+            bvh.add_channels(idx, &[PositionX, PositionY, PositionZ])
+                .unwrap();
+            num_added += 3;
+        }
+    }
+    assert_consistent(&bvh);
+    assert!(num_added > 0);
+    assert_eq!(bvh.num_channels(), num_channels + num_added);
+    for joint in bvh.joints() {
+        let types: Vec<_> = joint.channels.iter().map(|ch| ch.channel_type()).collect();
+        assert_eq!(&types[..3], &[PositionX, PositionY, PositionZ]);
+        assert!(types[3..].iter().all(|ty| ty.is_rotation()));
+    }
+
+    for (key, values) in &channel_values(&bvh) {
+        match before.get(key) {
+            Some(expected) => assert!(values == expected),
+            None => assert!(values.iter().all(|&value| value == 0.0)),
+        }
+    }
+
+    // Only the channels which were added are zero throughout the file.
+    assert_eq!(bvh.remove_zero_channels(), num_added);
+    assert_consistent(&bvh);
+    assert!(channel_values(&bvh) == before);
+    assert_eq!(bvh.remove_zero_channels(), 0);
+
+    assert_eq!(
+        bvh.remove_channels(elbow, &[PositionX]),
+        Err(EditJointsError::MissingChannel {
+            joint: elbow,
+            channel_type: PositionX,
+        })
+    );
+    bvh.remove_channels(elbow, &[RotationX, RotationY]).unwrap();
+    assert_consistent(&bvh);
+    let types: Vec<_> = bvh[elbow]
+        .channels
+        .iter()
+        .map(|ch| ch.channel_type())
+        .collect();
+    assert_eq!(types, [RotationZ]);
+
+    let mut expected = before;
+    expected.remove(&(b"LeftElbow".to_vec(), RotationX));
+    expected.remove(&(b"LeftElbow".to_vec(), RotationY));
+    assert!(channel_values(&bvh) == expected);
+}
+
+#[test]
+fn set_channel_order() {
+    use ChannelType::*;
+
+    let mut bvh = bvh! {
+        HIERARCHY
+        ROOT Hips
+        {
+            OFFSET 0.0 0.0 0.0
+            CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation
+            JOINT Chest
+            {
+                OFFSET 0.0 10.0 0.0
+                CHANNELS 2 Zrotation Xrotation
+                End Site
+                {
+                    OFFSET 0.0 10.0 0.0
+                }
+            }
+        }
+        MOTION
+        Frames: 2
+        Frame Time: 0.033333333
+        1.0 2.0 3.0 30.0 0.0 0.0 5.0 6.0
+        4.0 5.0 6.0 0.0 0.0 45.0 7.0 8.0
+    };
+
+    let hips = JointIndex::new(0);
+    let chest = JointIndex::new(1);
+    assert_eq!(
+        bvh.set_channel_order(hips, &[PositionX, PositionY, PositionZ]),
+        Err(EditJointsError::MissingChannel {
+            joint: hips,
+            channel_type: RotationZ,
+        })
+    );
+    assert_eq!(
+        bvh.set_channel_order(chest, &[RotationX, RotationZ]),
+        Err(EditJointsError::UnsupportedRotationChannels { joint: chest })
+    );
+    assert_eq!(
+        bvh.set_channel_order(chest, &[RotationZ, RotationZ]),
+        Err(EditJointsError::DuplicateChannel {
+            joint: chest,
+            channel_type: RotationZ,
+        })
+    );
+
+    // Reordering positions only moves the values.
+    bvh.set_channel_order(
+        hips,
+        &[
+            PositionZ, PositionY, PositionX, RotationZ, RotationX, RotationY,
+        ],
+    )
+    .unwrap();
+    assert_consistent(&bvh);
+
+    // Reordering rotations converts them, keeping the orientation of the joint.
+    bvh.set_channel_order(
+        hips,
+        &[
+            RotationY, RotationX, RotationZ, PositionZ, PositionY, PositionX,
+        ],
+    )
+    .unwrap();
+    assert_consistent(&bvh);
+    let expected: [[Float; 8]; 2] = [
+        [0.0, 0.0, 30.0, 3.0, 2.0, 1.0, 5.0, 6.0],
+        [45.0, 0.0, 0.0, 6.0, 5.0, 4.0, 7.0, 8.0],
+    ];
+    for (frame, expected) in bvh.frames().zip(expected.iter()) {
+        for (&value, &expected) in frame.as_slice().iter().zip(expected.iter()) {
+            assert!(
+                (value - expected).abs() < 1e-4,
+                "{:?} != {:?}",
+                frame,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn joint_motion() {
+    use bvh_anim::RotationOrder;
+
+    let mut bvh = bvh! {
+        HIERARCHY
+        ROOT Hips
+        {
+            OFFSET 0.0 0.0 0.0
+            CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation
+            JOINT Chest
+            {
+                OFFSET 0.0 5.0 0.0
+                CHANNELS 2 Yrotation Xrotation
+                End Site
+                {
+                    OFFSET 0.0 5.0 0.0
+                }
+            }
+        }
+        MOTION
+        Frames: 2
+        Frame Time: 0.0333333
+        1.0 2.0 3.0 10.0 20.0 30.0 40.0 50.0
+        4.0 5.0 6.0 11.0 21.0 31.0 41.0 51.0
+    };
+    let hips = JointIndex::new(0);
+    let chest = JointIndex::new(1);
+
+    let motion = bvh.joint_motion(1, hips);
+    assert_eq!(motion.translation, Some([4.0, 5.0, 6.0].into()));
+    let rotation = motion.rotation.unwrap();
+    assert_eq!(rotation.order, RotationOrder::ZXY);
+    assert_eq!(rotation.order.to_string(), "ZXY");
+    assert_eq!(rotation.angles, [21.0, 31.0, 11.0].into());
+    assert_eq!(bvh[hips].rotation_order(), Some(RotationOrder::ZXY));
+
+    // Missing rotation axes are zero and come last in the order.
+    let motion = bvh.joint_motion(0, chest);
+    assert_eq!(motion.translation, None);
+    let rotation = motion.rotation.unwrap();
+    assert_eq!(rotation.order, RotationOrder::YXZ);
+    assert_eq!(rotation.angles, [50.0, 40.0, 0.0].into());
+
+    // Only the channels of the joint are written back.
+    {
+        let mut motion = bvh.joint_motion_mut(0, chest);
+        motion.translation = Some([9.0, 9.0, 9.0].into());
+        let rotation = motion.rotation.as_mut().unwrap();
+        rotation.angles = [1.0, 2.0, 3.0].into();
+        rotation.order = RotationOrder::XYZ;
+    }
+    bvh.joint_motion_mut(1, hips).translation = Some([7.0, 8.0, 9.0].into());
+
+    let frames: Vec<&[Float]> = bvh.frames().map(|frame| frame.as_slice()).collect();
+    assert_eq!(frames[0], &[1.0, 2.0, 3.0, 10.0, 20.0, 30.0, 2.0, 1.0][..]);
+    assert_eq!(
+        frames[1],
+        &[7.0, 8.0, 9.0, 11.0, 21.0, 31.0, 41.0, 51.0][..]
+    );
+    assert_eq!(
+        bvh.joint_motion(0, chest).rotation.unwrap().order,
+        RotationOrder::YXZ
+    );
+}
+
+/// Every frame of `bvh`, copied out.
+fn all_frames(bvh: &Bvh) -> Vec<Vec<Float>> {
+    bvh.frames()
+        .map(|frame| frame.as_slice().to_vec())
+        .collect()
+}
+
+#[test]
+fn curves_in_either_layout() {
+    use ChannelType::*;
+
+    let frame_major = load_mocapbank();
+    let mut channel_major = frame_major.clone();
+    channel_major.set_layout(MotionLayout::ChannelMajor);
+    assert_eq!(frame_major.layout(), MotionLayout::FrameMajor);
+    assert_eq!(channel_major.layout(), MotionLayout::ChannelMajor);
+
+    // Curves and frames read the same values in both layouts.
+    let frames = all_frames(&frame_major);
+    assert_eq!(all_frames(&channel_major), frames);
+    for joint in frame_major.joints() {
+        for channel in &joint.channels {
+            let expected: Vec<Float> = frames.iter().map(|f| f[channel.motion_index()]).collect();
+            let (strided, contiguous) = (frame_major.curve(channel), channel_major.curve(channel));
+            assert_eq!(strided.to_vec(), expected);
+            assert_eq!(contiguous.as_slice(), Some(&expected[..]));
+            assert_eq!(strided.as_slice(), None);
+            assert!(strided.iter().rev().eq(expected.iter().rev()));
+            assert_eq!(strided[frames.len() - 1], expected[frames.len() - 1]);
+            assert_eq!(strided.get(frames.len()), None);
+            assert_eq!(channel_major.get_motion(3, channel), expected[3]);
+        }
+    }
+    assert_eq!(channel_major.to_bstring(), frame_major.to_bstring());
+
+    // Writing through a curve or a single value is seen by the frames.
+    let mut edits = [frame_major.clone(), channel_major.clone()];
+    for bvh in &mut edits {
+        let hips = JointIndex::new(0);
+        let x = bvh[hips].channels[0];
+        for value in bvh.curve_mut(&x).iter_mut() {
+            *value += 1.0;
+        }
+        let y = bvh[hips].channels[1];
+        bvh.set_motion(2, &y, 100.0);
+        bvh.joint_motion_mut(4, hips).translation = Some([1.0, 2.0, 3.0].into());
+    }
+    assert_eq!(edits[1].layout(), MotionLayout::ChannelMajor);
+    assert_eq!(all_frames(&edits[1]), all_frames(&edits[0]));
+
+    // Editing the hierarchy keeps the layout.
+    for bvh in &mut edits {
+        let elbow = bvh.joint_by_name("LeftElbow").unwrap();
+        bvh.add_channels(elbow, &[PositionY]).unwrap();
+        bvh.set_channel_order(elbow, &[RotationX, RotationY, RotationZ, PositionY])
+            .unwrap();
+        let knee = bvh.joint_by_name("RightKnee").unwrap();
+        bvh.remove_joint_merging_rotation(knee).unwrap();
+        assert_consistent(bvh);
+    }
+    assert_eq!(edits[1].layout(), MotionLayout::ChannelMajor);
+    assert_eq!(all_frames(&edits[1]), all_frames(&edits[0]));
+
+    // Borrowing the frames mutably goes back to frame-major.
+    let mut bvh = channel_major;
+    bvh.frames_mut().next().unwrap().as_mut_slice()[0] = -1.0;
+    assert_eq!(bvh.layout(), MotionLayout::FrameMajor);
+    assert_eq!(bvh.frames().next().unwrap().as_slice()[0], -1.0);
+
+    // Frames can be pushed in either layout.
+    let mut motions = [
+        Motion::new(2, Default::default()),
+        Motion::new(2, Default::default()),
+    ];
+    motions[1].set_layout(MotionLayout::ChannelMajor);
+    for motion in &mut motions {
+        motion.push_frame(&[1.0, 2.0]);
+        motion.push_frame(&[3.0, 4.0]);
+    }
+    assert_eq!(motions[0], motions[1]);
+    motions[1].set_layout(MotionLayout::FrameMajor);
+    assert!(motions[1]
+        .frames()
+        .map(|f| f.as_slice())
+        .eq(vec![&[1.0, 2.0][..], &[3.0, 4.0][..]]));
+}
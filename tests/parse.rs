@@ -130,3 +130,1471 @@ fn nonstandard_formatting() {
 
     assert_eq!(bvh, bvh_from_macro);
 }
+
+#[test]
+fn malformed_hierarchy_returns_error() {
+    use bvh_anim::errors::{LoadErrorKind, LoadJointsError};
+
+    macro_rules! assert_joints_err {
+        ($bytes:expr, $pattern:pat, $line:expr) => {
+            match bvh_anim::from_bytes(&$bytes[..]).map_err(|e| e.into_kind()) {
+                Err(LoadErrorKind::Joints(e @ $pattern)) => assert_eq!(e.line(), Some($line)),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        };
+    }
+
+    assert_joints_err!(
+        b"HIERARCHY\nHIERARCHY\n",
+        LoadJointsError::UnexpectedHierarchySection { .. },
+        1
+    );
+    assert_joints_err!(
+        b"ROOT Hips\n{\n}\n",
+        LoadJointsError::UnexpectedRoot { .. },
+        0
+    );
+    assert_joints_err!(
+        b"HIERARCHY\nROOT\n{\n}\n",
+        LoadJointsError::MissingJointName { .. },
+        1
+    );
+    assert_joints_err!(
+        b"HIERARCHY\nROOT Hips\n{\nOFFSET 0 0 0\nCHANNELS 0\nJOINT\n{\n}\n}\n",
+        LoadJointsError::MissingJointName { .. },
+        5
+    );
+    assert_joints_err!(
+        b"HIERARCHY\nROOT Hips\n{\nOFFSET 0 0 0\nCHANNELS 0\nEnd\n{\n}\n}\n",
+        LoadJointsError::MalformedEndSite { .. },
+        5
+    );
+    assert_joints_err!(
+        b"HIERARCHY\n}\n",
+        LoadJointsError::UnexpectedClosingBrace { .. },
+        1
+    );
+    assert_joints_err!(
+        b"HIERARCHY\nJOINT Chest\n{\n}\n",
+        LoadJointsError::UnexpectedJoint { .. },
+        1
+    );
+    assert_joints_err!(
+        b"HIERARCHY\nROOT Hips\n{\nROOT Chest\n}\n",
+        LoadJointsError::UnexpectedRoot { .. },
+        3
+    );
+    assert_joints_err!(
+        b"HIERARCHY\n{\n}\n",
+        LoadJointsError::UnexpectedOpeningBrace { .. },
+        1
+    );
+
+    // An end site may only cap a joint without children, and only once.
+    const CHEST: &[u8] = b"JOINT Chest\n{\nOFFSET 0 0 1\nCHANNELS 0\n";
+    const END_SITE: &[u8] = b"End Site\n{\nOFFSET 0 0 1\n}\n";
+    const HIPS: &[u8] = b"HIERARCHY\nROOT Hips\n{\nOFFSET 0 0 0\nCHANNELS 0\n";
+    assert_joints_err!(
+        [HIPS, CHEST, END_SITE, b"}\n", END_SITE, b"}\n"].concat(),
+        LoadJointsError::UnexpectedEndSite { .. },
+        14
+    );
+    assert_joints_err!(
+        [HIPS, END_SITE, CHEST, END_SITE, b"}\n}\n"].concat(),
+        LoadJointsError::UnexpectedJoint { .. },
+        9
+    );
+    assert_joints_err!(
+        [HIPS, END_SITE, END_SITE, b"}\n"].concat(),
+        LoadJointsError::UnexpectedEndSite { .. },
+        9
+    );
+
+    // A lenient parse keeps the children of a joint, and drops its end site.
+    {
+        use bvh_anim::parse::{ParseOptions, ParsePolicy, ParseWarning};
+
+        const MOTION: &[u8] = b"MOTION\nFrames: 0\nFrame Time: 0.0333333\n";
+        let bytes = [HIPS, END_SITE, CHEST, END_SITE, b"}\n}\n", MOTION].concat();
+        let (bvh, warnings) = ParseOptions::new()
+            .with_policy(ParsePolicy::Lenient)
+            .parse_bytes(&bytes[..])
+            .unwrap();
+
+        let joints = bvh.joints().collect::<Vec<_>>();
+        assert_eq!(joints.len(), 2);
+        assert!(!joints[0].has_end_site());
+        assert!(joints[1].has_end_site());
+        assert_eq!(joints[1].parent_index(), Some(0));
+
+        match warnings[..] {
+            [ParseWarning::EndSiteDropped { span }] => assert_eq!(span.line, 9),
+            ref other => panic!("unexpected warnings: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn broken_files_do_not_panic() {
+    for entry in std::fs::read_dir("./data/broken").unwrap() {
+        let path = entry.unwrap().path();
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(
+            bvh_anim::from_bytes(&bytes[..]).is_err(),
+            "{} should not parse",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn error_spans_point_at_token() {
+    use bvh_anim::errors::Span;
+
+    const BVH_BYTES: &[u8] = b"HIERARCHY
+ROOT Base
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 3 Xposition Yposition Zposition
+    End Site
+    {
+        OFFSET 0.0 0.0 30.0
+    }
+}
+MOTION
+Frames: 2
+Frame Time: 0.033333333
+0.0 0.0 0.0
+1.0 1.O 1.0
+";
+
+    let err = bvh_anim::from_bytes(BVH_BYTES).unwrap_err();
+    assert_eq!(err.span(), Some(Span::new(14, 4, 3)));
+    let rendered = err.display_with_source(BVH_BYTES).to_string();
+    assert!(rendered.starts_with(
+        "error: Could not load motion: could not parse the motion value for channel 1"
+    ));
+    assert!(rendered.ends_with(
+        "
+ --> 15:5
+   |
+15 | 1.0 1.O 1.0
+   |     ^^^"
+    ));
+
+    let err = bvh_anim::from_bytes(&BVH_BYTES[..BVH_BYTES.len() - 12]).unwrap_err();
+    assert_eq!(err.span(), Some(Span::new(11, 8, 1)));
+
+    let err = bvh_anim::from_bytes(&b"HIERARCHY\nROOT Base\n{\n"[..]).unwrap_err();
+    assert_eq!(err.span(), Some(Span::new(2, 1, 0)));
+}
+
+#[test]
+fn lenient_parse_repairs_inconsistencies() {
+    use bvh_anim::{
+        errors::{LoadErrorKind, LoadJointsError},
+        parse::{ParseOptions, ParsePolicy, ParseWarning},
+    };
+
+    const BVH_BYTES: &[u8] = b"HIERARCHY
+ROOT Base
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 4 Xposition Yposition Zposition
+    End Site
+    {
+        OFFSET 0.0 0.0 30.0
+    }
+}
+MOTION
+Frames: 2
+Frame Time: 0.033333333
+0.0 1.0
+1.0 2.0 3.0 4.0
+garbage
+
+2.0 2.0 2.0
+";
+
+    match bvh_anim::from_bytes(BVH_BYTES).map_err(|e| e.into_kind()) {
+        Err(LoadErrorKind::Joints(LoadJointsError::ChannelCountMismatch {
+            expected: 4,
+            actual: 3,
+            ..
+        })) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let (bvh, warnings) = ParseOptions::new()
+        .with_policy(ParsePolicy::Lenient)
+        .parse_bytes(BVH_BYTES)
+        .unwrap();
+
+    assert_eq!(bvh.num_channels(), 3);
+    assert_eq!(bvh.num_frames(), 3);
+    let frames = bvh.frames().map(|f| f.as_slice().to_vec()).collect::<Vec<_>>();
+    assert_eq!(
+        frames,
+        vec![
+            vec![0.0, 1.0, 0.0],
+            vec![1.0, 2.0, 3.0],
+            vec![2.0, 2.0, 2.0],
+        ]
+    );
+
+    let kinds = warnings
+        .iter()
+        .map(|w| match *w {
+            ParseWarning::ChannelCountMismatch { .. } => "channels",
+            ParseWarning::FramePadded { .. } => "padded",
+            ParseWarning::FrameTruncated { .. } => "truncated",
+            ParseWarning::FrameCountMismatch { .. } => "frames",
+            ParseWarning::SkippedLine { .. } => "skipped",
+            ParseWarning::EndSiteDropped { .. } => "end site",
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        ["channels", "padded", "truncated", "skipped", "frames"]
+    );
+    assert_eq!(warnings[3].span().line, 15);
+}
+
+#[test]
+fn frame_reader_matches_from_reader() {
+    use bvh_anim::parse::FrameReader;
+
+    let bvh = File::open("./data/test_mocapbank.bvh")
+        .map(BufReader::new)
+        .map(|r| bvh_anim::from_reader(r).unwrap())
+        .unwrap();
+
+    let mut reader = File::open("./data/test_mocapbank.bvh")
+        .map(BufReader::new)
+        .map(|r| FrameReader::new(r).unwrap())
+        .unwrap();
+
+    assert_eq!(reader.num_frames(), bvh.num_frames());
+    assert_eq!(reader.header().num_channels(), bvh.num_channels());
+    assert!(reader.header().joints().eq(bvh.joints()));
+
+    let mut expected_frames = bvh.frames();
+    while let Some(frame) = reader.next_frame() {
+        assert_eq!(frame.unwrap(), expected_frames.next().unwrap());
+    }
+    assert!(expected_frames.next().is_none());
+    assert_eq!(reader.num_frames_read(), bvh.num_frames());
+}
+
+#[test]
+fn frame_reader_reports_line_errors() {
+    use bvh_anim::{
+        errors::{LoadErrorKind, LoadMotionError},
+        parse::FrameReader,
+    };
+
+    const BVH_BYTES: &[u8] = b"HIERARCHY
+ROOT Base
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 3 Xposition Yposition Zposition
+    End Site
+    {
+        OFFSET 0.0 0.0 30.0
+    }
+}
+MOTION
+Frames: 3
+Frame Time: 0.033333333
+0.0 0.0 0.0
+1.0 x 1.0
+2.0 2.0
+";
+
+    let mut reader = FrameReader::new(BVH_BYTES).unwrap();
+    assert!(reader.next_frame().unwrap().is_ok());
+
+    let err = reader.next_frame().unwrap().unwrap_err();
+    match err.kind() {
+        LoadErrorKind::Motion(LoadMotionError::ParseMotionSection {
+            channel_index: 1, ..
+        }) => {}
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert_eq!(err.line(), Some(14));
+    assert!(reader.next_frame().is_none());
+}
+
+#[test]
+fn read_header_skips_motion_values() {
+    use bvh_anim::{
+        errors::{LoadErrorKind, LoadMotionError},
+        parse::{ParseOptions, ParsePolicy, ParseWarning},
+        Bvh,
+    };
+
+    let bvh = File::open("./data/test_mocapbank.bvh")
+        .map(BufReader::new)
+        .map(|r| bvh_anim::from_reader(r).unwrap())
+        .unwrap();
+
+    let header = File::open("./data/test_mocapbank.bvh")
+        .map(BufReader::new)
+        .map(|r| Bvh::read_header(r).unwrap())
+        .unwrap();
+
+    assert_eq!(header.num_frames(), bvh.num_frames());
+    assert_eq!(header.num_channels(), bvh.num_channels());
+    assert_eq!(header.frame_time(), bvh.frame_time());
+    assert!(header.joints().eq(bvh.joints()));
+    assert_eq!(header.as_bvh().num_frames(), 0);
+
+    // The motion values are never parsed, so garbage in them goes unnoticed.
+    const BVH_BYTES: &[u8] = b"HIERARCHY
+ROOT Base
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 3 Xposition Yposition Zposition
+    End Site
+    {
+        OFFSET 0.0 0.0 30.0
+    }
+}
+MOTION
+Frames: 3
+Frame Time: 0.033333333
+0.0 0.0 0.0
+not a frame
+
+";
+
+    let header = Bvh::read_header(BVH_BYTES).unwrap();
+    assert_eq!(header.num_frames(), 3);
+
+    let err = ParseOptions::new()
+        .with_count_frames(true)
+        .parse_header(BVH_BYTES)
+        .unwrap_err();
+    match err.kind() {
+        LoadErrorKind::Motion(LoadMotionError::MotionCountMismatch {
+            expected_num_frames: 3,
+            actual_total_motion_values: 6,
+            ..
+        }) => {}
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let (header, warnings) = ParseOptions::new()
+        .with_policy(ParsePolicy::Lenient)
+        .with_count_frames(true)
+        .parse_header(BVH_BYTES)
+        .unwrap();
+    assert_eq!(header.num_frames(), 2);
+    match warnings[..] {
+        [ParseWarning::FrameCountMismatch {
+            expected: 3,
+            actual: 2,
+            ..
+        }] => {}
+        ref other => panic!("unexpected warnings: {:?}", other),
+    }
+}
+
+#[test]
+fn push_parser_reads_from_socket() {
+    use bvh_anim::parse::{ParseEvent, PushParser};
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    const BVH_BYTES: &[u8] = include_bytes!("../data/test_mocapbank.bvh");
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_nodelay(true).unwrap();
+        // Chunks which do not line up with the lines of the file.
+        for chunk in BVH_BYTES.chunks(37) {
+            stream.write_all(chunk).unwrap();
+            stream.flush().unwrap();
+        }
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut parser = PushParser::new();
+    let mut buf = [0u8; 64];
+    let mut num_headers = 0;
+    let mut frames = vec![];
+
+    loop {
+        let n = stream.read(&mut buf).unwrap();
+        if n == 0 {
+            parser.finish();
+        } else {
+            parser.feed(&buf[..n]);
+        }
+
+        while let Some(event) = parser.next_event() {
+            match event.unwrap() {
+                ParseEvent::Header(_) => num_headers += 1,
+                ParseEvent::Frame(frame) => {
+                    assert_eq!(num_headers, 1);
+                    frames.push(frame.as_slice().to_vec());
+                }
+            }
+        }
+
+        if n == 0 {
+            break;
+        }
+    }
+    server.join().unwrap();
+
+    let bvh = bvh_anim::from_bytes(BVH_BYTES).unwrap();
+    let header = parser.header().unwrap();
+    assert_eq!(num_headers, 1);
+    assert_eq!(header.num_frames(), bvh.num_frames());
+    assert!(header.joints().eq(bvh.joints()));
+    assert_eq!(parser.num_frames_read(), bvh.num_frames());
+    assert!(frames
+        .iter()
+        .map(|f| &f[..])
+        .eq(bvh.frames().map(|f| f.as_slice())));
+}
+
+#[test]
+fn push_parser_handles_unknown_frame_count() {
+    use bvh_anim::{
+        errors::{LoadErrorKind, LoadJointsError},
+        parse::{ParseEvent, PushParser},
+    };
+
+    const BVH_BYTES: &[u8] = b"HIERARCHY
+ROOT Base
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 3 Xposition Yposition Zposition
+    End Site
+    {
+        OFFSET 0.0 0.0 30.0
+    }
+}
+MOTION
+Frame Time: 0.033333333
+0.0 1.0 2.0
+
+3.0 4.0 5.0";
+
+    let mut parser = PushParser::new();
+    let mut frames = vec![];
+
+    for byte in BVH_BYTES {
+        parser.feed(&[*byte]);
+        while let Some(event) = parser.next_event() {
+            if let ParseEvent::Frame(frame) = event.unwrap() {
+                frames.push(frame.as_slice().to_vec());
+            }
+        }
+    }
+
+    // The last line has no newline, so it is not parsed until the input ends.
+    assert_eq!(frames, vec![vec![0.0, 1.0, 2.0]]);
+    assert_eq!(parser.header().unwrap().num_frames(), 0);
+
+    parser.finish();
+    while let Some(event) = parser.next_event() {
+        if let ParseEvent::Frame(frame) = event.unwrap() {
+            frames.push(frame.as_slice().to_vec());
+        }
+    }
+    assert_eq!(frames, vec![vec![0.0, 1.0, 2.0], vec![3.0, 4.0, 5.0]]);
+    assert!(parser.next_event().is_none());
+
+    let mut parser = PushParser::new();
+    parser.feed(&BVH_BYTES[..22]);
+    assert!(parser.next_event().is_none());
+    parser.finish();
+    match parser.next_event().map(|e| e.map_err(|e| e.into_kind())) {
+        Some(Err(LoadErrorKind::Joints(LoadJointsError::MissingRoot { .. }))) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(parser.next_event().is_none());
+}
+
+#[test]
+fn large_motion_section_reports_global_error_location() {
+    use bvh_anim::errors::{LoadErrorKind, LoadMotionError};
+    use std::io::Cursor;
+
+    let mut bvh_string = String::from(
+        "HIERARCHY
+ROOT Base
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 3 Xposition Yposition Zposition
+    End Site
+    {
+        OFFSET 0.0 0.0 30.0
+    }
+}
+MOTION
+Frames: 50000
+Frame Time: 0.033333333
+",
+    );
+    const FIRST_FRAME_LINE: usize = 13;
+
+    for i in 0..50000 {
+        bvh_string.push_str(&format!("{}.0 {}.5 -{}.25\n", i, i, i));
+    }
+
+    let bvh = bvh_anim::from_str(&bvh_string).unwrap();
+    let from_reader = bvh_anim::from_reader(Cursor::new(&bvh_string)).unwrap();
+    assert_eq!(bvh, from_reader);
+    assert_eq!(bvh.num_frames(), 50000);
+    assert_eq!(bvh.frames().nth(40000).unwrap().as_slice(), &[40000.0, 40000.5, -40000.25]);
+
+    // Break two frames far apart; the earliest error must be reported.
+    let bad_bvh_string = bvh_string
+        .replacen("45000.5", "45000.x", 1)
+        .replacen("-38000.25", "-38000.2y", 1);
+
+    let check_error = |result: Result<bvh_anim::Bvh, bvh_anim::errors::LoadError>| {
+        let err = result.unwrap_err();
+        assert_eq!(err.line(), Some(FIRST_FRAME_LINE + 38000));
+        match err.into_kind() {
+            LoadErrorKind::Motion(LoadMotionError::ParseMotionSection {
+                channel_index: 2, ..
+            }) => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    };
+
+    check_error(bvh_anim::from_str(&bad_bvh_string));
+    check_error(bvh_anim::from_reader(Cursor::new(&bad_bvh_string)));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn load_from_mmap() {
+    let file = File::open("./data/test_mocapbank.bvh").unwrap();
+    let bvh = unsafe { bvh_anim::Bvh::from_mmap(&file) }.unwrap();
+
+    const BVH_BYTES: &[u8] = include_bytes!("../data/test_mocapbank.bvh");
+    assert_eq!(bvh, bvh_anim::from_bytes(BVH_BYTES).unwrap());
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn load_from_async_reader() {
+    use bvh_anim::{
+        errors::{LoadErrorKind, LoadMotionError},
+        parse::{ParseOptions, ParsePolicy},
+    };
+    use futures_executor::block_on;
+
+    const BVH_BYTES: &[u8] = include_bytes!("../data/test_mocapbank.bvh");
+
+    let bvh = block_on(bvh_anim::from_async_reader(BVH_BYTES)).unwrap();
+    assert_eq!(bvh, bvh_anim::from_bytes(BVH_BYTES).unwrap());
+
+    const BAD_BVH_BYTES: &[u8] = b"HIERARCHY
+ROOT Base
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 3 Xposition Yposition Zposition
+    End Site
+    {
+        OFFSET 0.0 0.0 30.0
+    }
+}
+MOTION
+Frames: 2
+Frame Time: 0.033333333
+0.0 0.0 0.0
+1.0 x 1.0
+";
+
+    let err = block_on(bvh_anim::from_async_reader(BAD_BVH_BYTES)).unwrap_err();
+    let sync_err = bvh_anim::from_bytes(BAD_BVH_BYTES).unwrap_err();
+    assert_eq!(err.span(), sync_err.span());
+    match err.kind() {
+        LoadErrorKind::Motion(LoadMotionError::ParseMotionSection {
+            channel_index: 1, ..
+        }) => {}
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let options = ParseOptions::new().with_policy(ParsePolicy::Lenient);
+    let (bvh, warnings) = block_on(options.parse_async(BAD_BVH_BYTES)).unwrap();
+    assert_eq!((bvh, warnings), options.parse_bytes(BAD_BVH_BYTES).unwrap());
+}
+
+#[test]
+fn multiple_root_hierarchies() {
+    use bvh_anim::parse::{ParseEvent, PushParser};
+
+    const BVH_BYTES: &[u8] = b"HIERARCHY
+ROOT Actor
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation
+    JOINT Head
+    {
+        OFFSET 0.0 10.0 0.0
+        CHANNELS 3 Zrotation Xrotation Yrotation
+        End Site
+        {
+            OFFSET 0.0 5.0 0.0
+        }
+    }
+}
+ROOT Prop
+{
+    OFFSET 1.0 0.0 0.0
+    CHANNELS 3 Xposition Yposition Zposition
+    End Site
+    {
+        OFFSET 0.0 1.0 0.0
+    }
+}
+MOTION
+Frames: 2
+Frame Time: 0.033333333
+0 1 2 3 4 5 6 7 8 9 10 11
+12 13 14 15 16 17 18 19 20 21 22 23
+";
+
+    let bvh = bvh_anim::from_bytes(BVH_BYTES).unwrap();
+
+    let joints = bvh.joints().collect::<Vec<_>>();
+    assert_eq!(joints.len(), 3);
+    assert_eq!(joints[1].parent_index(), Some(0));
+    assert_eq!(joints[2].parent_index(), None);
+    assert_eq!(joints[2].depth(), 0);
+
+    let roots = bvh.root_joints().map(|j| &j.name[..]).collect::<Vec<_>>();
+    assert_eq!(roots, [&b"Actor"[..], &b"Prop"[..]]);
+
+    let ranges = bvh
+        .root_channel_ranges()
+        .map(|(root, range)| (&root.name[..], range))
+        .collect::<Vec<_>>();
+    assert_eq!(ranges, [(&b"Actor"[..], 0..9), (&b"Prop"[..], 9..12)]);
+
+    let second_frame = bvh.frames().nth(1).unwrap();
+    assert_eq!(&second_frame.as_slice()[9..12], &[21.0, 22.0, 23.0]);
+    assert_eq!(joints[2].channels[0].motion_index(), 9);
+
+    // The writer must emit every root, so that the file can be loaded again.
+    let written = bvh_anim::from_bytes(&bvh.to_bstring()[..]).unwrap();
+    assert!(written.joints().eq(bvh.joints()));
+    assert!(written.frames().eq(bvh.frames()));
+
+    let mut parser = PushParser::new();
+    parser.feed(BVH_BYTES);
+    let mut num_frames = 0;
+    while let Some(event) = parser.next_event() {
+        match event.unwrap() {
+            ParseEvent::Header(header) => assert!(header.joints().eq(bvh.joints())),
+            ParseEvent::Frame(_) => num_frames += 1,
+        }
+    }
+    assert_eq!(num_frames, 2);
+}
+
+#[test]
+fn exporter_dialects_are_detected() {
+    use bvh_anim::{
+        parse::{Dialect, KeywordCase},
+        write::WriteOptions,
+    };
+
+    const BVH_BYTES: &[u8] = b"# Exported by a lowercase exporter
+hierarchy
+root Hips
+{
+    offset 0.0 0.0 0.0
+    channels 6 xposition yposition zposition zrotation xrotation yrotation
+    joint Chest
+    {
+        offset 0.0 5.0 0.0
+        channels 3 zrotation xrotation yrotation
+        end site
+        {
+            offset 0.0 5.0 0.0
+        }
+    }
+}
+motion
+frames:2
+frame time : 0.0333333
+# frame 0
+0.0 1.0 2.0 3.0 4.0 5.0 6.0 7.0 8.0
+10.0 11.0 12.0 13.0 14.0 15.0 16.0 17.0 18.0
+";
+
+    let bvh = bvh_anim::from_bytes(BVH_BYTES).unwrap();
+    assert_eq!(bvh.num_frames(), 2);
+    assert_eq!(bvh.frames().nth(1).unwrap().as_slice()[0], 10.0);
+
+    let dialect = *bvh.dialect();
+    assert_eq!(dialect.keyword_case, KeywordCase::Lowercase);
+    assert_eq!(dialect.channel_case, KeywordCase::Lowercase);
+    assert!(!dialect.lowercase_site);
+    assert!(dialect.compact_frames);
+    assert!(dialect.has_comments);
+    assert!(!dialect.is_standard());
+
+    // Writing with the detected dialect keeps the exporter's style.
+    let written = WriteOptions::new().with_dialect(dialect).write_to_string(&bvh);
+    let written = std::str::from_utf8(&written[..]).unwrap();
+    assert!(written.starts_with("hierarchy"));
+    assert!(written.contains("channels 3 zrotation xrotation yrotation"));
+    assert!(written.contains("end site"));
+    assert!(written.contains("frames:2"));
+    assert!(written.contains("frame time: "));
+
+    let reloaded = bvh_anim::from_str(written).unwrap();
+    assert!(reloaded.joints().eq(bvh.joints()));
+    assert!(reloaded.frames().eq(bvh.frames()));
+    let mut expected = dialect;
+    expected.has_comments = false;
+    assert_eq!(*reloaded.dialect(), expected);
+
+    // The dialect is not part of equality.
+    assert_eq!(reloaded, bvh);
+    let canonical = WriteOptions::new()
+        .with_preserve_syntax(false)
+        .write_to_string(&bvh);
+    let canonical = bvh_anim::from_bytes(&canonical[..]).unwrap();
+    assert!(canonical.dialect().is_standard());
+    assert_eq!(canonical, bvh);
+
+    const END_SITE_BVH: &str = "HIERARCHY
+ROOT Hips
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 1 Xposition
+    End site
+    {
+        OFFSET 0.0 0.0 0.0
+    }
+}
+MOTION
+Frames: 1
+FrameTime: 0.0333333
+1.0
+";
+
+    let end_site = bvh_anim::from_str(END_SITE_BVH).unwrap();
+    assert_eq!(
+        *end_site.dialect(),
+        Dialect::new().with_lowercase_site(true)
+    );
+    let written = WriteOptions::new()
+        .with_dialect(*end_site.dialect())
+        .write_to_string(&end_site);
+    assert!(std::str::from_utf8(&written[..]).unwrap().contains("End site"));
+
+    let mixed = bvh_anim::from_str(&END_SITE_BVH.replace("ROOT", "Root")).unwrap();
+    assert_eq!(mixed.dialect().keyword_case, KeywordCase::Mixed);
+    assert_eq!(mixed.dialect().channel_case, KeywordCase::Standard);
+}
+
+#[test]
+fn byte_order_marks_and_utf16_are_decoded() {
+    use bvh_anim::{
+        encoding::{NameEncoding, TextEncoding},
+        parse::{FrameReader, ParseEvent, PushParser},
+        Bvh,
+    };
+
+    const BVH_STRING: &str = "HIERARCHY
+ROOT Hüfte
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 3 Xposition Yposition Zposition
+    End Site
+    {
+        OFFSET 0.0 1.0 0.0
+    }
+}
+MOTION
+Frames: 1
+Frame Time: 0.0333333
+1.0 2.0 3.0
+";
+
+    let plain = bvh_anim::from_str(BVH_STRING).unwrap();
+    assert_eq!(plain.encoding(), TextEncoding::Utf8);
+
+    let mut utf8_bom = b"\xEF\xBB\xBF".to_vec();
+    utf8_bom.extend_from_slice(BVH_STRING.as_bytes());
+
+    let mut utf16_le = vec![0xFF, 0xFE];
+    let mut utf16_be = vec![0xFE, 0xFF];
+    for unit in BVH_STRING.encode_utf16() {
+        utf16_le.extend_from_slice(&unit.to_le_bytes());
+        utf16_be.extend_from_slice(&unit.to_be_bytes());
+    }
+
+    for &(ref bytes, encoding) in &[
+        (utf8_bom.clone(), TextEncoding::Utf8WithBom),
+        (utf16_le.clone(), TextEncoding::Utf16Le),
+        (utf16_be, TextEncoding::Utf16Be),
+    ] {
+        let bvh = bvh_anim::from_bytes(bytes).unwrap();
+        assert_eq!(bvh.encoding(), encoding);
+        assert_eq!(bvh.frames().next(), plain.frames().next());
+        assert_eq!(bvh, plain);
+
+        let root = bvh.root_joint().unwrap();
+        assert_eq!(root.name_str(), Ok("Hüfte"));
+        assert_eq!(root.name_lossy(NameEncoding::Utf8), "Hüfte");
+    }
+
+    // The byte order mark of a `utf8` file is written back.
+    let (preserved, _) = bvh_anim::parse::ParseOptions::new()
+        .with_preserve_syntax(true)
+        .parse_bytes(&utf8_bom[..])
+        .unwrap();
+    assert_eq!(preserved.to_bstring(), utf8_bom);
+    let bvh = bvh_anim::from_bytes(&utf8_bom[..]).unwrap();
+    assert!(bvh.to_bstring().starts_with(b"\xEF\xBB\xBFHIERARCHY"));
+    assert!(plain.to_bstring().starts_with(b"HIERARCHY"));
+    let utf16 = bvh_anim::from_bytes(&utf16_le[..]).unwrap();
+    assert!(utf16.to_bstring().starts_with(b"HIERARCHY"));
+
+    let reader = FrameReader::new(&utf8_bom[..]).unwrap();
+    assert_eq!(reader.header().as_bvh().encoding(), TextEncoding::Utf8WithBom);
+
+    // The line-based loaders transcode `utf16` as it is read, even when a code
+    // unit or a surrogate pair is split between two reads.
+    let bone_string = BVH_STRING.replace("Hüfte", "Hüfte\u{1F9B4}");
+    let bone = bvh_anim::from_str(&bone_string).unwrap();
+    let mut bone_utf16 = vec![0xFE, 0xFF];
+    for unit in bone_string.encode_utf16() {
+        bone_utf16.extend_from_slice(&unit.to_be_bytes());
+    }
+
+    for &(ref bytes, encoding, ref expected) in &[
+        (utf8_bom.clone(), TextEncoding::Utf8WithBom, &plain),
+        (utf16_le.clone(), TextEncoding::Utf16Le, &plain),
+        (bone_utf16, TextEncoding::Utf16Be, &bone),
+    ] {
+        let header = Bvh::read_header(&bytes[..]).unwrap();
+        assert_eq!(header.as_bvh().encoding(), encoding);
+        assert!(header.joints().eq(expected.joints()));
+
+        for &capacity in &[1, 3, 64] {
+            let mut reader =
+                FrameReader::new(BufReader::with_capacity(capacity, &bytes[..])).unwrap();
+            assert_eq!(reader.header().as_bvh().encoding(), encoding);
+            let frame = reader.next_frame().unwrap().unwrap().as_slice().to_vec();
+            assert_eq!(&frame[..], expected.frames().next().unwrap().as_slice());
+            assert!(reader.next_frame().is_none());
+        }
+
+        let mut parser = PushParser::new();
+        let mut frames = vec![];
+        for byte in bytes.chunks(1) {
+            parser.feed(byte);
+            while let Some(event) = parser.next_event() {
+                if let ParseEvent::Frame(frame) = event.unwrap() {
+                    frames.push(frame.as_slice().to_vec());
+                }
+            }
+        }
+        parser.finish();
+        assert!(parser.next_event().is_none());
+        let header = parser.header().unwrap();
+        assert_eq!(header.as_bvh().encoding(), encoding);
+        assert!(header.joints().eq(expected.joints()));
+        assert_eq!(frames.len(), 1);
+
+        #[cfg(feature = "async")]
+        {
+            let bvh = futures_executor::block_on(bvh_anim::from_async_reader(&bytes[..])).unwrap();
+            assert_eq!(bvh.encoding(), encoding);
+            assert_eq!(bvh, **expected);
+        }
+    }
+
+
+    let latin1_bytes = BVH_STRING.chars().map(|c| c as u8).collect::<Vec<_>>();
+    let latin1 = bvh_anim::from_bytes(&latin1_bytes).unwrap();
+    let root = latin1.root_joint().unwrap();
+    assert!(root.name_str().is_err());
+    assert_eq!(root.name_lossy(NameEncoding::Latin1), "Hüfte");
+    assert_eq!(root.name_lossy(NameEncoding::Utf8), "H\u{FFFD}fte");
+}
+
+#[test]
+fn load_compressed_files() {
+    use bvh_anim::{compression::Compression, write::WriteOptions, Bvh};
+
+    const BVH_BYTES: &[u8] = include_bytes!("../data/test_mocapbank.bvh");
+    let expected = bvh_anim::from_bytes(BVH_BYTES).unwrap();
+
+    assert_eq!(
+        Bvh::from_compressed_reader(BVH_BYTES).unwrap(),
+        expected
+    );
+    assert_eq!(
+        Bvh::from_path("./data/test_mocapbank.bvh").unwrap(),
+        expected
+    );
+
+    let uncompressed = WriteOptions::new().write_to_string(&expected);
+    let expected = bvh_anim::from_bytes(&uncompressed).unwrap();
+
+    for &(compression, extension) in &[(Compression::Gzip, "gz"), (Compression::Zstd, "zst")] {
+        let mut compressed = vec![];
+        let written = WriteOptions::new()
+            .with_compression(compression)
+            .write(&expected, &mut compressed);
+
+        if !compression.is_supported() {
+            assert!(written.is_err());
+            continue;
+        }
+
+        written.unwrap();
+        assert_eq!(Compression::detect(&compressed), compression);
+        assert!(compressed.len() < uncompressed.len());
+        assert_eq!(
+            Bvh::from_compressed_reader(&compressed[..]).unwrap(),
+            expected
+        );
+
+        let path = std::env::temp_dir().join(format!(
+            "bvh_anim_test_mocapbank_{}.bvh.{}",
+            std::process::id(),
+            extension
+        ));
+        std::fs::write(&path, &compressed).unwrap();
+        let loaded = Bvh::from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), expected);
+    }
+}
+
+#[test]
+fn untrusted_input_is_limited() {
+    use bvh_anim::{
+        errors::{LoadErrorKind, LoadJointsError, LoadMotionError},
+        parse::{FrameReader, Limits, ParseOptions, ParsePolicy, PushParser},
+    };
+
+    const BVH_STRING: &str = "HIERARCHY
+ROOT Hips
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 3 Xposition Yposition Zposition
+    JOINT Chest
+    {
+        OFFSET 0.0 5.0 0.0
+        CHANNELS 3 Zrotation Xrotation Yrotation
+        JOINT Neck
+        {
+            OFFSET 0.0 5.0 0.0
+            CHANNELS 3 Zrotation Xrotation Yrotation
+            End Site
+            {
+                OFFSET 0.0 5.0 0.0
+            }
+        }
+    }
+}
+MOTION
+Frames: 1
+Frame Time: 0.0333333
+1.0 2.0 3.0 4.0 5.0 6.0 7.0 8.0 9.0
+";
+
+    let parse = |limits: Limits, bvh: &str| {
+        ParseOptions::new()
+            .with_limits(limits)
+            .parse_bytes(bvh.as_bytes())
+            .map(|(bvh, _)| bvh)
+    };
+
+    assert!(parse(Limits::new(), BVH_STRING).is_ok());
+
+    // A header which claims far more frames than the file contains must not
+    // allocate memory for them.
+    let err = bvh_anim::from_str(&BVH_STRING.replace("Frames: 1", "Frames: 99999999999"))
+        .unwrap_err();
+    match err.into_kind() {
+        LoadErrorKind::Motion(LoadMotionError::MotionCountMismatch { .. }) => {}
+        e => panic!("unexpected error: {:?}", e),
+    }
+
+    let err = bvh_anim::from_str(&BVH_STRING.replace("Frames: 1", "Frames: 18446744073709551615"))
+        .unwrap_err();
+    match err.into_kind() {
+        LoadErrorKind::Motion(LoadMotionError::TooManyMotionValues { limit, span }) => {
+            assert_eq!(limit, usize::MAX);
+            assert_eq!(span.line, 21);
+        }
+        e => panic!("unexpected error: {:?}", e),
+    }
+
+    match parse(Limits::new().with_max_joints(2), BVH_STRING).unwrap_err().into_kind() {
+        LoadErrorKind::Joints(LoadJointsError::TooManyJoints { limit, span }) => {
+            assert_eq!(limit, 2);
+            assert_eq!(span.line, 9);
+        }
+        e => panic!("unexpected error: {:?}", e),
+    }
+
+    match parse(Limits::new().with_max_depth(1), BVH_STRING).unwrap_err().into_kind() {
+        LoadErrorKind::Joints(LoadJointsError::HierarchyTooDeep { limit, span }) => {
+            assert_eq!(limit, 1);
+            assert_eq!(span.line, 9);
+        }
+        e => panic!("unexpected error: {:?}", e),
+    }
+
+    match parse(Limits::new().with_max_channels(7), BVH_STRING).unwrap_err().into_kind() {
+        LoadErrorKind::Joints(LoadJointsError::TooManyChannels { limit, span }) => {
+            assert_eq!(limit, 7);
+            assert_eq!((span.line, span.column), (12, 33));
+        }
+        e => panic!("unexpected error: {:?}", e),
+    }
+
+    match parse(Limits::new().with_max_motion_values(8), BVH_STRING).unwrap_err().into_kind() {
+        LoadErrorKind::Motion(LoadMotionError::TooManyMotionValues { limit, .. }) => {
+            assert_eq!(limit, 8);
+        }
+        e => panic!("unexpected error: {:?}", e),
+    }
+
+    let limits = Limits::new()
+        .with_max_joints(3)
+        .with_max_depth(2)
+        .with_max_channels(9)
+        .with_max_frames(1)
+        .with_max_motion_values(9);
+    assert!(parse(limits, BVH_STRING).is_ok());
+
+    // Every loader checks the motion values against the limits as it reads
+    // them, whatever the header says.
+    let extra_frames = BVH_STRING.to_string() + &"1.0 2.0 3.0 4.0 5.0 6.0 7.0 8.0 9.0\n".repeat(2);
+    let lenient = ParseOptions::new()
+        .with_policy(ParsePolicy::Lenient)
+        .with_limits(Limits::new().with_max_frames(2));
+    let strict = ParseOptions::new().with_limits(Limits::new().with_max_motion_values(18));
+
+    let mut errors = vec![lenient.parse_bytes(extra_frames.as_bytes()).unwrap_err()];
+    #[cfg(not(feature = "rayon"))]
+    errors.push(strict.parse_bytes(extra_frames.as_bytes()).unwrap_err());
+    #[cfg(feature = "async")]
+    {
+        use futures_executor::block_on;
+        errors.push(block_on(lenient.parse_async(extra_frames.as_bytes())).unwrap_err());
+        errors.push(block_on(strict.parse_async(extra_frames.as_bytes())).unwrap_err());
+    }
+    for options in [&lenient, &strict].iter().copied() {
+        let mut reader =
+            FrameReader::with_options(extra_frames.as_bytes(), options.clone()).unwrap();
+        let mut err = None;
+        while let Some(frame) = reader.next_frame() {
+            err = err.or(frame.err());
+        }
+        errors.push(err.unwrap());
+
+        let mut parser = PushParser::with_options(options.clone());
+        parser.feed(extra_frames.as_bytes());
+        parser.finish();
+        let mut err = None;
+        while let Some(event) = parser.next_event() {
+            err = err.or(event.err());
+        }
+        errors.push(err.unwrap());
+    }
+
+    for err in errors {
+        assert_eq!(err.span().map(|span| span.line), Some(25));
+        match err.into_kind() {
+            LoadErrorKind::Motion(LoadMotionError::TooManyFrames { limit: 2, .. })
+            | LoadErrorKind::Motion(LoadMotionError::TooManyMotionValues { limit: 18, .. }) => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+}
+
+#[test]
+fn hierarchy_is_navigable_by_joint_index() {
+    use bvh_anim::{Bvh, JointIndex};
+
+    let bvh = File::open("./data/test_mocapbank.bvh")
+        .map(BufReader::new)
+        .map(bvh_anim::from_reader)
+        .unwrap()
+        .unwrap();
+
+    let parent_of = |bvh: &Bvh, idx: JointIndex| bvh[idx].parent();
+    let chain = |bvh: &Bvh, idx: JointIndex| {
+        std::iter::successors(Some(idx), |&idx| parent_of(bvh, idx)).collect::<Vec<_>>()
+    };
+
+    for idx in bvh.joint_indices() {
+        let children: Vec<_> = bvh
+            .joint_indices()
+            .filter(|&child| parent_of(&bvh, child) == Some(idx))
+            .collect();
+        assert_eq!(bvh.children(idx).collect::<Vec<_>>(), children);
+
+        // Joints are stored in depth-first order, so the descendants of a joint
+        // are the run of deeper joints which follow it.
+        let depth = bvh[idx].depth();
+        let descendants: Vec<_> = bvh
+            .joint_indices()
+            .skip(idx.get() + 1)
+            .take_while(|&other| bvh[other].depth() > depth)
+            .collect();
+        assert_eq!(bvh.descendants(idx).collect::<Vec<_>>(), descendants);
+
+        let mut breadth_first: Vec<_> = bvh.descendants_breadth_first(idx).collect();
+        assert!(breadth_first
+            .windows(2)
+            .all(|w| bvh[w[0]].depth() <= bvh[w[1]].depth()));
+        breadth_first.sort();
+        assert_eq!(breadth_first, descendants);
+
+        let path = chain(&bvh, idx);
+        assert_eq!(bvh.path_to_root(idx).collect::<Vec<_>>(), path);
+        assert_eq!(bvh.ancestors(idx).collect::<Vec<_>>(), &path[1..]);
+        assert_eq!(bvh.lowest_common_ancestor(idx, idx), Some(idx));
+    }
+
+    let left_ankle = bvh
+        .joint_indices()
+        .find(|&idx| bvh[idx].name[..] == b"LeftAnkle"[..])
+        .unwrap();
+    let right_ankle = bvh
+        .joint_indices()
+        .find(|&idx| bvh[idx].name[..] == b"RightAnkle"[..])
+        .unwrap();
+    let lca = bvh.lowest_common_ancestor(left_ankle, right_ankle).unwrap();
+    assert_eq!(&bvh[lca].name[..], b"Hips");
+    assert_eq!(bvh.lowest_common_ancestor(right_ankle, lca), Some(lca));
+
+    const TWO_ROOTS: &str = "HIERARCHY
+ROOT A
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 0
+    End Site
+    {
+        OFFSET 0.0 0.0 0.0
+    }
+}
+ROOT B
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 0
+    End Site
+    {
+        OFFSET 0.0 0.0 0.0
+    }
+}
+MOTION
+Frames: 0
+Frame Time: 0.0333333
+";
+    let bvh = bvh_anim::from_str(TWO_ROOTS).unwrap();
+    let (a, b) = (JointIndex::new(0), JointIndex::new(1));
+    assert_eq!(bvh.children(a).len(), 0);
+    assert_eq!(bvh.lowest_common_ancestor(a, b), None);
+}
+
+#[test]
+fn joints_are_selected_by_name() {
+    use bvh_anim::select::Selector;
+
+    let mut bvh = File::open("./data/test_mocapbank.bvh")
+        .map(BufReader::new)
+        .map(bvh_anim::from_reader)
+        .unwrap()
+        .unwrap();
+
+    // Names can be repeated, in which case the first joint is found.
+    for joint in bvh.joints() {
+        let first = bvh.joints().position(|other| other.name == joint.name).unwrap();
+        assert_eq!(bvh.joint_by_name(&joint.name).unwrap().get(), first);
+    }
+    assert_eq!(bvh.joint_by_name("LeftHand"), None);
+
+    let names = |selector: Selector<'_>| {
+        bvh.select(&selector)
+            .joints()
+            .map(|(_, joint)| String::from_utf8(joint.name.to_vec()).unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        names(Selector::glob("Left*")),
+        [
+            "LeftCollar",
+            "LeftShoulder",
+            "LeftElbow",
+            "LeftWrist",
+            "LeftHip",
+            "LeftKnee",
+            "LeftAnkle"
+        ]
+    );
+    assert_eq!(names(Selector::glob("*Hip")), ["LeftHip", "RightHip"]);
+    assert_eq!(names(Selector::glob("Right?nee")), ["RightKnee"]);
+    assert_eq!(
+        names(Selector::name("LeftHip").subtree()),
+        ["LeftHip", "LeftKnee", "LeftAnkle"]
+    );
+    assert_eq!(names(Selector::all()).len(), bvh.joints().len());
+
+    let selection = bvh.select(&Selector::name("LeftHip").subtree());
+    let channels: Vec<_> = selection.channels().copied().collect();
+    let expected: Vec<_> = selection
+        .joints()
+        .flat_map(|(_, joint)| joint.channels.iter().copied())
+        .collect();
+    assert_eq!(channels, expected);
+    assert_eq!(channels.len(), 9);
+
+    // Renaming joints must be reflected by later lookups.
+    for joint in bvh.joints_mut() {
+        let mut name = b"mixamorig:".to_vec();
+        name.extend_from_slice(&joint.name);
+        joint.name = name[..].into();
+    }
+    assert_eq!(bvh.joint_by_name("LeftHip"), None);
+    assert!(bvh.joint_by_name("mixamorig:LeftHip").is_some());
+
+    // Namespaced names survive being written and parsed again.
+    let reparsed = bvh_anim::from_bytes(&bvh.to_bstring()[..]).unwrap();
+    assert!(reparsed.joints().map(|j| &j.name).eq(bvh.joints().map(|j| &j.name)));
+
+    let stripped = bvh.select(&Selector::glob("Left*").strip_prefix("mixamorig:"));
+    assert_eq!(stripped.len(), 7);
+    let namespaced = bvh.select(&Selector::glob("Left*").strip_namespace());
+    assert_eq!(namespaced.indices(), stripped.indices());
+    assert!(bvh.select(&Selector::glob("Left*")).is_empty());
+}
+
+#[test]
+fn clips_share_a_skeleton() {
+    use bvh_anim::{
+        errors::{LoadErrorKind, LoadJointsError, MismatchedChannelsError},
+        Bvh, FrameRate, JointIndex, Motion,
+    };
+    use std::sync::Arc;
+
+    const BVH_BYTES: &[u8] = include_bytes!("../data/test_simple.bvh");
+    let first = bvh_anim::from_bytes(BVH_BYTES).unwrap();
+
+    let second_bytes = String::from_utf8(BVH_BYTES.to_vec())
+        .unwrap()
+        .replace("1.0 1.0 1.0", "2.0 2.0 2.0");
+    let second = Bvh::from_bytes_with_skeleton(&second_bytes, first.skeleton()).unwrap();
+    let third = Bvh::from_reader_with_skeleton(BVH_BYTES, first.skeleton()).unwrap();
+
+    assert!(Arc::ptr_eq(first.skeleton(), second.skeleton()));
+    assert!(Arc::ptr_eq(first.skeleton(), third.skeleton()));
+    assert_eq!(Arc::strong_count(first.skeleton()), 3);
+    assert_eq!(third, first);
+    assert_ne!(second.motion(), first.motion());
+    assert_eq!(second.frames().nth(1).unwrap().as_slice(), &[2.0; 9][..]);
+
+    // A hierarchy which differs from the skeleton is rejected.
+    let renamed = String::from_utf8(BVH_BYTES.to_vec())
+        .unwrap()
+        .replace("JOINT End", "JOINT Tip");
+    match Bvh::from_bytes_with_skeleton(&renamed, first.skeleton()).map_err(|e| e.into_kind()) {
+        Err(LoadErrorKind::Joints(LoadJointsError::SkeletonMismatch { joint })) => {
+            assert_eq!(joint, JointIndex::new(1))
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // Editing the joints of one clip leaves the other clips untouched.
+    let mut edited = second;
+    edited.joints_mut().next().unwrap().name = b"Root"[..].into();
+    assert!(!Arc::ptr_eq(first.skeleton(), edited.skeleton()));
+    assert_eq!(&first.root_joint().unwrap().name[..], &b"Base"[..]);
+    assert_eq!(&edited.root_joint().unwrap().name[..], &b"Root"[..]);
+    assert_eq!(first.joint_by_name("Root"), None);
+    assert_eq!(edited.joint_by_name("Root"), Some(JointIndex::new(0)));
+
+    // A motion can only be paired with a skeleton with the same number of channels.
+    let (skeleton, motion) = first.into_parts();
+    assert_eq!(skeleton.num_channels(), motion.num_channels());
+    let rebuilt = Bvh::from_parts(Arc::clone(&skeleton), motion).unwrap();
+    assert_eq!(rebuilt, third);
+
+    let mut short = Motion::new(3, FrameRate::FPS_30);
+    short.push_frame(&[0.0, 1.0, 2.0]);
+    assert_eq!(
+        Bvh::from_parts(skeleton, short).unwrap_err(),
+        MismatchedChannelsError {
+            skeleton_channels: 9,
+            motion_channels: 3,
+        }
+    );
+}
+
+#[test]
+fn frame_times_are_exact_frame_rates() {
+    use bstr::ByteSlice;
+    use bvh_anim::FrameRate;
+    use std::time::Duration;
+
+    fn parse_frame_time(frame_time: &str) -> FrameRate {
+        let text = format!(
+            "HIERARCHY\nROOT Base\n{{\nOFFSET 0 0 0\nCHANNELS 1 Xrotation\n\
+             End Site\n{{\nOFFSET 0 0 1\n}}\n}}\nMOTION\nFrames: 1\n\
+             Frame Time: {}\n0\n",
+            frame_time
+        );
+        bvh_anim::from_bytes(text.as_bytes()).unwrap().frame_rate()
+    }
+
+    // Frame times written to any number of figures snap to the standard rates.
+    assert_eq!(parse_frame_time("0.0333333"), FrameRate::FPS_30);
+    assert_eq!(parse_frame_time("0.033333333333"), FrameRate::FPS_30);
+    assert_eq!(parse_frame_time("0.0333667"), FrameRate::FPS_29_97);
+    assert_eq!(parse_frame_time("0.033367"), FrameRate::FPS_29_97);
+    assert_eq!(parse_frame_time("0.00833333"), FrameRate::FPS_120);
+    assert_eq!(parse_frame_time("0.0166667"), FrameRate::FPS_60);
+    assert_eq!(parse_frame_time("0.041667"), FrameRate::FPS_24);
+    assert_eq!(parse_frame_time("0.04"), FrameRate::FPS_25);
+
+    // Anything else is kept as written.
+    assert_eq!(parse_frame_time("0.0125"), FrameRate::new(80, 1).unwrap());
+    assert_eq!(parse_frame_time("0.0335"), FrameRate::new(2000, 67).unwrap());
+    assert_eq!(parse_frame_time("0"), FrameRate::default());
+
+    let ntsc = FrameRate::FPS_29_97;
+    assert_eq!(ntsc.to_string(), "30000/1001 fps");
+    assert_eq!(ntsc.time_of_frame(30000), Duration::from_secs(1001));
+    assert_eq!(ntsc.time_of_frame(1), Duration::from_nanos(33366667));
+    assert_eq!(ntsc.frame_at_time(Duration::from_secs(1001)), 30000);
+    assert_eq!(ntsc.frame_at_time(Duration::from_nanos(33366666)), 0);
+    assert_eq!(ntsc.frame_at_time(Duration::from_nanos(33366667)), 1);
+    assert_eq!(FrameRate::default().frame_at_time(Duration::from_secs(1)), 0);
+
+    // The writer prints the frame time from the exact frame rate.
+    let mut bvh = bvh_anim::from_bytes(include_bytes!("../data/test_simple.bvh")).unwrap();
+    bvh.set_frame_rate(FrameRate::FPS_29_97);
+    let written = bvh_anim::write::WriteOptions::new()
+        .with_full_precision(false)
+        .write_to_string(&bvh);
+    assert!(written.contains_str("Frame Time: 0.0333667"));
+    let reparsed = bvh_anim::from_bytes(&written[..]).unwrap();
+    assert_eq!(reparsed.frame_rate(), FrameRate::FPS_29_97);
+    let reparsed = bvh_anim::from_bytes(&bvh.to_bstring()[..]).unwrap();
+    assert_eq!(reparsed.frame_rate(), FrameRate::FPS_29_97);
+
+    bvh.set_frame_time(Duration::from_nanos(8333333));
+    assert_eq!(bvh.frame_rate(), FrameRate::FPS_120);
+}
+
+#[test]
+fn end_sites_are_consistent_across_paths() {
+    use bstr::ByteSlice;
+    use bvh_anim::{
+        builder::Builder,
+        parse::{ParseOptions, ParsePolicy},
+        ChannelType, JointIndex,
+    };
+    use std::time::Duration;
+
+    const BVH_BYTES: &[u8] = include_bytes!("../data/test_simple.bvh");
+    let parsed = bvh_anim::from_bytes(BVH_BYTES).unwrap();
+
+    let from_macro = bvh_anim::bvh! {
+        HIERARCHY
+        ROOT Base
+        {
+            OFFSET 0.0 0.0 0.0
+            CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation
+            JOINT End
+            {
+                OFFSET 0.0 0.0 15.0
+                CHANNELS 3 Zrotation Xrotation Yrotation
+                End Site
+                {
+                    OFFSET 0.0 0.0 30.0
+                }
+            }
+        }
+        MOTION
+        Frames: 2
+        Frame Time: 0.033333333
+        0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0
+        1.0 1.0 1.0 1.0 1.0 1.0 1.0 1.0 1.0
+    };
+
+    use ChannelType::*;
+    let root_channels = [PositionX, PositionY, PositionZ, RotationZ, RotationX, RotationY];
+    let built = Builder::with_root_joint(b"Base".as_bstr(), [0.0; 3].into(), &root_channels)
+        // The end site of a joint which gains a child is removed.
+        .push_end([0.0, 0.0, 1.0].into())
+        .push_child(1, b"End".as_bstr(), [0.0, 0.0, 15.0].into(), &root_channels[3..])
+        .push_end([0.0, 0.0, 30.0].into())
+        .with_motion(2, Duration::from_nanos(33_333_333))
+        .push_frame(&[0.0; 9])
+        .push_frame(&[1.0; 9])
+        .build()
+        .unwrap();
+
+    let written = bvh_anim::from_bytes(&parsed.to_bstring()[..]).unwrap();
+
+    for bvh in &[&from_macro, &built, &written] {
+        assert_eq!(*bvh, &parsed);
+    }
+
+    // The end site is a field of the leaf joint, not a joint of its own.
+    assert_eq!(parsed.joints().len(), 2);
+    let (base, end) = (&parsed[JointIndex::new(0)], &parsed[JointIndex::new(1)]);
+    assert!(!base.has_end_site() && !base.is_end_joint());
+    assert!(end.has_end_site() && end.is_end_joint());
+    assert_eq!(end.end_site(), Some([0.0, 0.0, 30.0].into()));
+
+    // A leaf without an end site is still written with balanced braces.
+    let no_end_site = Builder::with_root_joint(b"Base".as_bstr(), [0.0; 3].into(), &[])
+        .push_child(1, b"Left".as_bstr(), [1.0, 0.0, 0.0].into(), &[])
+        .push_child_with_parent(1, b"Right".as_bstr(), [-1.0, 0.0, 0.0].into(), &[], 0)
+        .with_motion(0, Duration::default())
+        .build()
+        .unwrap();
+    let reparsed = bvh_anim::from_bytes(&no_end_site.to_bstring()[..]).unwrap();
+    assert_eq!(reparsed, no_end_site);
+    assert!(reparsed.joints().all(|joint| !joint.has_end_site()));
+
+    // A preserved syntax tree is only kept if its end sites match the joints.
+    let preserve = ParseOptions::new().with_preserve_syntax(true);
+    let (preserved, _) = preserve.parse_bytes(BVH_BYTES).unwrap();
+    assert!(preserved.syntax().is_some());
+    assert_eq!(preserved.to_bstring(), BVH_BYTES);
+
+    let early_end_site = BVH_BYTES.replace(
+        "    JOINT End",
+        "    End Site\n    {\n        OFFSET 0.0 0.0 1.0\n    }\n    JOINT End",
+    );
+    let (repaired, warnings) = preserve
+        .with_policy(ParsePolicy::Lenient)
+        .parse_bytes(&early_end_site[..])
+        .unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(repaired.syntax().is_none());
+    let reparsed = bvh_anim::from_bytes(&repaired.to_bstring()[..]).unwrap();
+    assert_eq!(reparsed, parsed);
+}
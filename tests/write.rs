@@ -36,6 +36,7 @@ fn test_write() {
         .with_offset_significant_figures(1)
         .with_frame_time_significant_figures(9)
         .with_motion_values_significant_figures(1)
+        .with_full_precision(false)
         .with_line_terminator(LineTerminator::native())
         .with_indent(IndentStyle::with_spaces(4))
         .write_to_string(&bvh);
@@ -52,8 +53,212 @@ fn test_load_write_is_identical() {
         .with_frame_time_significant_figures(9)
         .with_offset_significant_figures(1)
         .with_motion_values_significant_figures(1)
+        .with_full_precision(false)
         .with_line_terminator(LineTerminator::native())
         .write_to_string(&bvh);
 
     assert_eq!(bvh_string, BVH_STRING);
 }
+
+#[cfg(feature = "async")]
+#[test]
+fn test_write_async_matches_write() {
+    const BVH_STRING: &str = include_str!("../data/test_mocapbank.bvh");
+    let bvh = bvh_anim::from_str(BVH_STRING).unwrap();
+    let options = WriteOptions::new().with_indent(IndentStyle::with_spaces(4));
+
+    let mut written = vec![];
+    options.write(&bvh, &mut written).unwrap();
+
+    let mut written_async = vec![];
+    futures_executor::block_on(options.write_async(&bvh, &mut written_async)).unwrap();
+
+    assert_eq!(written_async, written);
+}
+
+#[test]
+fn test_write_preserves_unmodified_syntax() {
+    use bvh_anim::parse::ParseOptions;
+    use std::time::Duration;
+
+    const MOCAPBANK: &[u8] = include_bytes!("../data/test_mocapbank.bvh");
+    let options = ParseOptions::new().with_preserve_syntax(true);
+
+    let (bvh, _) = options.parse_bytes(MOCAPBANK).unwrap();
+    assert_eq!(bvh.to_bstring(), MOCAPBANK);
+    assert_eq!(bvh, bvh_anim::from_bytes(MOCAPBANK).unwrap());
+
+    const BVH_STRING: &[u8] = b"# Exported by hand\r
+HIERARCHY\r
+ROOT Hips\r
+{\r
+\tOFFSET 0.00 0.00 0.00\r
+\tCHANNELS 3 Xposition Yposition Zposition\r
+\tJOINT Chest\r
+\t{\r
+\t\tOFFSET 0 5.000 0\r
+\t\tCHANNELS 1 Xrotation\r
+\t\tEnd Site\r
+\t\t{\r
+\t\t\tOFFSET 0 1e1 0\r
+\t\t}\r
+\t}\r
+}\r
+MOTION\r
+Frames:    2\r
+Frame Time: 0.0333333\r
+1   2.50  3 -1 \r
+# between frames\r
+4.000 5 6    7\r
+";
+
+    let (mut bvh, _) = options.parse_bytes(BVH_STRING).unwrap_or_else(|e| panic!("{}", e));
+    assert_eq!(bvh.to_bstring(), BVH_STRING);
+
+    {
+        let mut joints = bvh.joints_mut();
+        let hips = joints.next().unwrap();
+        hips.offset.y = 1.5;
+        let chest = joints.next().unwrap();
+        chest.name = "Spine".as_bytes().into();
+    }
+    bvh.frames_mut().next().unwrap().as_mut_slice()[1] = 0.25;
+    bvh.set_frame_time(Duration::from_millis(40));
+
+    let expected = b"# Exported by hand\r
+HIERARCHY\r
+ROOT Hips\r
+{\r
+\tOFFSET 0.00 1.50000 0.00\r
+\tCHANNELS 3 Xposition Yposition Zposition\r
+\tJOINT Spine\r
+\t{\r
+\t\tOFFSET 0 5.000 0\r
+\t\tCHANNELS 1 Xrotation\r
+\t\tEnd Site\r
+\t\t{\r
+\t\t\tOFFSET 0 1e1 0\r
+\t\t}\r
+\t}\r
+}\r
+MOTION\r
+Frames:    2\r
+Frame Time: 0.0400000\r
+1   0.25  3 -1 \r
+# between frames\r
+4.000 5 6    7\r
+";
+    let written = WriteOptions::new().with_full_precision(false).write_to_string(&bvh);
+    assert_eq!(written, &expected[..]);
+
+    let formatted = WriteOptions::new().with_preserve_syntax(false).write_to_string(&bvh);
+    assert!(formatted.starts_with(b"HIERARCHY\n"));
+
+    bvh.clear_syntax();
+    assert_eq!(bvh.to_bstring(), formatted);
+}
+
+#[test]
+fn test_write_preserved_channel_major() {
+    use bvh_anim::{parse::ParseOptions, JointIndex, MotionLayout};
+
+    const BVH_STRING: &[u8] = b"HIERARCHY
+ROOT Hips
+{
+\tOFFSET 0 0 0
+\tCHANNELS 2 Xposition Yposition
+\tEnd Site
+\t{
+\t\tOFFSET 0 1 0
+\t}
+}
+MOTION
+Frames: 3
+Frame Time: 0.0333333
+1 2
+3 4
+5 6
+";
+
+    let options = ParseOptions::new().with_preserve_syntax(true);
+    let (mut bvh, _) = options.parse_bytes(BVH_STRING).unwrap();
+    bvh.set_layout(MotionLayout::ChannelMajor);
+    assert_eq!(bvh.to_bstring(), BVH_STRING);
+
+    let mut written = vec![];
+    WriteOptions::new().write(&bvh, &mut written).unwrap();
+    assert_eq!(written, BVH_STRING);
+
+    let y = bvh[JointIndex::new(0)].channels[1];
+    *bvh.curve_mut(&y).get_mut(1).unwrap() = 8.0;
+    let mut frame = bvh.frames().next().unwrap().as_slice().to_vec();
+    frame[0] = 7.0;
+    bvh.motion_mut().push_frame(&frame);
+
+    let expected = [
+        &BVH_STRING.replace("Frames: 3", "Frames: 4").replace("3 4", "3 8")[..],
+        b"7 2\n",
+    ]
+    .concat();
+    let written = WriteOptions::new().with_full_precision(true).write_to_string(&bvh);
+    assert_eq!(written, expected);
+}
+
+#[test]
+fn test_write_full_precision_round_trips() {
+    let mut bvh = bvh_anim::from_bytes(&include_bytes!("../data/test_mocapbank.bvh")[..]).unwrap();
+    {
+        let root = bvh.joints_mut().next().unwrap();
+        root.offset.x = 1234.0 + 1.0 / 3.0;
+    }
+    for (i, value) in bvh.frames_mut().flat_map(|frame| frame.as_mut_slice()).enumerate() {
+        *value += (i as bvh_anim::Float) / 7.0;
+    }
+
+    let options = WriteOptions::new()
+        .with_preserve_syntax(false)
+        .with_full_precision(true);
+    let mut written = options.write_to_string(&bvh);
+    for _ in 0..3 {
+        let reloaded = bvh_anim::from_bytes(&written[..]).unwrap();
+        assert_eq!(reloaded.root_joint().unwrap().offset, bvh.root_joint().unwrap().offset);
+        assert!(reloaded
+            .frames()
+            .zip(bvh.frames())
+            .all(|(a, b)| a.as_slice() == b.as_slice()));
+
+        let rewritten = options.write_to_string(&reloaded);
+        assert_eq!(rewritten, written);
+        written = rewritten;
+    }
+
+    // A fixed number of figures is lossy.
+    let written = WriteOptions::new()
+        .with_preserve_syntax(false)
+        .with_full_precision(false)
+        .write_to_string(&bvh);
+    let reloaded = bvh_anim::from_bytes(&written[..]).unwrap();
+    assert!(reloaded
+        .frames()
+        .zip(bvh.frames())
+        .any(|(a, b)| a.as_slice() != b.as_slice()));
+}
+
+#[test]
+fn test_write_full_precision_frame_time() {
+    use bvh_anim::FrameRate;
+
+    let mut bvh = bvh_anim::from_bytes(&include_bytes!("../data/test_simple.bvh")[..]).unwrap();
+    bvh.set_frame_rate(FrameRate::new(1024, 1).unwrap());
+
+    let options = WriteOptions::new().with_preserve_syntax(false);
+    let written = options.clone().with_full_precision(true).write_to_string(&bvh);
+    assert!(written.contains_str("Frame Time: 0.0009765625\n"));
+    let reloaded = bvh_anim::from_bytes(&written[..]).unwrap();
+    assert_eq!(reloaded.frame_rate(), bvh.frame_rate());
+
+    let written = options.with_full_precision(false).write_to_string(&bvh);
+    assert!(written.contains_str("Frame Time: 0.0009766\n"));
+
+    assert_eq!(WriteOptions::new().full_precision, cfg!(feature = "f64"));
+}